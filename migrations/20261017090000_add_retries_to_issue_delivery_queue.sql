@@ -0,0 +1,7 @@
+-- Add migration script here
+ALTER TABLE issue_delivery_queue
+    ADD COLUMN n_attempts SMALLINT NOT NULL DEFAULT 0;
+ALTER TABLE issue_delivery_queue
+    ADD COLUMN execute_after timestamptz NOT NULL DEFAULT now();
+ALTER TABLE issue_delivery_queue
+    ADD COLUMN last_error TEXT NULL;
@@ -0,0 +1,10 @@
+-- Add migration script here
+CREATE TABLE issue_delivery_dead_letters
+(
+    newsletter_issue_id uuid        NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_email    TEXT        NOT NULL,
+    n_attempts          SMALLINT    NOT NULL,
+    last_error          TEXT        NOT NULL,
+    failed_at           timestamptz NOT NULL,
+    PRIMARY KEY (newsletter_issue_id, subscriber_email)
+);
@@ -0,0 +1,8 @@
+-- Add migration script here
+ALTER TABLE newsletter_issues
+    ADD COLUMN status TEXT NOT NULL DEFAULT 'published';
+ALTER TABLE newsletter_issues
+    ADD COLUMN send_at timestamptz NULL;
+-- Scheduled issues are only published when the scheduler fans them out.
+ALTER TABLE newsletter_issues
+    ALTER COLUMN published_at DROP NOT NULL;
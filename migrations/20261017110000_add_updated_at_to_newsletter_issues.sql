@@ -0,0 +1,4 @@
+-- Add migration script here
+-- Drafts are edited in place; keep track of the last change so they can be listed by recency.
+ALTER TABLE newsletter_issues
+    ADD COLUMN updated_at timestamptz NOT NULL DEFAULT now();
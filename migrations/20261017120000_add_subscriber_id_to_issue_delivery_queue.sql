@@ -0,0 +1,12 @@
+-- Add migration script here
+-- The worker needs more than the email address to personalise an issue.
+ALTER TABLE issue_delivery_queue
+    ADD COLUMN subscriber_id uuid NULL REFERENCES subscriptions (id);
+UPDATE issue_delivery_queue
+SET subscriber_id = subscriptions.id
+FROM subscriptions
+WHERE subscriptions.email = issue_delivery_queue.subscriber_email;
+-- Tasks without a matching subscription could never be delivered anyway.
+DELETE FROM issue_delivery_queue WHERE subscriber_id IS NULL;
+ALTER TABLE issue_delivery_queue
+    ALTER COLUMN subscriber_id SET NOT NULL;
@@ -0,0 +1,4 @@
+-- Add migration script here
+-- The Markdown source an issue's bodies were rendered from, if it was written in Markdown.
+ALTER TABLE newsletter_issues
+    ADD COLUMN markdown_content TEXT NULL;
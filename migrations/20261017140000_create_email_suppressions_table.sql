@@ -0,0 +1,9 @@
+-- Add migration script here
+-- Addresses the email provider told us never to mail again.
+CREATE TABLE email_suppressions(
+    email TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    details TEXT NULL,
+    suppressed_at timestamptz NOT NULL,
+    PRIMARY KEY (email)
+);
@@ -0,0 +1,9 @@
+-- Add migration script here
+-- Existing accounts predate roles and could do everything: keep it that way.
+ALTER TABLE users
+    ADD COLUMN role TEXT NOT NULL DEFAULT 'owner'
+        CHECK (role IN ('owner', 'editor', 'viewer'));
+ALTER TABLE users
+    ALTER COLUMN role DROP DEFAULT;
+ALTER TABLE users
+    ADD COLUMN is_active BOOLEAN NOT NULL DEFAULT true;
@@ -0,0 +1,3 @@
+-- Add migration script here
+-- Rows stored before fingerprints existed have none and match any request.
+ALTER TABLE idempotency ADD COLUMN request_fingerprint TEXT NULL;
@@ -0,0 +1,13 @@
+-- Add migration script here
+-- Keys are now namespaced by who sent them: a user, an API token or, for anonymous
+-- callers, a client IP. `user_id` is kept for user-scoped records so that they still
+-- go away with their owner.
+ALTER TABLE idempotency ADD COLUMN scope TEXT NULL;
+UPDATE idempotency SET scope = 'user:' || user_id;
+ALTER TABLE idempotency ALTER COLUMN scope SET NOT NULL;
+ALTER TABLE idempotency DROP CONSTRAINT idempotency_pkey;
+ALTER TABLE idempotency ALTER COLUMN user_id DROP NOT NULL;
+ALTER TABLE idempotency ADD PRIMARY KEY (scope, idempotency_key);
+-- Flash messages sent while processing the request, JSON-encoded, sent again when its
+-- response is replayed.
+ALTER TABLE idempotency ADD COLUMN flash_messages TEXT NULL;
@@ -0,0 +1,26 @@
+-- Add migration script here
+-- No foreign key on actor_id: entries must outlive the users they mention.
+CREATE TABLE audit_log
+(
+    audit_log_id BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    actor_id     uuid        NOT NULL,
+    action       TEXT        NOT NULL,
+    target_id    uuid        NULL,
+    ip           TEXT        NULL,
+    occurred_at  timestamptz NOT NULL DEFAULT now()
+);
+CREATE INDEX audit_log_actor_id_idx ON audit_log (actor_id);
+CREATE INDEX audit_log_occurred_at_idx ON audit_log (occurred_at);
+
+CREATE FUNCTION reject_audit_log_changes() RETURNS trigger AS
+$$
+BEGIN
+    RAISE EXCEPTION 'audit_log is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_log_is_append_only
+    BEFORE UPDATE OR DELETE OR TRUNCATE
+    ON audit_log
+    FOR EACH STATEMENT
+EXECUTE FUNCTION reject_audit_log_changes();
@@ -0,0 +1,14 @@
+-- Add migration script here
+-- The first owner is now created with `zero2prod-admin create-admin`. The seeded account
+-- is only removed while it still has the hash it was seeded with, i.e. the published
+-- default password: an account whose password has been changed keeps working.
+DELETE FROM idempotency
+WHERE user_id = 'ddf8994f-d522-4659-8d02-c1d479057be6'
+  AND EXISTS (
+    SELECT 1 FROM users
+    WHERE user_id = 'ddf8994f-d522-4659-8d02-c1d479057be6'
+      AND password_hash = '$argon2id$v=19$m=15000,t=2,p=1$OEx/rcq+3ts//WUDzGNl2g$Am8UFBA4w5NJEmAtquGvBmAlu92q/VQcaoL5AyJPfc8'
+  );
+DELETE FROM users
+WHERE user_id = 'ddf8994f-d522-4659-8d02-c1d479057be6'
+  AND password_hash = '$argon2id$v=19$m=15000,t=2,p=1$OEx/rcq+3ts//WUDzGNl2g$Am8UFBA4w5NJEmAtquGvBmAlu92q/VQcaoL5AyJPfc8';
@@ -0,0 +1,26 @@
+-- Add migration script here
+-- Wakes the delivery workers up as soon as tasks are enqueued or made due, once the
+-- transaction commits. One notification per statement on INSERT: an issue fans out to
+-- every subscriber in a single INSERT. Retries push `execute_after` into the future and
+-- must not wake anybody up, so updates only notify for rows that are due right away;
+-- identical notifications within a transaction are delivered once.
+CREATE FUNCTION notify_issue_delivery_queue() RETURNS trigger AS
+$$
+BEGIN
+    PERFORM pg_notify('issue_delivery_queue', '');
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER issue_delivery_queue_notify_on_insert
+    AFTER INSERT
+    ON issue_delivery_queue
+    FOR EACH STATEMENT
+EXECUTE FUNCTION notify_issue_delivery_queue();
+
+CREATE TRIGGER issue_delivery_queue_notify_when_due
+    AFTER UPDATE OF execute_after
+    ON issue_delivery_queue
+    FOR EACH ROW
+    WHEN (NEW.execute_after <= now())
+EXECUTE FUNCTION notify_issue_delivery_queue();
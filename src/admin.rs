@@ -0,0 +1,14 @@
+use clap::Parser;
+use zero2prod_my::cli::{run, Cli};
+use zero2prod_my::configuration::get_configuration;
+use zero2prod_my::telemetry::{get_subscriber, init_subscriber};
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    // The report goes to stdout, the logs to stderr.
+    let subscriber = get_subscriber("zero2prod-admin".into(), "warn".into(), std::io::stderr);
+    init_subscriber(subscriber);
+    let configuration = get_configuration().expect("Failed to read configuration");
+    run(cli.command, configuration, &mut std::io::stdout()).await
+}
@@ -0,0 +1,212 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+/// What a user did. Stored as text, so new actions need no migration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    LogIn,
+    LogOut,
+    ChangePassword,
+    ResetPassword,
+    EnableTwoFactor,
+    DisableTwoFactor,
+    RevokeSession,
+    RevokeAllSessions,
+    CreateApiToken,
+    RevokeApiToken,
+    InviteUser,
+    DeactivateUser,
+    ActivateUser,
+    DeleteUser,
+    ClearLoginAttempts,
+    CreateAdmin,
+    ResetAdmin,
+    PublishNewsletter,
+    ScheduleNewsletter,
+    RescheduleNewsletter,
+    CancelNewsletter,
+    RequeueDelivery,
+}
+
+impl AuditAction {
+    pub const ALL: [AuditAction; 22] = [
+        AuditAction::LogIn,
+        AuditAction::LogOut,
+        AuditAction::ChangePassword,
+        AuditAction::ResetPassword,
+        AuditAction::EnableTwoFactor,
+        AuditAction::DisableTwoFactor,
+        AuditAction::RevokeSession,
+        AuditAction::RevokeAllSessions,
+        AuditAction::CreateApiToken,
+        AuditAction::RevokeApiToken,
+        AuditAction::InviteUser,
+        AuditAction::DeactivateUser,
+        AuditAction::ActivateUser,
+        AuditAction::DeleteUser,
+        AuditAction::ClearLoginAttempts,
+        AuditAction::CreateAdmin,
+        AuditAction::ResetAdmin,
+        AuditAction::PublishNewsletter,
+        AuditAction::ScheduleNewsletter,
+        AuditAction::RescheduleNewsletter,
+        AuditAction::CancelNewsletter,
+        AuditAction::RequeueDelivery,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::LogIn => "log_in",
+            AuditAction::LogOut => "log_out",
+            AuditAction::ChangePassword => "change_password",
+            AuditAction::ResetPassword => "reset_password",
+            AuditAction::EnableTwoFactor => "enable_two_factor",
+            AuditAction::DisableTwoFactor => "disable_two_factor",
+            AuditAction::RevokeSession => "revoke_session",
+            AuditAction::RevokeAllSessions => "revoke_all_sessions",
+            AuditAction::CreateApiToken => "create_api_token",
+            AuditAction::RevokeApiToken => "revoke_api_token",
+            AuditAction::InviteUser => "invite_user",
+            AuditAction::DeactivateUser => "deactivate_user",
+            AuditAction::ActivateUser => "activate_user",
+            AuditAction::DeleteUser => "delete_user",
+            AuditAction::ClearLoginAttempts => "clear_login_attempts",
+            AuditAction::CreateAdmin => "create_admin",
+            AuditAction::ResetAdmin => "reset_admin",
+            AuditAction::PublishNewsletter => "publish_newsletter",
+            AuditAction::ScheduleNewsletter => "schedule_newsletter",
+            AuditAction::RescheduleNewsletter => "reschedule_newsletter",
+            AuditAction::CancelNewsletter => "cancel_newsletter",
+            AuditAction::RequeueDelivery => "requeue_delivery",
+        }
+    }
+
+    /// Issues with a `send_at` are scheduled rather than published right away.
+    pub fn publishing(send_at: Option<DateTime<Utc>>) -> Self {
+        match send_at {
+            None => AuditAction::PublishNewsletter,
+            Some(_) => AuditAction::ScheduleNewsletter,
+        }
+    }
+}
+
+impl TryFrom<String> for AuditAction {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        AuditAction::ALL
+            .into_iter()
+            .find(|action| action.as_str() == value)
+            .with_context(|| format!("{} is not a known audit action", value))
+    }
+}
+
+impl std::fmt::Display for AuditAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Appends an entry to the audit log. Pass the transaction the action itself runs in,
+/// if any, so that the entry is only kept if the action goes through.
+#[tracing::instrument(name = "Record an audit event", skip(executor))]
+pub async fn record_audit_event(
+    executor: impl PgExecutor<'_>,
+    actor_id: Uuid,
+    action: AuditAction,
+    target_id: Option<Uuid>,
+    ip: Option<&str>,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+INSERT INTO audit_log (actor_id, action, target_id, ip)
+VALUES ($1, $2, $3, $4)
+"#,
+        actor_id,
+        action.as_str(),
+        target_id,
+        ip
+    )
+        .execute(executor)
+        .await
+        .context("Failed to record an audit event")?;
+    Ok(())
+}
+
+pub struct AuditEntry {
+    pub audit_log_id: i64,
+    pub occurred_at: DateTime<Utc>,
+    pub actor_id: Uuid,
+    /// `None` once the actor has been deleted.
+    pub actor_username: Option<String>,
+    pub action: String,
+    pub target_id: Option<Uuid>,
+    pub ip: Option<String>,
+}
+
+/// Narrows down the audit log; every criterion left empty matches everything.
+#[derive(Debug, Default)]
+pub struct AuditFilter {
+    pub actor_username: Option<String>,
+    pub action: Option<AuditAction>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+/// The matching entries, most recent first. A `limit` of `None` returns all of them.
+#[tracing::instrument(name = "List audit log entries", skip(pool))]
+pub async fn list_audit_entries(
+    pool: &PgPool,
+    filter: &AuditFilter,
+    limit: Option<i64>,
+    offset: i64,
+) -> Result<Vec<AuditEntry>, anyhow::Error> {
+    let entries = sqlx::query_as!(
+        AuditEntry,
+        r#"
+SELECT
+    a.audit_log_id,
+    a.occurred_at,
+    a.actor_id,
+    u.username AS "actor_username?",
+    a.action,
+    a.target_id,
+    a.ip
+FROM audit_log a
+LEFT JOIN users u ON u.user_id = a.actor_id
+WHERE ($1::text IS NULL OR u.username = $1)
+  AND ($2::text IS NULL OR a.action = $2)
+  AND ($3::timestamptz IS NULL OR a.occurred_at >= $3)
+  AND ($4::timestamptz IS NULL OR a.occurred_at < $4)
+ORDER BY a.audit_log_id DESC
+LIMIT $5
+OFFSET $6
+"#,
+        filter.actor_username,
+        filter.action.map(|action| action.as_str()),
+        filter.since,
+        filter.until,
+        limit,
+        offset
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to retrieve the audit log")?;
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn actions_round_trip_through_their_string_form() {
+        for action in AuditAction::ALL {
+            assert_ok_eq!(AuditAction::try_from(action.as_str().to_string()), action);
+        }
+        assert_err!(AuditAction::try_from("launch_missiles".to_string()));
+    }
+}
@@ -0,0 +1,264 @@
+use crate::authentication::{AuthError, Role};
+use actix_web::http::header::{HeaderMap, AUTHORIZATION};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use secrecy::{ExposeSecret, SecretString};
+use sha2::{Digest, Sha256};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+/// Every token starts with it, so a leaked token is easy to recognise in logs or commits.
+const API_TOKEN_PREFIX: &str = "z2p_";
+
+/// What an API token is allowed to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiScope {
+    /// `POST /newsletters`.
+    PublishNewsletters,
+}
+
+impl ApiScope {
+    pub const ALL: [ApiScope; 1] = [ApiScope::PublishNewsletters];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiScope::PublishNewsletters => "newsletters:publish",
+        }
+    }
+
+    /// A token never grants more than its owner could do from the admin area.
+    pub fn required_role(&self) -> Role {
+        match self {
+            ApiScope::PublishNewsletters => Role::Editor,
+        }
+    }
+}
+
+impl TryFrom<String> for ApiScope {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "newsletters:publish" => Ok(Self::PublishNewsletters),
+            other => anyhow::bail!("{} is not a valid API scope", other),
+        }
+    }
+}
+
+impl std::fmt::Display for ApiScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+pub struct ApiToken {
+    pub api_token_id: Uuid,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl ApiToken {
+    pub fn is_active(&self) -> bool {
+        self.revoked_at.is_none() && self.expires_at.is_none_or(|expires_at| expires_at > Utc::now())
+    }
+}
+
+/// The owner of a valid API token, with what the token lets them do.
+#[derive(Debug)]
+pub struct ApiCaller {
+    pub user_id: Uuid,
+    pub role: Role,
+    pub scopes: Vec<ApiScope>,
+}
+
+impl ApiCaller {
+    pub fn is_allowed_to(&self, scope: ApiScope) -> bool {
+        self.scopes.contains(&scope) && self.role >= scope.required_role()
+    }
+}
+
+/// Stores a new token for `user_id` and returns its id along with the token in clear text:
+/// this is the only time it is available. Tokens are long random strings, so unlike passwords a plain SHA-256 digest is
+/// enough to keep a leaked table useless, and it can be looked up on every API call.
+#[tracing::instrument(name = "Create an API token", skip(executor))]
+pub async fn create_api_token(
+    user_id: Uuid,
+    name: &str,
+    scopes: &[ApiScope],
+    expires_at: Option<DateTime<Utc>>,
+    executor: impl PgExecutor<'_>,
+) -> Result<(Uuid, String), anyhow::Error> {
+    let api_token_id = Uuid::new_v4();
+    let token = generate_api_token();
+    let scopes: Vec<String> = scopes.iter().map(|s| s.as_str().to_owned()).collect();
+    sqlx::query!(
+        r#"
+INSERT INTO api_tokens (api_token_id, user_id, name, token_hash, scopes, expires_at)
+VALUES ($1, $2, $3, $4, $5, $6)
+"#,
+        api_token_id,
+        user_id,
+        name,
+        hash_api_token(&token),
+        &scopes,
+        expires_at
+    )
+        .execute(executor)
+        .await
+        .context("Failed to store the API token")?;
+    Ok((api_token_id, token))
+}
+
+#[tracing::instrument(name = "List API tokens", skip(pool))]
+pub async fn list_api_tokens(user_id: Uuid, pool: &PgPool) -> Result<Vec<ApiToken>, anyhow::Error> {
+    let tokens = sqlx::query_as!(
+        ApiToken,
+        r#"
+SELECT api_token_id, name, scopes, created_at, expires_at, last_used_at, revoked_at
+FROM api_tokens
+WHERE user_id = $1
+ORDER BY created_at DESC
+"#,
+        user_id
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to retrieve the API tokens")?;
+    Ok(tokens)
+}
+
+/// Returns `false` if `user_id` has no such token, or it was already revoked.
+#[tracing::instrument(name = "Revoke an API token", skip(executor))]
+pub async fn revoke_api_token(
+    user_id: Uuid,
+    api_token_id: Uuid,
+    executor: impl PgExecutor<'_>,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+UPDATE api_tokens
+SET revoked_at = now()
+WHERE api_token_id = $1 AND user_id = $2 AND revoked_at IS NULL
+"#,
+        api_token_id,
+        user_id
+    )
+        .execute(executor)
+        .await
+        .context("Failed to revoke the API token")?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Looks up the token, recording that it has been used.
+/// Tokens of deactivated users stop working along with their owner.
+#[tracing::instrument(name = "Validate an API token", skip(token, pool))]
+pub async fn validate_api_token(token: SecretString, pool: &PgPool) -> Result<ApiCaller, AuthError> {
+    let row = sqlx::query!(
+        r#"
+UPDATE api_tokens
+SET last_used_at = now()
+FROM users
+WHERE api_tokens.token_hash = $1
+  AND api_tokens.revoked_at IS NULL
+  AND (api_tokens.expires_at IS NULL OR api_tokens.expires_at > now())
+  AND users.user_id = api_tokens.user_id
+  AND users.is_active
+RETURNING api_tokens.user_id, users.role, api_tokens.scopes
+"#,
+        hash_api_token(token.expose_secret())
+    )
+        .fetch_optional(pool)
+        .await
+        .context("Failed to validate the API token")?
+        .ok_or_else(|| AuthError::InvalidCredentials(anyhow::anyhow!("Unknown, expired or revoked API token.")))?;
+    Ok(ApiCaller {
+        user_id: row.user_id,
+        role: Role::try_from(row.role)?,
+        // Scopes that no longer exist are ignored rather than failing the request.
+        scopes: row
+            .scopes
+            .into_iter()
+            .filter_map(|scope| ApiScope::try_from(scope).ok())
+            .collect(),
+    })
+}
+
+/// Extracts the token from an `Authorization: Bearer <token>` header.
+pub fn bearer_token(headers: &HeaderMap) -> Result<SecretString, anyhow::Error> {
+    let header_value = headers
+        .get(AUTHORIZATION)
+        .context("The 'Authorization' header was missing")?
+        .to_str()
+        .context("The 'Authorization' header was not a valid UTF-8 string")?;
+    let token = header_value
+        .strip_prefix("Bearer ")
+        .context("The authorization scheme was not 'Bearer'")?
+        .trim();
+    if token.is_empty() {
+        anyhow::bail!("The 'Bearer' credential was empty");
+    }
+    Ok(SecretString::from(token.to_owned()))
+}
+
+fn generate_api_token() -> String {
+    let mut rng = rand::thread_rng();
+    let secret: String = std::iter::repeat_with(|| rng.sample(rand::distributions::Alphanumeric))
+        .map(char::from)
+        .take(40)
+        .collect();
+    format!("{}{}", API_TOKEN_PREFIX, secret)
+}
+
+fn hash_api_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::HeaderValue;
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn scopes_round_trip_through_their_string_form() {
+        for scope in ApiScope::ALL {
+            assert_ok_eq!(ApiScope::try_from(scope.as_str().to_string()), scope);
+        }
+    }
+
+    #[test]
+    fn a_scope_is_only_honoured_if_the_owner_has_the_role_for_it() {
+        let caller = |role| ApiCaller {
+            user_id: Uuid::new_v4(),
+            role,
+            scopes: vec![ApiScope::PublishNewsletters],
+        };
+        assert!(caller(Role::Editor).is_allowed_to(ApiScope::PublishNewsletters));
+        assert!(!caller(Role::Viewer).is_allowed_to(ApiScope::PublishNewsletters));
+    }
+
+    #[test]
+    fn bearer_tokens_are_read_from_the_authorization_header() {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer z2p_abc"));
+        assert_eq!(bearer_token(&headers).unwrap().expose_secret(), "z2p_abc");
+
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Basic dXNlcjpwYXNz"));
+        assert_err!(bearer_token(&headers));
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer "));
+        assert_err!(bearer_token(&headers));
+    }
+
+    #[test]
+    fn generated_tokens_are_prefixed_and_unique() {
+        let token = generate_api_token();
+        assert!(token.starts_with(API_TOKEN_PREFIX));
+        assert_eq!(token.len(), API_TOKEN_PREFIX.len() + 40);
+        assert_ne!(token, generate_api_token());
+    }
+}
@@ -0,0 +1,159 @@
+use crate::session_state::TypedSession;
+use crate::utils::e500;
+use actix_web::body::MessageBody;
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::header::{HeaderMap, ORIGIN, REFERER};
+use actix_web::middleware::Next;
+use actix_web::{web, FromRequest, HttpMessage, HttpRequest, HttpResponse};
+use rand::Rng;
+use sha2::{Digest, Sha256};
+
+/// The form field that carries the token in the HTML forms.
+pub const CSRF_FORM_FIELD: &str = "csrf_token";
+/// The header that carries the token for clients that do not submit forms.
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+/// The CSRF token of the current session, stored in the request extensions
+/// by `reject_forged_requests` for the handlers that render forms.
+#[derive(Clone, Debug)]
+pub struct CsrfToken(String);
+
+impl CsrfToken {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The hidden input every `method="post"` form must embed.
+    pub fn form_field(&self) -> String {
+        format!(
+            r#"<input type="hidden" name="{}" value="{}">"#,
+            CSRF_FORM_FIELD, self.0
+        )
+    }
+}
+
+/// Issues a per-session CSRF token and rejects state-changing requests that do not carry it,
+/// either in the `csrf_token` form field or in the `X-CSRF-Token` header.
+/// Requests coming from a foreign origin are rejected even before the token is looked at.
+pub async fn reject_forged_requests(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let session = {
+        let (http_request, payload) = req.parts_mut();
+        TypedSession::from_request(http_request, payload).await
+    }?;
+    let token = match session.get_csrf_token().map_err(e500)? {
+        Some(token) => token,
+        None => {
+            let token = generate_csrf_token();
+            session.insert_csrf_token(&token).map_err(e500)?;
+            token
+        }
+    };
+
+    if !req.method().is_safe() {
+        if let Err(e) = check_origin(req.request()) {
+            return Err(forbidden(e));
+        }
+        let submitted = submitted_token(&mut req).await?;
+        if !submitted.is_some_and(|submitted| tokens_match(&submitted, &token)) {
+            return Err(forbidden(anyhow::anyhow!("Missing or invalid CSRF token.")));
+        }
+    }
+
+    req.extensions_mut().insert(CsrfToken(token));
+    next.call(req).await
+}
+
+/// Browsers send `Origin` on every cross-site POST; older ones only send `Referer`.
+/// When neither is there we cannot tell, and the token check alone decides.
+fn check_origin(request: &HttpRequest) -> Result<(), anyhow::Error> {
+    let Some(source) = source_origin(request.headers()) else {
+        return Ok(());
+    };
+    let host = request.connection_info().host().to_owned();
+    match authority(&source) {
+        Some(authority) if authority.eq_ignore_ascii_case(&host) => Ok(()),
+        _ => Err(anyhow::anyhow!(
+            "The request comes from {}, which is not {}.",
+            source,
+            host
+        )),
+    }
+}
+
+fn source_origin(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(ORIGIN)
+        .or_else(|| headers.get(REFERER))
+        .map(|value| value.to_str().unwrap_or_default().to_owned())
+}
+
+/// `https://example.com:8000/path` -> `example.com:8000`.
+/// Opaque origins such as `null` have none.
+fn authority(url: &str) -> Option<&str> {
+    let (_, rest) = url.split_once("://")?;
+    rest.split(['/', '?', '#']).next().filter(|a| !a.is_empty())
+}
+
+/// Looks for the token in the header first, then in the URL-encoded body.
+/// The body is put back afterwards so that the handler can still extract its form.
+async fn submitted_token(req: &mut ServiceRequest) -> Result<Option<String>, actix_web::Error> {
+    if let Some(value) = req.headers().get(CSRF_HEADER) {
+        return Ok(value.to_str().ok().map(ToOwned::to_owned));
+    }
+    if req.content_type() != "application/x-www-form-urlencoded" {
+        return Ok(None);
+    }
+    let body = req.extract::<web::Bytes>().await?;
+    let token = serde_urlencoded::from_bytes::<Vec<(String, String)>>(&body)
+        .ok()
+        .and_then(|fields| {
+            fields
+                .into_iter()
+                .find(|(name, _)| name == CSRF_FORM_FIELD)
+                .map(|(_, value)| value)
+        });
+    req.set_payload(Payload::from(body));
+    Ok(token)
+}
+
+/// Compares fixed-size digests so the comparison does not leak how much of the token matched.
+fn tokens_match(submitted: &str, expected: &str) -> bool {
+    Sha256::digest(submitted.as_bytes()) == Sha256::digest(expected.as_bytes())
+}
+
+pub(crate) fn generate_csrf_token() -> String {
+    let mut rng = rand::thread_rng();
+    std::iter::repeat_with(|| rng.sample(rand::distributions::Alphanumeric))
+        .map(char::from)
+        .take(32)
+        .collect()
+}
+
+fn forbidden(e: anyhow::Error) -> actix_web::Error {
+    let response = HttpResponse::Forbidden().body("The request could not be verified. Reload the page and try again.");
+    InternalError::from_response(e, response).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn authority_is_the_host_and_port_of_the_url() {
+        assert_eq!(authority("https://example.com"), Some("example.com"));
+        assert_eq!(authority("http://127.0.0.1:8000/admin/users?x=1"), Some("127.0.0.1:8000"));
+        assert_eq!(authority("null"), None);
+        assert_eq!(authority("file:///etc/passwd"), None);
+    }
+
+    #[test]
+    fn tokens_must_match_exactly() {
+        assert!(tokens_match("abc", "abc"));
+        assert!(!tokens_match("abc", "abd"));
+        assert!(!tokens_match("", "abc"));
+    }
+}
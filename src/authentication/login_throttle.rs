@@ -0,0 +1,219 @@
+use crate::configuration::LoginThrottleSettings;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool};
+use std::time::Duration;
+
+/// What a failed-attempts counter is keyed on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThrottleScope {
+    Username,
+    Ip,
+}
+
+impl ThrottleScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ThrottleScope::Username => "username",
+            ThrottleScope::Ip => "ip",
+        }
+    }
+}
+
+impl TryFrom<String> for ThrottleScope {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "username" => Ok(Self::Username),
+            "ip" => Ok(Self::Ip),
+            other => Err(format!("{} is not a valid throttle scope.", other)),
+        }
+    }
+}
+
+pub struct FailedLoginCounter {
+    pub scope: String,
+    pub subject: String,
+    pub failures: i32,
+    pub last_failure_at: DateTime<Utc>,
+    pub locked_until: Option<DateTime<Utc>>,
+}
+
+/// Returns when the lockout ends if either the username or the client IP is locked out.
+/// Callers must check this *before* `validate_credentials`, so locked out guesses never
+/// reach Argon2. Counters are keyed on the submitted username whether or not such a user
+/// exists, so a lockout reveals nothing about which accounts are real.
+#[tracing::instrument(name = "Check login lockout", skip(pool))]
+pub async fn check_login_lockout(
+    username: &str,
+    client_ip: Option<&str>,
+    pool: &PgPool,
+) -> Result<Option<DateTime<Utc>>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+SELECT MAX(locked_until) AS locked_until
+FROM failed_login_attempts
+WHERE ((scope = 'username' AND subject = $1) OR (scope = 'ip' AND subject = $2))
+  AND locked_until > now()
+"#,
+        username,
+        client_ip
+    )
+        .fetch_one(pool)
+        .await
+        .context("Failed to check the login lockout")?;
+    Ok(row.locked_until)
+}
+
+/// Counts a failed attempt against the username and the client IP, locking out whichever
+/// crossed its limit, then waits a progressively longer delay before returning.
+#[tracing::instrument(name = "Record failed login", skip(settings, pool))]
+pub async fn record_failed_login(
+    username: &str,
+    client_ip: Option<&str>,
+    settings: &LoginThrottleSettings,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    let mut failures = count_failure(ThrottleScope::Username, username, settings.max_failures_per_username, settings, pool).await?;
+    if let Some(client_ip) = client_ip {
+        failures = failures.max(
+            count_failure(ThrottleScope::Ip, client_ip, settings.max_failures_per_ip, settings, pool).await?,
+        );
+    }
+    tokio::time::sleep(failure_delay(settings, failures)).await;
+    Ok(())
+}
+
+/// Forgets the failures of `username`. The IP counter is left alone: logging into one's
+/// own account must not give a free pass to guess other people's passwords.
+#[tracing::instrument(name = "Record successful login", skip(pool))]
+pub async fn record_successful_login(username: &str, pool: &PgPool) -> Result<(), anyhow::Error> {
+    clear_failed_logins(ThrottleScope::Username, username, pool).await
+}
+
+#[tracing::instrument(name = "Clear failed logins", skip(executor))]
+pub async fn clear_failed_logins(
+    scope: ThrottleScope,
+    subject: &str,
+    executor: impl PgExecutor<'_>,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"DELETE FROM failed_login_attempts WHERE scope = $1 AND subject = $2"#,
+        scope.as_str(),
+        subject
+    )
+        .execute(executor)
+        .await
+        .context("Failed to clear failed login attempts")?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Get failed login counters", skip(pool))]
+pub async fn get_failed_login_counters(pool: &PgPool) -> Result<Vec<FailedLoginCounter>, anyhow::Error> {
+    let counters = sqlx::query_as!(
+        FailedLoginCounter,
+        r#"
+SELECT scope, subject, failures, last_failure_at, locked_until
+FROM failed_login_attempts
+ORDER BY locked_until DESC NULLS LAST, last_failure_at DESC
+"#
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to retrieve failed login counters")?;
+    Ok(counters)
+}
+
+/// Returns the number of consecutive failures for the subject, this one included.
+async fn count_failure(
+    scope: ThrottleScope,
+    subject: &str,
+    max_failures: i32,
+    settings: &LoginThrottleSettings,
+    pool: &PgPool,
+) -> Result<i32, anyhow::Error> {
+    let now = Utc::now();
+    let reset_before = now - settings.reset_after();
+    let failures = sqlx::query!(
+        r#"
+INSERT INTO failed_login_attempts (scope, subject, failures, last_failure_at)
+VALUES ($1, $2, 1, $3)
+ON CONFLICT (scope, subject) DO UPDATE
+SET failures = CASE
+        WHEN failed_login_attempts.last_failure_at < $4 THEN 1
+        ELSE failed_login_attempts.failures + 1
+    END,
+    last_failure_at = $3
+RETURNING failures
+"#,
+        scope.as_str(),
+        subject,
+        now,
+        reset_before
+    )
+        .fetch_one(pool)
+        .await
+        .context("Failed to record a failed login attempt")?
+        .failures;
+    if failures >= max_failures {
+        let locked_until = now + lockout_duration(settings, failures - max_failures);
+        tracing::warn!(scope = scope.as_str(), %locked_until, "Too many failed login attempts, locking out");
+        sqlx::query!(
+            r#"UPDATE failed_login_attempts SET locked_until = $3 WHERE scope = $1 AND subject = $2"#,
+            scope.as_str(),
+            subject,
+            locked_until
+        )
+            .execute(pool)
+            .await
+            .context("Failed to lock out after failed login attempts")?;
+    }
+    Ok(failures)
+}
+
+fn failure_delay(settings: &LoginThrottleSettings, failures: i32) -> Duration {
+    let exponent = failures.saturating_sub(1).clamp(0, 31) as u32;
+    settings
+        .base_delay()
+        .saturating_mul(2u32.saturating_pow(exponent))
+        .min(settings.max_delay())
+}
+
+/// The first lockout lasts `lockout_seconds`; every failure past the limit doubles it, up to 32x.
+fn lockout_duration(settings: &LoginThrottleSettings, failures_past_limit: i32) -> Duration {
+    let exponent = failures_past_limit.clamp(0, 5) as u32;
+    settings.lockout().saturating_mul(2u32.pow(exponent))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> LoginThrottleSettings {
+        LoginThrottleSettings {
+            max_failures_per_username: 5,
+            max_failures_per_ip: 50,
+            lockout_seconds: 900,
+            reset_after_seconds: 3600,
+            base_delay_milliseconds: 250,
+            max_delay_milliseconds: 4000,
+        }
+    }
+
+    #[test]
+    fn failure_delay_grows_exponentially_up_to_the_cap() {
+        let settings = settings();
+        for (failures, expected) in [(1, 250), (2, 500), (3, 1000), (5, 4000), (30, 4000)] {
+            assert_eq!(failure_delay(&settings, failures), Duration::from_millis(expected));
+        }
+    }
+
+    #[test]
+    fn lockout_doubles_with_every_failure_past_the_limit() {
+        let settings = settings();
+        assert_eq!(lockout_duration(&settings, 0), Duration::from_secs(900));
+        assert_eq!(lockout_duration(&settings, 1), Duration::from_secs(1800));
+        assert_eq!(lockout_duration(&settings, 100), Duration::from_secs(900 * 32));
+    }
+}
@@ -1,10 +1,13 @@
+use crate::authentication::Role;
 use crate::session_state::TypedSession;
 use crate::utils::{e500, see_other};
 use actix_web::body::MessageBody;
 use actix_web::dev::{ServiceRequest, ServiceResponse};
 use actix_web::error::InternalError;
 use actix_web::middleware::Next;
-use actix_web::{FromRequest, HttpMessage};
+use actix_web::{web, FromRequest, HttpMessage, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
 use std::fmt::Debug;
 use std::ops::Deref;
 use uuid::Uuid;
@@ -18,19 +21,88 @@ pub async fn reject_anonymous_users(
         TypedSession::from_request(http_request, payload).await
     }?;
 
-    match session.get_user_id().map_err(e500)? {
+    let Some(user_id) = session.get_user_id().map_err(e500)? else {
+        let e = anyhow::anyhow!("The user has not logged in.");
+        return Err(InternalError::from_response(e, see_other("/login")).into());
+    };
+    let pool = req
+        .app_data::<web::Data<PgPool>>()
+        .context("The database connection pool is missing from the application data.")
+        .map_err(e500)?;
+    match get_active_user_role(user_id, pool).await.map_err(e500)? {
         None => {
-            let response = see_other("/login");
-            let e = anyhow::anyhow!("The user has not logged in.");
-            Err(InternalError::from_response(e, response).into())
+            // The account was deleted or deactivated after this session started.
+            session.log_out();
+            let e = anyhow::anyhow!("The user is no longer active.");
+            Err(InternalError::from_response(e, see_other("/login")).into())
         }
-        Some(user_id) => {
+        Some(role) => {
+            session.touch(req.request())?;
             req.extensions_mut().insert(UserId(user_id));
+            req.extensions_mut().insert(role);
             next.call(req).await
         }
     }
 }
 
+/// Route-level guards layered on top of `reject_anonymous_users`,
+/// which stores the user's `Role` in the request extensions.
+pub async fn require_viewer(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    require_role(Role::Viewer, req, next).await
+}
+
+pub async fn require_editor(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    require_role(Role::Editor, req, next).await
+}
+
+pub async fn require_owner(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    require_role(Role::Owner, req, next).await
+}
+
+async fn require_role(
+    required: Role,
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let role = req.extensions().get::<Role>().copied();
+    match role {
+        Some(role) if role >= required => next.call(req).await,
+        _ => {
+            let e = anyhow::anyhow!("The user does not have the {} role.", required);
+            let response = HttpResponse::Forbidden().body(format!(
+                "You need the {} role to perform this action.",
+                required
+            ));
+            Err(InternalError::from_response(e, response).into())
+        }
+    }
+}
+
+#[tracing::instrument(name = "Get the role of an active user", skip(pool))]
+async fn get_active_user_role(user_id: Uuid, pool: &PgPool) -> Result<Option<Role>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+SELECT role
+FROM users
+WHERE user_id = $1 AND is_active
+"#,
+        user_id
+    )
+        .fetch_optional(pool)
+        .await
+        .context("Failed to retrieve the user's role.")?;
+    row.map(|r| Role::try_from(r.role)).transpose()
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct UserId(pub Uuid);
 
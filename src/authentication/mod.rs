@@ -1,7 +1,33 @@
 mod password;
 pub use password::{
-    change_password, validate_credentials, AuthError, Credentials,
+    change_password, create_user, validate_credentials, AuthError, Credentials, PasswordHashing,
+};
+mod api_token;
+pub use api_token::{
+    bearer_token, create_api_token, list_api_tokens, revoke_api_token, validate_api_token, ApiCaller,
+    ApiScope, ApiToken,
+};
+mod csrf;
+pub use csrf::{reject_forged_requests, CsrfToken, CSRF_FORM_FIELD, CSRF_HEADER};
+pub(crate) use csrf::generate_csrf_token;
+mod login_throttle;
+pub use login_throttle::{
+    check_login_lockout, clear_failed_logins, get_failed_login_counters, record_failed_login,
+    record_successful_login, FailedLoginCounter, ThrottleScope,
 };
 mod middleware;
 pub use middleware::reject_anonymous_users;
+pub use middleware::{require_editor, require_owner, require_viewer};
 pub use middleware::UserId;
+mod password_reset;
+pub use password_reset::{
+    discard_password_reset_tokens, issue_password_reset_token, redeem_password_reset_token,
+    PASSWORD_RESET_TOKEN_TTL,
+};
+mod role;
+pub use role::Role;
+mod two_factor;
+pub use two_factor::{
+    disable_two_factor, enable_two_factor, generate_totp_secret, get_totp_secret, otpauth_uri,
+    remaining_recovery_codes, totp_code, verify_enrollment_code, verify_second_factor,
+};
@@ -1,9 +1,11 @@
+use crate::authentication::Role;
+use crate::configuration::PasswordHashingSettings;
 use crate::telemetry::spawn_blocking_with_tracing;
 use anyhow::Context;
 use argon2::password_hash::SaltString;
-use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version};
 use secrecy::{ExposeSecret, SecretString};
-use sqlx::PgPool;
+use sqlx::{PgExecutor, PgPool};
 use uuid::Uuid;
 
 #[derive(thiserror::Error, Debug)]
@@ -18,41 +20,125 @@ pub struct Credentials {
     pub(crate) username: String,
     pub(crate) password: SecretString,
 }
+/// The configured Argon2 parameters, along with a hash computed with them.
+/// That dummy hash is what unknown usernames are verified against, so that they take
+/// as long to reject as a wrong password for an existing user.
+#[derive(Clone)]
+pub struct PasswordHashing {
+    params: Params,
+    dummy_hash: SecretString,
+}
+
+impl PasswordHashing {
+    pub fn new(settings: &PasswordHashingSettings) -> Result<Self, anyhow::Error> {
+        let params = Params::new(settings.memory_kib, settings.iterations, settings.parallelism, None)
+            .map_err(|e| anyhow::anyhow!("Invalid Argon2 parameters: {}", e))?;
+        let dummy_password = SecretString::from(Uuid::new_v4().to_string());
+        let dummy_hash = compute_password_hash(dummy_password, &params)?;
+        Ok(Self { params, dummy_hash })
+    }
+
+    /// Whether `hash` was made with anything other than Argon2id and the current parameters.
+    fn is_outdated(&self, hash: &PasswordHash) -> bool {
+        let Ok(params) = Params::try_from(hash) else {
+            return true;
+        };
+        hash.algorithm != Algorithm::Argon2id.ident()
+            || hash.version != Some(Version::V0x13.into())
+            || params.m_cost() != self.params.m_cost()
+            || params.t_cost() != self.params.t_cost()
+            || params.p_cost() != self.params.p_cost()
+    }
+}
+
 #[tracing::instrument(
     name = "Validate credentials",
-    skip(credentials, pool)
+    skip(credentials, hashing, pool)
 )]
-pub async fn validate_credentials(credentials: Credentials, pool: &PgPool) -> Result<uuid::Uuid, AuthError> {
+pub async fn validate_credentials(
+    credentials: Credentials,
+    hashing: &PasswordHashing,
+    pool: &PgPool,
+) -> Result<uuid::Uuid, AuthError> {
     let mut user_id = None;
-    let mut expected_password_hash = SecretString::from("$argon2id$v=19$m=15000,t=2,p=1$goV5w/49LP4yuat+AN4pIQ$lY3KAqM/9V7ds+i9QwIAeOF+AAowx5ysp+ksRHtk0os");
+    let mut expected_password_hash = hashing.dummy_hash.clone();
     if let Some((stored_user_id, stored_password_hash)) = get_stored_credentials(&credentials.username, pool)
         .await?
     {
         user_id = Some(stored_user_id);
         expected_password_hash = stored_password_hash;
     }
-    spawn_blocking_with_tracing(move ||
-        verify_password_hash(expected_password_hash, credentials.password)
+    let stored_password_hash = expected_password_hash.clone();
+    let verifying_hashing = hashing.clone();
+    let upgraded_password_hash = spawn_blocking_with_tracing(move ||
+        verify_password_hash(expected_password_hash, credentials.password, &verifying_hashing)
     )
         .await
         .context("Invalid password")??;
 
-    user_id.ok_or_else(|| AuthError::InvalidCredentials(anyhow::anyhow!("Unknown username")))
+    let user_id = user_id.ok_or_else(|| AuthError::InvalidCredentials(anyhow::anyhow!("Unknown username")))?;
+    if let Some(upgraded_password_hash) = upgraded_password_hash {
+        // The login itself succeeded: failing to upgrade the hash must not undo it.
+        if let Err(e) = store_upgraded_password_hash(user_id, &stored_password_hash, &upgraded_password_hash, pool).await {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to upgrade the password hash",
+            );
+        }
+    }
+    Ok(user_id)
 }
 
+/// Checks the password and, when the stored hash uses outdated parameters,
+/// returns a fresh hash of the same password made with the current ones.
 #[tracing::instrument(
     name = "Verify password hash",
-    skip(expected_password_hash, provided_password)
+    skip(expected_password_hash, provided_password, hashing)
 )]
 fn verify_password_hash(
     expected_password_hash: SecretString,
     provided_password: SecretString,
-) -> Result<(), AuthError> {
-    let expected_password_hash = PasswordHash::new(&expected_password_hash.expose_secret())
+    hashing: &PasswordHashing,
+) -> Result<Option<SecretString>, AuthError> {
+    let expected_password_hash = PasswordHash::new(expected_password_hash.expose_secret())
         .context("Failed to parse hash in PHC string format")?;
     Argon2::default()
         .verify_password(provided_password.expose_secret().as_bytes(), &expected_password_hash)
-        .map_err(|_err| AuthError::InvalidCredentials(anyhow::anyhow!("Invalid password")))
+        .map_err(|_err| AuthError::InvalidCredentials(anyhow::anyhow!("Invalid password")))?;
+    if !hashing.is_outdated(&expected_password_hash) {
+        return Ok(None);
+    }
+    let upgraded = compute_password_hash(provided_password, &hashing.params)
+        .context("Failed to rehash the password with the current parameters")?;
+    Ok(Some(upgraded))
+}
+
+#[tracing::instrument(
+    name = "Store upgraded password hash",
+    skip(previous_password_hash, password_hash, pool)
+)]
+async fn store_upgraded_password_hash(
+    user_id: Uuid,
+    previous_password_hash: &SecretString,
+    password_hash: &SecretString,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    // Leave the row alone if the password was changed in the meantime.
+    sqlx::query!(
+        r#"
+UPDATE users
+SET password_hash = $3
+WHERE user_id = $1 AND password_hash = $2
+"#,
+        user_id,
+        previous_password_hash.expose_secret(),
+        password_hash.expose_secret()
+    )
+        .execute(pool)
+        .await
+        .context("Failed to store the upgraded password hash")?;
+    Ok(())
 }
 
 #[tracing::instrument(
@@ -67,7 +153,7 @@ async fn get_stored_credentials(
         r#"
 SELECT user_id, password_hash
 FROM users
-WHERE username = $1
+WHERE username = $1 AND is_active
 "#,
         username
     )
@@ -78,14 +164,15 @@ WHERE username = $1
     Ok(row)
 }
 fn compute_password_hash(
-    password: SecretString
+    password: SecretString,
+    params: &Params,
 ) -> Result<SecretString, anyhow::Error> {
     let salt = SaltString::generate(&mut rand::thread_rng());
 
-    let password_hash = argon2::Argon2::new(
-        argon2::Algorithm::Argon2id,
-        argon2::Version::V0x13,
-        argon2::Params::new(15000, 2, 1, None).unwrap(),
+    let password_hash = Argon2::new(
+        Algorithm::Argon2id,
+        Version::V0x13,
+        params.clone(),
     )
         .hash_password(password.expose_secret().as_bytes(), &salt)?
         .to_string();
@@ -95,15 +182,17 @@ fn compute_password_hash(
 
 #[tracing::instrument(
 name= "Change password"
-skip(password, pool)
+skip(password, hashing, executor)
 )]
 pub async fn change_password(
     user_id: Uuid,
     password: SecretString,
-    pool: &PgPool,
+    hashing: &PasswordHashing,
+    executor: impl PgExecutor<'_>,
 ) -> Result<(), anyhow::Error> {
+    let params = hashing.params.clone();
     let password_hash = spawn_blocking_with_tracing(
-        move || compute_password_hash(password)
+        move || compute_password_hash(password, &params)
     ).await?
         .context("Failed to hash password")?;
 
@@ -115,10 +204,91 @@ WHERE user_id = $2
 "#,
         password_hash.expose_secret(),
         user_id
-    ).execute(pool)
+    ).execute(executor)
         .await
         .context("Failed to change user's password in the database")?;
 
     Ok(())
 }
 
+/// Adds a new admin user. Returns `None` if the username or email address is already taken.
+#[tracing::instrument(
+    name = "Create user",
+    skip(password, hashing, executor)
+)]
+pub async fn create_user(
+    username: &str,
+    email: Option<&str>,
+    password: SecretString,
+    role: Role,
+    hashing: &PasswordHashing,
+    executor: impl PgExecutor<'_>,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let params = hashing.params.clone();
+    let password_hash = spawn_blocking_with_tracing(
+        move || compute_password_hash(password, &params)
+    ).await?
+        .context("Failed to hash password")?;
+
+    let row = sqlx::query!(
+        r#"
+INSERT INTO users (user_id, username, email, password_hash, role)
+VALUES ($1, $2, $3, $4, $5)
+ON CONFLICT DO NOTHING
+RETURNING user_id
+"#,
+        Uuid::new_v4(),
+        username,
+        email,
+        password_hash.expose_secret(),
+        role.as_str()
+    ).fetch_optional(executor)
+        .await
+        .context("Failed to store the new user in the database")?;
+
+    Ok(row.map(|r| r.user_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hashing(memory_kib: u32, iterations: u32) -> PasswordHashing {
+        PasswordHashing::new(&PasswordHashingSettings {
+            memory_kib,
+            iterations,
+            parallelism: 1,
+        })
+            .unwrap()
+    }
+
+    #[test]
+    fn the_dummy_hash_uses_the_configured_parameters() {
+        let hashing = hashing(1024, 1);
+        let dummy_hash = PasswordHash::new(hashing.dummy_hash.expose_secret()).unwrap();
+        assert!(!hashing.is_outdated(&dummy_hash));
+        assert!(hashing.dummy_hash.expose_secret().contains("m=1024,t=1,p=1"));
+    }
+
+    #[test]
+    fn hashes_with_other_parameters_are_outdated() {
+        let old = hashing(1024, 1);
+        let current = hashing(2048, 1);
+        let old_hash = PasswordHash::new(old.dummy_hash.expose_secret()).unwrap();
+        assert!(current.is_outdated(&old_hash));
+    }
+
+    #[test]
+    fn only_outdated_hashes_are_upgraded() {
+        let old = hashing(1024, 1);
+        let current = hashing(1024, 2);
+        let password = SecretString::from("correct horse battery staple");
+        let old_hash = compute_password_hash(password.clone(), &old.params).unwrap();
+
+        let upgraded = verify_password_hash(old_hash, password.clone(), &current)
+            .unwrap()
+            .unwrap();
+        assert!(upgraded.expose_secret().contains("m=1024,t=2,p=1"));
+        assert!(verify_password_hash(upgraded, password, &current).unwrap().is_none());
+    }
+}
@@ -0,0 +1,79 @@
+use anyhow::Context;
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+/// How long a password reset link stays valid after it has been emailed.
+pub const PASSWORD_RESET_TOKEN_TTL: chrono::Duration = chrono::Duration::minutes(30);
+
+/// Stores a fresh reset token for `user_id` and returns it in clear text.
+/// Only its SHA-256 digest is persisted: a leaked table does not let anyone reset a password.
+#[tracing::instrument(name = "Issue a password reset token", skip(pool))]
+pub async fn issue_password_reset_token(user_id: Uuid, pool: &PgPool) -> Result<String, anyhow::Error> {
+    let token = generate_reset_token();
+    sqlx::query!(
+        r#"
+INSERT INTO password_reset_tokens (token_hash, user_id, expires_at)
+VALUES ($1, $2, $3)
+"#,
+        hash_reset_token(&token),
+        user_id,
+        chrono::Utc::now() + PASSWORD_RESET_TOKEN_TTL
+    )
+        .execute(pool)
+        .await
+        .context("Failed to store the password reset token")?;
+    Ok(token)
+}
+
+/// Consumes the token and returns the user it was issued to, if it is known and still valid.
+/// The token is deleted either way, so a link can never be used twice.
+#[tracing::instrument(name = "Redeem a password reset token", skip(token, executor))]
+pub async fn redeem_password_reset_token(
+    token: &str,
+    executor: impl PgExecutor<'_>,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+DELETE FROM password_reset_tokens
+WHERE token_hash = $1
+RETURNING user_id, expires_at
+"#,
+        hash_reset_token(token)
+    )
+        .fetch_optional(executor)
+        .await
+        .context("Failed to redeem the password reset token")?;
+    Ok(row
+        .filter(|r| r.expires_at > chrono::Utc::now())
+        .map(|r| r.user_id))
+}
+
+/// Drops every outstanding reset token of `user_id`, e.g. once the password has been reset.
+#[tracing::instrument(name = "Discard password reset tokens", skip(executor))]
+pub async fn discard_password_reset_tokens(
+    user_id: Uuid,
+    executor: impl PgExecutor<'_>,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"DELETE FROM password_reset_tokens WHERE user_id = $1"#,
+        user_id
+    )
+        .execute(executor)
+        .await
+        .context("Failed to discard the user's password reset tokens")?;
+    Ok(())
+}
+
+fn generate_reset_token() -> String {
+    let mut rng = rand::thread_rng();
+    std::iter::repeat_with(|| rng.sample(rand::distributions::Alphanumeric))
+        .map(char::from)
+        .take(32)
+        .collect()
+}
+
+fn hash_reset_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
@@ -0,0 +1,305 @@
+//! Time-based one-time passwords (RFC 6238) as a second login factor,
+//! with one-time recovery codes for users who lose their authenticator.
+use anyhow::Context;
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+const ISSUER: &str = "zero2prod";
+const TOTP_DIGITS: u32 = 6;
+const TOTP_STEP_SECONDS: u64 = 30;
+const RECOVERY_CODE_COUNT: usize = 10;
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// A fresh 160-bit secret, base32-encoded as authenticator apps expect it.
+pub fn generate_totp_secret() -> String {
+    let key: [u8; 20] = rand::thread_rng().r#gen();
+    base32_encode(&key)
+}
+
+/// The provisioning URI to type or scan (as a QR code) into an authenticator app.
+pub fn otpauth_uri(username: &str, secret: &str) -> String {
+    format!(
+        "otpauth://totp/{issuer}:{username}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={TOTP_DIGITS}&period={TOTP_STEP_SECONDS}",
+        issuer = ISSUER,
+        username = percent_encode(username),
+    )
+}
+
+/// The code an authenticator app shows at `unix_time`. `None` if the secret is not valid base32.
+pub fn totp_code(secret: &str, unix_time: u64) -> Option<String> {
+    let key = base32_decode(secret)?;
+    let code = hotp(&key, unix_time / TOTP_STEP_SECONDS, TOTP_DIGITS);
+    Some(format!("{:0width$}", code, width = TOTP_DIGITS as usize))
+}
+
+/// Returns the time step `code` belongs to, allowing one step of clock drift either way.
+fn matching_step(secret: &str, code: &str, unix_time: u64) -> Option<u64> {
+    if code.len() != TOTP_DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let key = base32_decode(secret)?;
+    let code: u32 = code.parse().ok()?;
+    let current = unix_time / TOTP_STEP_SECONDS;
+    [current.saturating_sub(1), current, current + 1]
+        .into_iter()
+        .find(|step| hotp(&key, *step, TOTP_DIGITS) == code)
+}
+
+fn hotp(key: &[u8], counter: u64, digits: u32) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC can take a key of any size");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([hash[offset], hash[offset + 1], hash[offset + 2], hash[offset + 3]])
+        & 0x7fff_ffff;
+    binary % 10u32.pow(digits)
+}
+
+fn base32_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::new();
+    let (mut buffer, mut bits) = (0u32, 0u32);
+    for &byte in bytes {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(BASE32_ALPHABET[((buffer >> bits) & 31) as usize] as char);
+        }
+        buffer &= (1 << bits) - 1;
+    }
+    if bits > 0 {
+        encoded.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char);
+    }
+    encoded
+}
+
+fn base32_decode(encoded: &str) -> Option<Vec<u8>> {
+    let mut decoded = Vec::new();
+    let (mut buffer, mut bits) = (0u32, 0u32);
+    for c in encoded.chars().filter(|c| *c != '=') {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|a| *a as char == c.to_ascii_uppercase())? as u32;
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Some(decoded)
+}
+
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+            b => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn generate_recovery_code() -> String {
+    const CHARSET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+    let mut rng = rand::thread_rng();
+    let code: String = (0..10)
+        .map(|_| CHARSET[rng.gen_range(0..CHARSET.len())] as char)
+        .collect();
+    format!("{}-{}", &code[..5], &code[5..])
+}
+
+/// Recovery codes are compared ignoring case, dashes and whitespace.
+fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    format!("{:x}", Sha256::digest(normalized.as_bytes()))
+}
+
+fn unix_now() -> u64 {
+    chrono::Utc::now().timestamp() as u64
+}
+
+#[tracing::instrument(name = "Get TOTP secret", skip(pool))]
+pub async fn get_totp_secret(user_id: Uuid, pool: &PgPool) -> Result<Option<String>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT totp_secret FROM users WHERE user_id = $1"#,
+        user_id
+    )
+        .fetch_optional(pool)
+        .await
+        .context("Failed to retrieve the user's TOTP secret")?;
+    Ok(row.and_then(|r| r.totp_secret))
+}
+
+/// Checks `code` against the secret being enrolled, before anything is stored.
+pub fn verify_enrollment_code(secret: &str, code: &str) -> bool {
+    matching_step(secret, code.trim(), unix_now()).is_some()
+}
+
+/// Turns two-factor authentication on and returns a fresh set of recovery codes.
+/// Only their hashes are kept: the caller must show them to the user right away.
+#[tracing::instrument(name = "Enable two-factor authentication", skip(secret, transaction))]
+pub async fn enable_two_factor(
+    user_id: Uuid,
+    secret: &str,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<Vec<String>, anyhow::Error> {
+    let recovery_codes: Vec<String> = (0..RECOVERY_CODE_COUNT).map(|_| generate_recovery_code()).collect();
+    sqlx::query!(
+        r#"UPDATE users SET totp_secret = $2, totp_last_used_step = NULL WHERE user_id = $1"#,
+        user_id,
+        secret
+    )
+        .execute(&mut **transaction)
+        .await
+        .context("Failed to store the TOTP secret")?;
+    sqlx::query!(r#"DELETE FROM totp_recovery_codes WHERE user_id = $1"#, user_id)
+        .execute(&mut **transaction)
+        .await
+        .context("Failed to discard old recovery codes")?;
+    for code in &recovery_codes {
+        sqlx::query!(
+            r#"INSERT INTO totp_recovery_codes (user_id, code_hash) VALUES ($1, $2)"#,
+            user_id,
+            hash_recovery_code(code)
+        )
+            .execute(&mut **transaction)
+            .await
+            .context("Failed to store a recovery code")?;
+    }
+    Ok(recovery_codes)
+}
+
+#[tracing::instrument(name = "Disable two-factor authentication", skip(transaction))]
+pub async fn disable_two_factor(
+    user_id: Uuid,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"UPDATE users SET totp_secret = NULL, totp_last_used_step = NULL WHERE user_id = $1"#,
+        user_id
+    )
+        .execute(&mut **transaction)
+        .await
+        .context("Failed to clear the TOTP secret")?;
+    sqlx::query!(r#"DELETE FROM totp_recovery_codes WHERE user_id = $1"#, user_id)
+        .execute(&mut **transaction)
+        .await
+        .context("Failed to discard recovery codes")?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Count remaining recovery codes", skip(pool))]
+pub async fn remaining_recovery_codes(user_id: Uuid, pool: &PgPool) -> Result<i64, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT COUNT(*) AS "n!" FROM totp_recovery_codes WHERE user_id = $1"#,
+        user_id
+    )
+        .fetch_one(pool)
+        .await
+        .context("Failed to count recovery codes")?;
+    Ok(row.n)
+}
+
+/// Accepts either a current TOTP code or one of the user's unused recovery codes.
+/// Both are single-use: a TOTP code cannot be replayed within its validity window
+/// and a recovery code is deleted as soon as it is accepted.
+#[tracing::instrument(name = "Verify second factor", skip(code, pool))]
+pub async fn verify_second_factor(user_id: Uuid, code: &str, pool: &PgPool) -> Result<bool, anyhow::Error> {
+    let code = code.trim();
+    let Some(secret) = get_totp_secret(user_id, pool).await? else {
+        return Ok(false);
+    };
+    if let Some(step) = matching_step(&secret, code, unix_now()) {
+        let accepted = sqlx::query!(
+            r#"
+UPDATE users
+SET totp_last_used_step = $2
+WHERE user_id = $1 AND (totp_last_used_step IS NULL OR totp_last_used_step < $2)
+"#,
+            user_id,
+            step as i64
+        )
+            .execute(pool)
+            .await
+            .context("Failed to record the TOTP code as used")?
+            .rows_affected();
+        return Ok(accepted == 1);
+    }
+    let redeemed = sqlx::query!(
+        r#"DELETE FROM totp_recovery_codes WHERE user_id = $1 AND code_hash = $2"#,
+        user_id,
+        hash_recovery_code(code)
+    )
+        .execute(pool)
+        .await
+        .context("Failed to redeem the recovery code")?
+        .rows_affected();
+    Ok(redeemed == 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 6238, appendix B: the SHA-1 secret is the ASCII string "12345678901234567890".
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn hotp_matches_the_rfc_6238_test_vectors() {
+        for (time, expected) in [
+            (59u64, 94287082),
+            (1111111109, 7081804),
+            (1111111111, 14050471),
+            (1234567890, 89005924),
+            (2000000000, 69279037),
+        ] {
+            assert_eq!(hotp(RFC_SECRET, time / TOTP_STEP_SECONDS, 8), expected);
+        }
+    }
+
+    #[test]
+    fn base32_round_trips() {
+        assert_eq!(base32_encode(b"foobar"), "MZXW6YTBOI");
+        assert_eq!(base32_decode("MZXW6YTBOI").unwrap(), b"foobar");
+        assert_eq!(base32_decode(&base32_encode(RFC_SECRET)).unwrap(), RFC_SECRET);
+    }
+
+    #[test]
+    fn codes_from_adjacent_steps_are_accepted() {
+        let secret = base32_encode(RFC_SECRET);
+        let code = totp_code(&secret, 1111111109).unwrap();
+        assert_eq!(code, "081804");
+        assert!(matching_step(&secret, &code, 1111111109 + 30).is_some());
+        assert!(matching_step(&secret, &code, 1111111109 - 30).is_some());
+        assert!(matching_step(&secret, &code, 1111111109 + 90).is_none());
+    }
+
+    #[test]
+    fn malformed_codes_are_rejected() {
+        let secret = base32_encode(RFC_SECRET);
+        assert!(matching_step(&secret, "", 59).is_none());
+        assert!(matching_step(&secret, "28708", 59).is_none());
+        assert!(matching_step(&secret, "+87082", 59).is_none());
+    }
+
+    #[test]
+    fn recovery_codes_are_compared_loosely() {
+        assert_eq!(hash_recovery_code("abcde-fghjk"), hash_recovery_code(" ABCDEFGHJK "));
+        assert_ne!(hash_recovery_code("ABCDE-FGHJK"), hash_recovery_code("ABCDE-FGHJL"));
+    }
+
+    #[test]
+    fn the_otpauth_uri_escapes_the_username() {
+        let uri = otpauth_uri("jane doe", "MZXW6YTBOI");
+        assert!(uri.starts_with("otpauth://totp/zero2prod:jane%20doe?secret=MZXW6YTBOI&"));
+    }
+}
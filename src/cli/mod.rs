@@ -0,0 +1,168 @@
+//! `zero2prod-admin`: bootstrapping and day-to-day operations that would otherwise take raw SQL.
+mod queue;
+mod subscribers;
+mod users;
+
+use crate::configuration::Settings;
+use crate::idempotency::purge_expired_idempotency_records;
+use crate::startup::get_connection_pool;
+use anyhow::Context;
+use clap::{Args, Parser, Subcommand};
+use sqlx::PgPool;
+use std::io::Write;
+use std::time::Duration;
+use uuid::Uuid;
+
+#[derive(Parser, Debug)]
+#[command(name = "zero2prod-admin", about = "Administrative tasks for zero2prod", version)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Apply the pending database migrations.
+    Migrate,
+    /// Create an owner account.
+    CreateAdmin(CreateAdminArgs),
+    /// Set a new password for an account, reactivate it and lift its login lockout.
+    /// Also turns off its two-factor authentication and logs it out everywhere.
+    ResetAdmin(ResetAdminArgs),
+    /// Inspect the subscribers.
+    #[command(subcommand)]
+    Subscribers(SubscribersCommand),
+    /// Inspect and re-queue newsletter deliveries.
+    #[command(subcommand)]
+    Queue(QueueCommand),
+    /// Maintain the saved responses of idempotent requests.
+    #[command(subcommand)]
+    Idempotency(IdempotencyCommand),
+}
+
+#[derive(Args, Debug)]
+pub struct CreateAdminArgs {
+    #[arg(long)]
+    pub username: String,
+    /// Needed to reset a forgotten password from the login page.
+    #[arg(long)]
+    pub email: Option<String>,
+    #[command(flatten)]
+    pub password: PasswordArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct ResetAdminArgs {
+    #[arg(long)]
+    pub username: String,
+    #[command(flatten)]
+    pub password: PasswordArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct PasswordArgs {
+    /// Read from the first line of standard input when not set.
+    /// Prefer the environment variable: arguments show up in the process list.
+    #[arg(long, env = "ZERO2PROD_ADMIN_PASSWORD", hide_env_values = true)]
+    pub password: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SubscribersCommand {
+    /// The most recent subscribers.
+    List {
+        /// `pending_confirmation`, `confirmed`, `unsubscribed`, `bounced` or `complained`.
+        #[arg(long)]
+        status: Option<String>,
+        #[arg(long, default_value_t = 50)]
+        limit: i64,
+    },
+    /// Subscribers whose email address or name contains the given text.
+    Search {
+        query: String,
+        #[arg(long, default_value_t = 50)]
+        limit: i64,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum QueueCommand {
+    /// How many deliveries are pending or dead-lettered, per issue.
+    Stats,
+    /// The pending deliveries, the ones due first at the top.
+    List {
+        #[arg(long)]
+        issue: Option<Uuid>,
+        #[arg(long, default_value_t = 50)]
+        limit: i64,
+    },
+    /// The deliveries that ran out of attempts.
+    DeadLetters {
+        #[arg(long)]
+        issue: Option<Uuid>,
+        #[arg(long, default_value_t = 50)]
+        limit: i64,
+    },
+    /// Move dead letters back into the queue and make backed-off deliveries due now,
+    /// both with a fresh retry budget.
+    Requeue {
+        #[arg(long)]
+        issue: Uuid,
+        /// Only the delivery to this address.
+        #[arg(long)]
+        email: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum IdempotencyCommand {
+    /// Delete the saved responses that are past their time to live.
+    Purge {
+        /// Overrides the configured time to live.
+        #[arg(long)]
+        older_than_seconds: Option<u64>,
+    },
+}
+
+/// Runs `command` against the database of `configuration`, writing its report to `out`.
+pub async fn run(command: Command, configuration: Settings, out: &mut impl Write) -> Result<(), anyhow::Error> {
+    let pool = get_connection_pool(&configuration.database);
+    match command {
+        Command::Migrate => {
+            migrate(&pool).await?;
+            writeln!(out, "The database is up to date.")?;
+        }
+        Command::CreateAdmin(args) => users::create_admin(&pool, &configuration, args, out).await?,
+        Command::ResetAdmin(args) => users::reset_admin(&pool, &configuration, args, out).await?,
+        Command::Subscribers(command) => subscribers::run(&pool, command, out).await?,
+        Command::Queue(command) => queue::run(&pool, command, out).await?,
+        Command::Idempotency(IdempotencyCommand::Purge { older_than_seconds }) => {
+            let ttl = older_than_seconds
+                .map(Duration::from_secs)
+                .unwrap_or_else(|| configuration.idempotency.ttl());
+            let purged =
+                purge_expired_idempotency_records(&pool, ttl, configuration.idempotency.cleanup_batch_size).await?;
+            writeln!(out, "Purged {} idempotency records.", purged)?;
+        }
+    }
+    Ok(())
+}
+
+#[tracing::instrument(name = "Run database migrations", skip(pool))]
+async fn migrate(pool: &PgPool) -> Result<(), anyhow::Error> {
+    sqlx::migrate!("./migrations")
+        .run(pool)
+        .await
+        .context("Failed to migrate the database")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_command_line_is_well_formed() {
+        use clap::CommandFactory;
+        Cli::command().debug_assert();
+    }
+}
@@ -0,0 +1,170 @@
+use super::QueueCommand;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::io::Write;
+use uuid::Uuid;
+
+pub(super) async fn run(pool: &PgPool, command: QueueCommand, out: &mut impl Write) -> Result<(), anyhow::Error> {
+    match command {
+        QueueCommand::Stats => stats(pool, out).await,
+        QueueCommand::List { issue, limit } => list(pool, issue, limit, out).await,
+        QueueCommand::DeadLetters { issue, limit } => dead_letters(pool, issue, limit, out).await,
+        QueueCommand::Requeue { issue, email } => requeue(pool, issue, email.as_deref(), out).await,
+    }
+}
+
+async fn stats(pool: &PgPool, out: &mut impl Write) -> Result<(), anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+SELECT
+    i.newsletter_issue_id,
+    i.title,
+    (SELECT count(*) FROM issue_delivery_queue q
+     WHERE q.newsletter_issue_id = i.newsletter_issue_id) AS "pending!",
+    (SELECT count(*) FROM issue_delivery_queue q
+     WHERE q.newsletter_issue_id = i.newsletter_issue_id AND q.n_attempts > 0) AS "retrying!",
+    (SELECT count(*) FROM issue_delivery_dead_letters d
+     WHERE d.newsletter_issue_id = i.newsletter_issue_id) AS "dead_lettered!"
+FROM newsletter_issues i
+WHERE EXISTS (SELECT 1 FROM issue_delivery_queue q WHERE q.newsletter_issue_id = i.newsletter_issue_id)
+   OR EXISTS (SELECT 1 FROM issue_delivery_dead_letters d WHERE d.newsletter_issue_id = i.newsletter_issue_id)
+ORDER BY i.published_at DESC
+"#
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to count the deliveries")?;
+    writeln!(out, "newsletter_issue_id\ttitle\tpending\tretrying\tdead_lettered")?;
+    for r in rows {
+        writeln!(out, "{}\t{}\t{}\t{}\t{}", r.newsletter_issue_id, r.title, r.pending, r.retrying, r.dead_lettered)?;
+    }
+    Ok(())
+}
+
+struct QueuedDelivery {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+    n_attempts: i16,
+    execute_after: DateTime<Utc>,
+    last_error: Option<String>,
+}
+
+async fn list(pool: &PgPool, issue: Option<Uuid>, limit: i64, out: &mut impl Write) -> Result<(), anyhow::Error> {
+    let deliveries = sqlx::query_as!(
+        QueuedDelivery,
+        r#"
+SELECT newsletter_issue_id, subscriber_email, n_attempts, execute_after, last_error
+FROM issue_delivery_queue
+WHERE $1::uuid IS NULL OR newsletter_issue_id = $1
+ORDER BY execute_after
+LIMIT $2
+"#,
+        issue,
+        limit
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to retrieve the queued deliveries")?;
+    writeln!(out, "newsletter_issue_id\tsubscriber_email\tn_attempts\texecute_after\tlast_error")?;
+    for d in deliveries {
+        writeln!(
+            out,
+            "{}\t{}\t{}\t{}\t{}",
+            d.newsletter_issue_id,
+            d.subscriber_email,
+            d.n_attempts,
+            d.execute_after.to_rfc3339(),
+            d.last_error.as_deref().unwrap_or("-")
+        )?;
+    }
+    Ok(())
+}
+
+struct DeadLetter {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+    n_attempts: i16,
+    failed_at: DateTime<Utc>,
+    last_error: String,
+}
+
+async fn dead_letters(pool: &PgPool, issue: Option<Uuid>, limit: i64, out: &mut impl Write) -> Result<(), anyhow::Error> {
+    let dead_letters = sqlx::query_as!(
+        DeadLetter,
+        r#"
+SELECT newsletter_issue_id, subscriber_email, n_attempts, failed_at, last_error
+FROM issue_delivery_dead_letters
+WHERE $1::uuid IS NULL OR newsletter_issue_id = $1
+ORDER BY failed_at DESC
+LIMIT $2
+"#,
+        issue,
+        limit
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to retrieve the dead letters")?;
+    writeln!(out, "newsletter_issue_id\tsubscriber_email\tn_attempts\tfailed_at\tlast_error")?;
+    for d in dead_letters {
+        writeln!(
+            out,
+            "{}\t{}\t{}\t{}\t{}",
+            d.newsletter_issue_id,
+            d.subscriber_email,
+            d.n_attempts,
+            d.failed_at.to_rfc3339(),
+            d.last_error
+        )?;
+    }
+    Ok(())
+}
+
+#[tracing::instrument(name = "Re-queue deliveries", skip(pool, out))]
+async fn requeue(pool: &PgPool, issue: Uuid, email: Option<&str>, out: &mut impl Write) -> Result<(), anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    // Dead letters of people who have since left have nowhere to go and stay where they are.
+    let revived = sqlx::query!(
+        r#"
+WITH revived AS (
+    DELETE FROM issue_delivery_dead_letters d
+    USING subscriptions s
+    WHERE d.newsletter_issue_id = $1
+      AND ($2::text IS NULL OR d.subscriber_email = $2)
+      AND s.email = d.subscriber_email
+    RETURNING d.newsletter_issue_id, d.subscriber_email, s.id
+)
+INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email, subscriber_id)
+SELECT newsletter_issue_id, subscriber_email, id FROM revived
+ON CONFLICT DO NOTHING
+"#,
+        issue,
+        email
+    )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to re-enqueue the dead letters")?
+        .rows_affected();
+    let rescheduled = sqlx::query!(
+        r#"
+UPDATE issue_delivery_queue
+SET n_attempts = 0, execute_after = now()
+WHERE newsletter_issue_id = $1
+  AND ($2::text IS NULL OR subscriber_email = $2)
+  AND (n_attempts > 0 OR execute_after > now())
+"#,
+        issue,
+        email
+    )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to reset the retry schedule")?
+        .rows_affected();
+    transaction.commit().await?;
+    writeln!(
+        out,
+        "Re-queued {} dead letters and made {} backed-off deliveries due now.",
+        revived, rescheduled
+    )?;
+    Ok(())
+}
@@ -0,0 +1,63 @@
+use super::SubscribersCommand;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::io::Write;
+use uuid::Uuid;
+
+struct Subscriber {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+pub(super) async fn run(pool: &PgPool, command: SubscribersCommand, out: &mut impl Write) -> Result<(), anyhow::Error> {
+    let subscribers = match command {
+        SubscribersCommand::List { status, limit } => {
+            sqlx::query_as!(
+                Subscriber,
+                r#"
+SELECT id, email, name, status, subscribed_at
+FROM subscriptions
+WHERE $1::text IS NULL OR status = $1
+ORDER BY subscribed_at DESC
+LIMIT $2
+"#,
+                status,
+                limit
+            )
+                .fetch_all(pool)
+                .await
+        }
+        SubscribersCommand::Search { query, limit } => {
+            let pattern = format!("%{}%", escape_like(&query));
+            sqlx::query_as!(
+                Subscriber,
+                r#"
+SELECT id, email, name, status, subscribed_at
+FROM subscriptions
+WHERE email ILIKE $1 OR name ILIKE $1
+ORDER BY subscribed_at DESC
+LIMIT $2
+"#,
+                pattern,
+                limit
+            )
+                .fetch_all(pool)
+                .await
+        }
+    }
+        .context("Failed to retrieve the subscribers")?;
+    writeln!(out, "id\temail\tname\tstatus\tsubscribed_at")?;
+    for s in &subscribers {
+        writeln!(out, "{}\t{}\t{}\t{}\t{}", s.id, s.email, s.name, s.status, s.subscribed_at.to_rfc3339())?;
+    }
+    Ok(())
+}
+
+/// `%` and `_` in the query are matched literally.
+fn escape_like(query: &str) -> String {
+    query.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
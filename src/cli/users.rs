@@ -0,0 +1,103 @@
+use super::{CreateAdminArgs, PasswordArgs, ResetAdminArgs};
+use crate::audit::{record_audit_event, AuditAction};
+use crate::authentication::{
+    change_password, clear_failed_logins, create_user, disable_two_factor, PasswordHashing, Role,
+    ThrottleScope,
+};
+use crate::configuration::Settings;
+use crate::domain::SubscriberEmail;
+use crate::session_store::IndexedSessionStore;
+use anyhow::Context;
+use secrecy::{ExposeSecret, SecretString};
+use sqlx::PgPool;
+use std::io::Write;
+
+/// Audited as the new owner acting on itself, since nobody is logged in on the command line.
+pub(super) async fn create_admin(
+    pool: &PgPool,
+    configuration: &Settings,
+    args: CreateAdminArgs,
+    out: &mut impl Write,
+) -> Result<(), anyhow::Error> {
+    let username = args.username.trim();
+    if username.is_empty() {
+        anyhow::bail!("The username cannot be empty.");
+    }
+    let email = args
+        .email
+        .map(|email| SubscriberEmail::parse(email.trim().to_owned()).map_err(anyhow::Error::msg))
+        .transpose()?;
+    let password = read_password(args.password)?;
+    let hashing = PasswordHashing::new(&configuration.password_hashing)?;
+    let mut transaction = pool.begin().await.context("Failed to start a transaction")?;
+    let user_id = create_user(
+        username,
+        email.as_ref().map(AsRef::as_ref),
+        password,
+        Role::Owner,
+        &hashing,
+        &mut *transaction,
+    )
+        .await?
+        .with_context(|| format!("The username {} or the email address is already taken.", username))?;
+    record_audit_event(&mut *transaction, user_id, AuditAction::CreateAdmin, Some(user_id), None).await?;
+    transaction.commit().await.context("Failed to commit the new owner")?;
+    writeln!(out, "Created the owner {} ({}).", username, user_id)?;
+    Ok(())
+}
+
+/// For an owner who lost their password or their authenticator: whoever had access to the
+/// account is logged out, and the owner logs in with the new password alone.
+/// Audited as the account acting on itself, like `create_admin`.
+pub(super) async fn reset_admin(
+    pool: &PgPool,
+    configuration: &Settings,
+    args: ResetAdminArgs,
+    out: &mut impl Write,
+) -> Result<(), anyhow::Error> {
+    let username = args.username.trim();
+    let password = read_password(args.password)?;
+    let hashing = PasswordHashing::new(&configuration.password_hashing)?;
+    let mut transaction = pool.begin().await.context("Failed to start a transaction")?;
+    let user_id = sqlx::query!(
+        r#"
+UPDATE users
+SET is_active = true
+WHERE username = $1
+RETURNING user_id
+"#,
+        username
+    )
+        .fetch_optional(&mut *transaction)
+        .await
+        .context("Failed to reactivate the user")?
+        .with_context(|| format!("There is no user called {}.", username))?
+        .user_id;
+    change_password(user_id, password, &hashing, &mut *transaction).await?;
+    disable_two_factor(user_id, &mut transaction).await?;
+    clear_failed_logins(ThrottleScope::Username, username, &mut *transaction).await?;
+    record_audit_event(&mut *transaction, user_id, AuditAction::ResetAdmin, Some(user_id), None).await?;
+    transaction.commit().await.context("Failed to commit the reset")?;
+    let session_store = IndexedSessionStore::new(configuration.redis_uri.expose_secret()).await?;
+    session_store.revoke_user_sessions(user_id).await?;
+    writeln!(out, "Reset the password of {} ({}).", username, user_id)?;
+    Ok(())
+}
+
+fn read_password(args: PasswordArgs) -> Result<SecretString, anyhow::Error> {
+    let password = match args.password {
+        Some(password) => password,
+        None => {
+            let mut password = String::new();
+            std::io::stdin()
+                .read_line(&mut password)
+                .context("Failed to read the password from standard input")?;
+            password.trim_end_matches(['\r', '\n']).to_owned()
+        }
+    };
+    let password = SecretString::from(password);
+    if password.expose_secret().is_empty() {
+        anyhow::bail!("The password cannot be empty.");
+    }
+    Ok(password)
+}
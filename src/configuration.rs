@@ -1,10 +1,13 @@
 use crate::domain::SubscriberEmail;
-use crate::email_client::EmailClient;
+use crate::email_client::{EmailTransport, FileEmailClient, PostmarkEmailClient, SmtpEmailClient};
 use config::ConfigError;
 use secrecy::{ExposeSecret, SecretString};
 use serde::Deserialize;
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
 use sqlx::ConnectOptions;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::Duration;
 
 #[derive(Deserialize, Clone)]
@@ -12,27 +15,169 @@ pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSetting,
-    pub redis_uri: SecretString
+    pub redis_uri: SecretString,
+    pub worker: WorkerSettings,
+    pub login_throttle: LoginThrottleSettings,
+    pub password_hashing: PasswordHashingSettings,
+    pub idempotency: IdempotencySettings,
+}
+
+/// Argon2id cost parameters for new password hashes. Raising them upgrades existing
+/// hashes the next time their owner logs in.
+#[derive(Deserialize, Clone, Debug)]
+pub struct PasswordHashingSettings {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct WorkerSettings {
+    pub max_delivery_attempts: i16,
+    pub retry_base_delay_milliseconds: u64,
+    pub retry_max_delay_seconds: u64,
+    pub scheduler_poll_interval_seconds: u64,
+    /// The worker is woken up when tasks are enqueued, and also polls the queue this often
+    /// in case a notification was missed.
+    pub queue_poll_interval_seconds: u64,
+    /// How many emails are delivered at the same time.
+    pub concurrency: usize,
+}
+impl WorkerSettings {
+    pub fn retry_base_delay(&self) -> Duration {
+        Duration::from_millis(self.retry_base_delay_milliseconds)
+    }
+    pub fn retry_max_delay(&self) -> Duration {
+        Duration::from_secs(self.retry_max_delay_seconds)
+    }
+    pub fn scheduler_poll_interval(&self) -> Duration {
+        Duration::from_secs(self.scheduler_poll_interval_seconds)
+    }
+    pub fn queue_poll_interval(&self) -> Duration {
+        Duration::from_secs(self.queue_poll_interval_seconds)
+    }
+}
+
+/// How long saved responses are replayed, and how the expired ones are cleaned up.
+#[derive(Deserialize, Clone, Debug)]
+pub struct IdempotencySettings {
+    /// A key older than this is treated as never seen before.
+    pub ttl_seconds: u64,
+    pub cleanup_interval_seconds: u64,
+    /// Expired rows are deleted this many at a time, to keep each delete short.
+    pub cleanup_batch_size: i64,
+    /// How long a duplicate waits for the original request to finish before getting a 409.
+    /// API clients can ask for less with `Prefer: wait=<seconds>`.
+    pub in_flight_wait_milliseconds: u64,
+}
+impl IdempotencySettings {
+    pub fn ttl(&self) -> Duration {
+        Duration::from_secs(self.ttl_seconds)
+    }
+    pub fn cleanup_interval(&self) -> Duration {
+        Duration::from_secs(self.cleanup_interval_seconds)
+    }
+    pub fn in_flight_wait(&self) -> Duration {
+        Duration::from_millis(self.in_flight_wait_milliseconds)
+    }
+}
+
+/// Limits on failed password checks, counted per username and per client IP.
+#[derive(Deserialize, Clone, Debug)]
+pub struct LoginThrottleSettings {
+    pub max_failures_per_username: i32,
+    pub max_failures_per_ip: i32,
+    /// How long the first lockout lasts; each further failure doubles it.
+    pub lockout_seconds: u64,
+    /// Counters start over once this long has passed without a failure.
+    pub reset_after_seconds: u64,
+    pub base_delay_milliseconds: u64,
+    pub max_delay_milliseconds: u64,
+}
+impl LoginThrottleSettings {
+    pub fn lockout(&self) -> Duration {
+        Duration::from_secs(self.lockout_seconds)
+    }
+    pub fn reset_after(&self) -> Duration {
+        Duration::from_secs(self.reset_after_seconds)
+    }
+    pub fn base_delay(&self) -> Duration {
+        Duration::from_millis(self.base_delay_milliseconds)
+    }
+    pub fn max_delay(&self) -> Duration {
+        Duration::from_millis(self.max_delay_milliseconds)
+    }
 }
 
 #[derive(Deserialize, Clone)]
 pub struct EmailClientSetting {
+    #[serde(default)]
+    pub transport: EmailTransportKind,
     pub base_url: String,
     pub sender_email: String,
     pub authorization_token: SecretString,
     pub timeout_milliseconds: u64,
+    /// Shared with the provider, which sends it as the Basic auth password on bounce webhooks.
+    pub webhook_secret: SecretString,
+    pub smtp: Option<SmtpSettings>,
+    pub file_directory: Option<PathBuf>,
 }
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailTransportKind {
+    #[default]
+    Postmark,
+    Smtp,
+    File,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct SmtpSettings {
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<SecretString>,
+    pub require_tls: bool,
+}
+
 impl EmailClientSetting {
-    pub fn client(self) -> EmailClient {
+    pub fn client(self) -> Arc<dyn EmailTransport> {
         let sender_email = self.sender().expect("Invalid sender email address.");
 
         let timeout = self.timeout();
-        EmailClient::new(
-            self.base_url,
-            sender_email,
-            self.authorization_token,
-            timeout,
-        )
+        match self.transport {
+            EmailTransportKind::Postmark => Arc::new(PostmarkEmailClient::new(
+                self.base_url,
+                sender_email,
+                self.authorization_token,
+                timeout,
+            )),
+            EmailTransportKind::Smtp => {
+                let smtp = self.smtp.expect("Missing `email_client.smtp` settings.");
+                let credentials = smtp.username.zip(smtp.password);
+                Arc::new(
+                    SmtpEmailClient::new(
+                        &smtp.host,
+                        smtp.port,
+                        credentials,
+                        smtp.require_tls,
+                        sender_email,
+                        timeout,
+                    )
+                        .expect("Failed to build the SMTP email client."),
+                )
+            }
+            EmailTransportKind::File => {
+                let directory = self
+                    .file_directory
+                    .expect("Missing `email_client.file_directory` setting.");
+                Arc::new(
+                    FileEmailClient::new(directory, sender_email)
+                        .expect("Failed to create the email output directory."),
+                )
+            }
+        }
     }
     
     pub fn sender(&self) -> Result<SubscriberEmail, String> {
@@ -48,7 +193,19 @@ pub struct ApplicationSettings {
     pub port: u16,
     pub host: String,
     pub base_url: String,
-    pub hmac_secret: SecretString
+    pub hmac_secret: SecretString,
+    /// How long requests in flight and background tasks get to finish once the process is
+    /// asked to stop. Whatever is still running after that is aborted.
+    pub shutdown_timeout_seconds: u64,
+    /// The reverse proxies in front of the application. The `X-Forwarded-For` header is
+    /// only believed on connections from one of them: anybody else can put any address in it.
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
+}
+impl ApplicationSettings {
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_secs(self.shutdown_timeout_seconds)
+    }
 }
 
 pub fn get_configuration() -> Result<Settings, ConfigError> {
@@ -94,7 +251,7 @@ impl DatabaseSettings {
         PgConnectOptions::new()
             .host(&self.host)
             .username(&self.username)
-            .password(&self.password.expose_secret())
+            .password(self.password.expose_secret())
             .port(self.port)
             .ssl_mode(ssl_mode)
     }
@@ -0,0 +1,134 @@
+use crate::utils::html_escape;
+
+/// Issue content with `{{ name }}`, `{{ email }}` and `{{ unsubscribe_url }}` merge tags,
+/// filled in for each subscriber at send time.
+#[derive(Debug)]
+pub struct IssueTemplate(Vec<Segment>);
+
+#[derive(Debug, PartialEq)]
+enum Segment {
+    Text(String),
+    Tag(MergeTag),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MergeTag {
+    Name,
+    Email,
+    UnsubscribeUrl,
+}
+
+/// The per-subscriber values substituted into an `IssueTemplate`.
+pub struct MergeValues<'a> {
+    pub name: &'a str,
+    pub email: &'a str,
+    pub unsubscribe_url: &'a str,
+}
+
+impl IssueTemplate {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut segments = Vec::new();
+        let mut rest = s;
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                segments.push(Segment::Text(rest[..start].to_owned()));
+            }
+            let after_open = &rest[start + 2..];
+            let Some(end) = after_open.find("}}") else {
+                return Err("A merge tag was opened with {{ but never closed with }}.".into());
+            };
+            let tag = match after_open[..end].trim() {
+                "name" => MergeTag::Name,
+                "email" => MergeTag::Email,
+                "unsubscribe_url" => MergeTag::UnsubscribeUrl,
+                other => return Err(format!("{{{{ {} }}}} is not a known merge tag.", other)),
+            };
+            segments.push(Segment::Tag(tag));
+            rest = &after_open[end + 2..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Text(rest.to_owned()));
+        }
+        Ok(Self(segments))
+    }
+
+    /// Substitutes the values verbatim - for the subject line and the plain-text body.
+    pub fn render_text(&self, values: &MergeValues) -> String {
+        self.render(values, |s| s.to_owned())
+    }
+
+    /// Substitutes HTML-escaped values; the surrounding markup is left untouched.
+    pub fn render_html(&self, values: &MergeValues) -> String {
+        self.render(values, html_escape)
+    }
+
+    fn render(&self, values: &MergeValues, escape: impl Fn(&str) -> String) -> String {
+        let mut rendered = String::new();
+        for segment in &self.0 {
+            match segment {
+                Segment::Text(text) => rendered.push_str(text),
+                Segment::Tag(MergeTag::Name) => rendered.push_str(&escape(values.name)),
+                Segment::Tag(MergeTag::Email) => rendered.push_str(&escape(values.email)),
+                Segment::Tag(MergeTag::UnsubscribeUrl) => {
+                    rendered.push_str(&escape(values.unsubscribe_url))
+                }
+            }
+        }
+        rendered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::{IssueTemplate, MergeValues};
+    use claims::assert_err;
+
+    fn values() -> MergeValues<'static> {
+        MergeValues {
+            name: "Ursula <Le Guin>",
+            email: "ursula@example.com",
+            unsubscribe_url: "https://example.com/unsubscribe?token=a&b",
+        }
+    }
+
+    #[test]
+    fn content_without_tags_is_left_as_is() {
+        let template = IssueTemplate::parse("<style>a{b{}}</style>Hello!").unwrap();
+        assert_eq!(template.render_html(&values()), "<style>a{b{}}</style>Hello!");
+    }
+
+    #[test]
+    fn tags_are_replaced_with_and_without_spaces() {
+        let template = IssueTemplate::parse("Hi {{name}}, this is {{ email }}.").unwrap();
+        assert_eq!(
+            template.render_text(&values()),
+            "Hi Ursula <Le Guin>, this is ursula@example.com."
+        );
+    }
+
+    #[test]
+    fn values_are_escaped_in_html() {
+        let template =
+            IssueTemplate::parse(r#"<p>Hi {{ name }}</p><a href="{{ unsubscribe_url }}">x</a>"#)
+                .unwrap();
+        assert_eq!(
+            template.render_html(&values()),
+            r#"<p>Hi Ursula &lt;Le Guin&gt;</p><a href="https://example.com/unsubscribe?token=a&amp;b">x</a>"#
+        );
+    }
+
+    #[test]
+    fn unknown_tags_are_rejected() {
+        assert_err!(IssueTemplate::parse("Hi {{ surname }}"));
+    }
+
+    #[test]
+    fn empty_tags_are_rejected() {
+        assert_err!(IssueTemplate::parse("Hi {{ }}"));
+    }
+
+    #[test]
+    fn unclosed_tags_are_rejected() {
+        assert_err!(IssueTemplate::parse("Hi {{ name"));
+    }
+}
@@ -0,0 +1,84 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, SecretString};
+use sha2::Sha256;
+use uuid::Uuid;
+
+/// A per-subscriber token of the form `<subscriber_id>.<signature>`, where the signature is an
+/// HMAC-SHA256 of the subscriber id. It never expires: the link in an old issue must keep working.
+#[derive(Debug)]
+pub struct UnsubscribeToken(String);
+
+impl AsRef<str> for UnsubscribeToken {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl UnsubscribeToken {
+    pub fn generate(subscriber_id: Uuid, hmac_secret: &SecretString) -> Self {
+        let signature = mac(subscriber_id, hmac_secret).finalize().into_bytes();
+        Self(format!("{}.{}", subscriber_id, URL_SAFE_NO_PAD.encode(signature)))
+    }
+
+    /// Returns the subscriber id the token was issued for, if the signature checks out.
+    pub fn verify(token: &str, hmac_secret: &SecretString) -> Result<Uuid, anyhow::Error> {
+        let (subscriber_id, signature) = token
+            .split_once('.')
+            .ok_or_else(|| anyhow::anyhow!("The unsubscribe token is malformed"))?;
+        let subscriber_id = Uuid::parse_str(subscriber_id)?;
+        let signature = URL_SAFE_NO_PAD.decode(signature)?;
+        mac(subscriber_id, hmac_secret)
+            .verify_slice(&signature)
+            .map_err(|_| anyhow::anyhow!("The unsubscribe token signature is invalid"))?;
+        Ok(subscriber_id)
+    }
+}
+
+fn mac(subscriber_id: Uuid, hmac_secret: &SecretString) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(hmac_secret.expose_secret().as_bytes()).unwrap();
+    mac.update(b"unsubscribe:");
+    mac.update(subscriber_id.as_bytes());
+    mac
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UnsubscribeToken;
+    use claims::{assert_err, assert_ok_eq};
+    use secrecy::SecretString;
+    use uuid::Uuid;
+
+    fn secret() -> SecretString {
+        SecretString::from("a-secret-key".to_string())
+    }
+
+    #[test]
+    fn a_generated_token_is_verified() {
+        let subscriber_id = Uuid::new_v4();
+        let token = UnsubscribeToken::generate(subscriber_id, &secret());
+        assert_ok_eq!(UnsubscribeToken::verify(token.as_ref(), &secret()), subscriber_id);
+    }
+
+    #[test]
+    fn a_token_signed_with_another_secret_is_rejected() {
+        let token = UnsubscribeToken::generate(Uuid::new_v4(), &SecretString::from("another-key".to_string()));
+        assert_err!(UnsubscribeToken::verify(token.as_ref(), &secret()));
+    }
+
+    #[test]
+    fn a_token_for_another_subscriber_is_rejected() {
+        let token = UnsubscribeToken::generate(Uuid::new_v4(), &secret());
+        let (_, signature) = token.as_ref().split_once('.').unwrap();
+        let forged = format!("{}.{}", Uuid::new_v4(), signature);
+        assert_err!(UnsubscribeToken::verify(&forged, &secret()));
+    }
+
+    #[test]
+    fn malformed_tokens_are_rejected() {
+        for token in ["", "not-a-token", "also.not.a-token"] {
+            assert_err!(UnsubscribeToken::verify(token, &secret()));
+        }
+    }
+}
@@ -0,0 +1,68 @@
+mod file;
+mod postmark;
+mod smtp;
+
+pub use file::FileEmailClient;
+pub use postmark::PostmarkEmailClient;
+pub use smtp::SmtpEmailClient;
+
+use crate::domain::SubscriberEmail;
+use anyhow::Context;
+use lettre::message::header::{HeaderName, HeaderValue};
+use lettre::message::{Mailbox, MultiPart};
+use lettre::Message;
+
+/// The outbound side of email: routes and the delivery worker only ever see this trait,
+/// `EmailClientSetting::client` decides which backend sits behind it.
+#[async_trait::async_trait]
+pub trait EmailTransport: Send + Sync {
+    async fn send_email_with_headers(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<(), anyhow::Error>;
+
+    async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<(), anyhow::Error> {
+        self.send_email_with_headers(recipient, subject, html_content, text_content, &[])
+            .await
+    }
+}
+
+/// Builds the MIME message shared by the SMTP and file backends.
+fn build_message(
+    sender: &SubscriberEmail,
+    recipient: &SubscriberEmail,
+    subject: &str,
+    html_content: &str,
+    text_content: &str,
+    headers: &[(&str, &str)],
+) -> Result<Message, anyhow::Error> {
+    let from: Mailbox = sender.as_ref().parse().context("Invalid sender address")?;
+    let to: Mailbox = recipient.as_ref().parse().context("Invalid recipient address")?;
+    let mut message = Message::builder()
+        .from(from)
+        .to(to)
+        .subject(subject)
+        .multipart(MultiPart::alternative_plain_html(
+            text_content.to_owned(),
+            html_content.to_owned(),
+        ))
+        .context("Failed to build the email message")?;
+    for (name, value) in headers {
+        let name = HeaderName::new_from_ascii(name.to_string())
+            .with_context(|| format!("Invalid header name: {}", name))?;
+        message
+            .headers_mut()
+            .insert_raw(HeaderValue::new(name, value.to_string()));
+    }
+    Ok(message)
+}
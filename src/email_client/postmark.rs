@@ -1,18 +1,19 @@
 use crate::domain::SubscriberEmail;
+use crate::email_client::EmailTransport;
 use reqwest::{Client, Url};
 use secrecy::{ExposeSecret, SecretString};
 use serde::Serialize;
 use std::time::Duration;
 
 
-pub struct EmailClient {
+pub struct PostmarkEmailClient {
     http_client: Client,
     base_url: Url,
     sender: SubscriberEmail,
     authorization_token: SecretString,
 }
 
-impl EmailClient {
+impl PostmarkEmailClient {
     pub fn new(base_url: String, sender: SubscriberEmail, authorization_token: SecretString, timeout: Duration) -> Self {
         let base_url = Url::parse(&base_url).expect("Invalid pares base_url to reqwest::Url");
         let http_client = Client::builder().timeout(timeout)
@@ -25,17 +26,30 @@ impl EmailClient {
             authorization_token,
         }
     }
+}
 
-    
-    pub async fn send_email(&self, recipient: &SubscriberEmail, subject: &str, html_content: &str, text_content: &str) -> Result<(), reqwest::Error> {
+#[async_trait::async_trait]
+impl EmailTransport for PostmarkEmailClient {
+    async fn send_email_with_headers(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<(), anyhow::Error> {
         // let url = self.base_url.join("email").expect("aa");
 
         let url = self.base_url.join("email").unwrap();
-        let request_body = SendEmailRequest::new(self.sender.as_ref(),
-                                                 recipient.as_ref(),
-                                                 subject,
-                                                 html_content,
-                                                 text_content);
+        let mut request_body = SendEmailRequest::new(self.sender.as_ref(),
+                                                     recipient.as_ref(),
+                                                     subject,
+                                                     text_content,
+                                                     html_content);
+        request_body.headers = headers
+            .iter()
+            .map(|(name, value)| EmailHeader { name, value })
+            .collect();
 
         self
             .http_client
@@ -56,6 +70,15 @@ struct SendEmailRequest<'a> {
     subject: &'a str,
     text_body: &'a str,
     html_body: &'a str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    headers: Vec<EmailHeader<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct EmailHeader<'a> {
+    name: &'a str,
+    value: &'a str,
 }
 impl<'a> SendEmailRequest<'a> {
     pub fn new(from: &'a str, to: &'a str, subject: &'a str, text_body: &'a str, html_body: &'a str) -> Self {
@@ -65,6 +88,7 @@ impl<'a> SendEmailRequest<'a> {
             subject,
             text_body,
             html_body,
+            headers: Vec::new(),
         }
     }
 }
@@ -72,7 +96,7 @@ impl<'a> SendEmailRequest<'a> {
 #[cfg(test)]
 mod tests {
     use crate::domain::SubscriberEmail;
-    use crate::email_client::EmailClient;
+    use crate::email_client::{EmailTransport, PostmarkEmailClient};
     use claims::{assert_err, assert_ok};
     use fake::faker::internet::en::SafeEmail;
     use fake::faker::lorem::zh_cn::{Paragraph, Sentence};
@@ -80,7 +104,7 @@ mod tests {
     use secrecy::SecretString;
     use std::time::Duration;
 
-    use wiremock::matchers::{any, header, header_exists, method, path};
+    use wiremock::matchers::{any, body_partial_json, header, header_exists, method, path};
     use wiremock::{Match, Mock, MockServer, Request, ResponseTemplate};
 
 
@@ -96,9 +120,9 @@ mod tests {
         SubscriberEmail::parse(SafeEmail().fake()).unwrap()
     }
 
-    fn email_client(base_url: String) -> EmailClient {
+    fn email_client(base_url: String) -> PostmarkEmailClient {
         let fa = Faker.fake::<String>();    
-        EmailClient::new(base_url, email(), SecretString::from(fa), Duration::from_millis(200))
+        PostmarkEmailClient::new(base_url, email(), SecretString::from(fa), Duration::from_millis(200))
     }
     struct SendEmailBodyMatcher;
     impl Match for SendEmailBodyMatcher {
@@ -136,6 +160,34 @@ mod tests {
             .send_email(&email(), &subject(), &content(), &content()).await;
     }
 
+    #[tokio::test]
+    async fn send_email_with_headers_forwards_the_headers() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(path("/email"))
+            .and(method("POST"))
+            .and(body_partial_json(serde_json::json!({
+                "Headers": [{"Name": "List-Unsubscribe-Post", "Value": "List-Unsubscribe=One-Click"}]
+            })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email_with_headers(
+                &email(),
+                &subject(),
+                &content(),
+                &content(),
+                &[("List-Unsubscribe-Post", "List-Unsubscribe=One-Click")],
+            )
+            .await;
+
+        assert_ok!(outcome);
+    }
+
     #[tokio::test]
     async fn send_email_succeeds_if_the_server_returns_200() {
         let mock_server = MockServer::start().await;
@@ -0,0 +1,160 @@
+use crate::domain::SubscriberEmail;
+use crate::email_client::{build_message, EmailTransport};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use secrecy::{ExposeSecret, SecretString};
+use std::time::Duration;
+
+pub struct SmtpEmailClient {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    sender: SubscriberEmail,
+}
+
+impl SmtpEmailClient {
+    pub fn new(
+        host: &str,
+        port: u16,
+        credentials: Option<(String, SecretString)>,
+        require_tls: bool,
+        sender: SubscriberEmail,
+        timeout: Duration,
+    ) -> Result<Self, anyhow::Error> {
+        let mut builder = if require_tls {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?
+        } else {
+            // Plain-text connections are only meant for local SMTP sinks.
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host)
+        }
+            .port(port)
+            .timeout(Some(timeout));
+        if let Some((username, password)) = credentials {
+            builder = builder.credentials(Credentials::new(
+                username,
+                password.expose_secret().to_owned(),
+            ));
+        }
+        Ok(Self {
+            transport: builder.build(),
+            sender,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for SmtpEmailClient {
+    async fn send_email_with_headers(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<(), anyhow::Error> {
+        let message = build_message(&self.sender, recipient, subject, html_content, text_content, headers)?;
+        self.transport.send(message).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{EmailTransport, SmtpEmailClient};
+    use claims::{assert_err, assert_ok};
+    use fake::faker::internet::en::SafeEmail;
+    use fake::Fake;
+    use std::time::Duration;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use tokio::sync::oneshot;
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    /// A minimal SMTP sink: accepts one message and hands its DATA section back.
+    async fn spawn_smtp_sink(reject_data: bool) -> (u16, oneshot::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (sender, receiver) = oneshot::channel();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            writer.write_all(b"220 localhost ESMTP sink\r\n").await.unwrap();
+            let mut data = String::new();
+            let mut in_data = false;
+            while let Ok(Some(line)) = lines.next_line().await {
+                if in_data {
+                    if line == "." {
+                        in_data = false;
+                        let reply: &[u8] = if reject_data {
+                            b"554 Transaction failed\r\n"
+                        } else {
+                            b"250 OK\r\n"
+                        };
+                        writer.write_all(reply).await.unwrap();
+                    } else {
+                        data.push_str(&line);
+                        data.push('\n');
+                    }
+                    continue;
+                }
+                let command = line.to_uppercase();
+                if command.starts_with("EHLO") {
+                    writer.write_all(b"250 localhost\r\n").await.unwrap();
+                } else if command.starts_with("DATA") {
+                    in_data = true;
+                    writer.write_all(b"354 Go ahead\r\n").await.unwrap();
+                } else if command.starts_with("QUIT") {
+                    writer.write_all(b"221 Bye\r\n").await.unwrap();
+                    break;
+                } else {
+                    writer.write_all(b"250 OK\r\n").await.unwrap();
+                }
+            }
+            let _ = sender.send(data);
+        });
+        (port, receiver)
+    }
+
+    fn email_client(port: u16) -> SmtpEmailClient {
+        SmtpEmailClient::new("127.0.0.1", port, None, false, email(), Duration::from_secs(2)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn send_email_delivers_a_multipart_message_to_the_smtp_server() {
+        let (port, received) = spawn_smtp_sink(false).await;
+        let email_client = email_client(port);
+
+        let outcome = email_client
+            .send_email_with_headers(
+                &email(),
+                "Newsletter title",
+                "<p>Newsletter body as HTML</p>",
+                "Newsletter body as plain text",
+                &[("List-Unsubscribe-Post", "List-Unsubscribe=One-Click")],
+            )
+            .await;
+
+        assert_ok!(outcome);
+        drop(email_client);
+        let data = received.await.unwrap();
+        assert!(data.contains("Subject: Newsletter title"));
+        assert!(data.contains("List-Unsubscribe-Post: List-Unsubscribe=One-Click"));
+        assert!(data.contains("multipart/alternative"));
+        assert!(data.contains("Newsletter body as plain text"));
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_the_smtp_server_rejects_the_message() {
+        let (port, _received) = spawn_smtp_sink(true).await;
+        let email_client = email_client(port);
+
+        let outcome = email_client
+            .send_email(&email(), "Subject", "<p>Body</p>", "Body")
+            .await;
+
+        assert_err!(outcome);
+    }
+}
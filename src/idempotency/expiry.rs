@@ -0,0 +1,62 @@
+use crate::configuration::{IdempotencySettings, Settings};
+use crate::shutdown::Shutdown;
+use crate::startup::get_connection_pool;
+use chrono::Utc;
+use sqlx::PgPool;
+use std::time::Duration;
+
+pub async fn run_idempotency_cleanup_until_stopped(
+    configuration: Settings,
+    shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    cleanup_loop(connection_pool, configuration.idempotency, shutdown).await
+}
+
+async fn cleanup_loop(pool: PgPool, settings: IdempotencySettings, shutdown: Shutdown) -> Result<(), anyhow::Error> {
+    while !shutdown.is_requested() {
+        // Failures are logged by `purge_expired_idempotency_records`; the next run retries.
+        let _ = purge_expired_idempotency_records(&pool, settings.ttl(), settings.cleanup_batch_size).await;
+        shutdown.sleep(settings.cleanup_interval()).await;
+    }
+    Ok(())
+}
+
+/// Deletes the records older than `ttl`, `batch_size` rows per statement so that no delete
+/// holds its locks for long. Rows locked by a request in flight are skipped, not waited on.
+/// Returns how many rows were purged.
+#[tracing::instrument(skip(pool), fields(purged = tracing::field::Empty), err)]
+pub async fn purge_expired_idempotency_records(
+    pool: &PgPool,
+    ttl: Duration,
+    batch_size: i64,
+) -> Result<u64, anyhow::Error> {
+    let expired_before = Utc::now() - chrono::Duration::from_std(ttl)?;
+    let mut purged = 0;
+    loop {
+        let deleted = sqlx::query!(
+            r#"
+DELETE FROM idempotency
+WHERE (scope, idempotency_key) IN (
+    SELECT scope, idempotency_key
+    FROM idempotency
+    WHERE created_at < $1
+    LIMIT $2
+    FOR UPDATE SKIP LOCKED
+)
+"#,
+            expired_before,
+            batch_size
+        )
+            .execute(pool)
+            .await?
+            .rows_affected();
+        purged += deleted;
+        if deleted < batch_size as u64 {
+            break;
+        }
+    }
+    tracing::Span::current().record("purged", purged);
+    tracing::info!(purged, "Purged expired idempotency records");
+    Ok(purged)
+}
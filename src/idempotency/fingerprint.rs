@@ -0,0 +1,82 @@
+use crate::authentication::CSRF_FORM_FIELD;
+use crate::idempotency::IDEMPOTENCY_KEY_FORM_FIELD;
+use actix_web::{HttpMessage, HttpRequest};
+use sha2::{Digest, Sha256};
+
+/// A digest of what a request asks for, stored next to its idempotency key so that reusing
+/// the key for a different request can be told apart from a retry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestFingerprint(String);
+
+impl RequestFingerprint {
+    /// `operation` tells endpoints apart, `fields` are the inputs that make up the request.
+    /// `serde_json` keeps object keys sorted, so the digest does not depend on the order in
+    /// which the client sent them.
+    pub fn new(operation: &str, fields: serde_json::Value) -> Self {
+        let canonical = serde_json::json!({
+            "operation": operation,
+            "fields": fields,
+        });
+        Self(format!("{:x}", Sha256::digest(canonical.to_string().as_bytes())))
+    }
+
+    /// The method, path and body of `request`. JSON bodies are compared by value and forms
+    /// field by field, leaving out the idempotency key and the CSRF token, which change between
+    /// two submissions of the same form without changing what is asked for.
+    pub fn of_request(request: &HttpRequest, body: &[u8]) -> Self {
+        let fields = match request.content_type() {
+            "application/json" => serde_json::from_slice(body).ok(),
+            "application/x-www-form-urlencoded" => serde_urlencoded::from_bytes::<Vec<(String, String)>>(body)
+                .ok()
+                .map(|mut fields| {
+                    fields.retain(|(name, _)| name != IDEMPOTENCY_KEY_FORM_FIELD && name != CSRF_FORM_FIELD);
+                    fields.sort();
+                    serde_json::json!(fields)
+                }),
+            _ => None,
+        }
+            .unwrap_or_else(|| serde_json::json!(format!("{:x}", Sha256::digest(body))));
+        Self::new(&format!("{} {}", request.method(), request.path()), fields)
+    }
+}
+
+impl AsRef<str> for RequestFingerprint {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+    use serde_json::json;
+
+    #[test]
+    fn the_order_of_the_fields_does_not_matter() {
+        let a: serde_json::Value = serde_json::from_str(r#"{"title": "t", "html": "h"}"#).unwrap();
+        let b: serde_json::Value = serde_json::from_str(r#"{"html": "h", "title": "t"}"#).unwrap();
+        assert_eq!(RequestFingerprint::new("publish", a), RequestFingerprint::new("publish", b));
+    }
+
+    #[test]
+    fn different_requests_have_different_fingerprints() {
+        let fingerprint = RequestFingerprint::new("publish", json!({"title": "t"}));
+        assert_ne!(fingerprint, RequestFingerprint::new("publish", json!({"title": "u"})));
+        assert_ne!(fingerprint, RequestFingerprint::new("publish draft", json!({"title": "t"})));
+    }
+
+    #[test]
+    fn form_submissions_differing_only_in_their_tokens_match() {
+        let request = TestRequest::post()
+            .uri("/admin/newsletters")
+            .insert_header(("Content-Type", "application/x-www-form-urlencoded"))
+            .to_http_request();
+        let fingerprint = |body: &str| RequestFingerprint::of_request(&request, body.as_bytes());
+        assert_eq!(
+            fingerprint("title=t&idempotency_key=a&csrf_token=x"),
+            fingerprint("csrf_token=y&title=t&idempotency_key=b")
+        );
+        assert_ne!(fingerprint("title=t"), fingerprint("title=u"));
+    }
+}
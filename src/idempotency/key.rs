@@ -1,3 +1,8 @@
+/// The header API clients use to make a request safe to retry.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+/// The hidden field HTML forms carry it in instead.
+pub const IDEMPOTENCY_KEY_FORM_FIELD: &str = "idempotency_key";
+
 pub struct IdempotencyKey(String);
 impl TryFrom<String> for IdempotencyKey {
     type Error = anyhow::Error;
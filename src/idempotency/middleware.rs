@@ -0,0 +1,178 @@
+use crate::configuration::IdempotencySettings;
+use crate::idempotency::{
+    preferred_wait, save_response, try_processing, IdempotencyError, IdempotencyKey, IdempotencyScope, NextAction,
+    RequestFingerprint, IDEMPOTENCY_KEY_FORM_FIELD, IDEMPOTENCY_KEY_HEADER,
+};
+use crate::utils::e500;
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, FromRequest, HttpMessage, HttpRequest};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::cell::RefCell;
+use std::future::{ready, Ready};
+use std::rc::Rc;
+use std::sync::Arc;
+use tokio::sync::{Mutex, OwnedMappedMutexGuard, OwnedMutexGuard};
+
+/// Makes a route safe to retry: a request carrying a key it has already seen gets the saved
+/// response back instead of being processed again.
+///
+/// The key is read from the `Idempotency-Key` header, or from the `idempotency_key` field of a
+/// URL-encoded form. Keys are scoped to their sender, see `IdempotencyScope`. Requests without a
+/// key are processed as usual.
+///
+/// The handler can extract `Idempotency` to do its work in the transaction that records the
+/// key, so that the work and the saved response are committed together. That transaction is
+/// committed if the handler answers with a success or a redirect, and rolled back otherwise,
+/// which also frees the key for another attempt.
+pub async fn idempotent(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let pool = req
+        .app_data::<web::Data<PgPool>>()
+        .context("The database connection pool is missing from the application data.")
+        .map_err(e500)?
+        .clone();
+    let settings = req
+        .app_data::<web::Data<IdempotencySettings>>()
+        .context("The idempotency settings are missing from the application data.")
+        .map_err(e500)?
+        .clone();
+    let body = req.extract::<web::Bytes>().await?;
+    req.set_payload(Payload::from(body.clone()));
+
+    let idempotency_key = submitted_key(&req, &body)?;
+    let scope = IdempotencyScope::of(&req);
+    let transaction = match &idempotency_key {
+        Some(idempotency_key) => {
+            let fingerprint = RequestFingerprint::of_request(req.request(), &body);
+            let wait = preferred_wait(req.headers(), settings.in_flight_wait());
+            match try_processing(&pool, idempotency_key, &scope, &fingerprint, settings.ttl(), wait).await? {
+                NextAction::StartProcessing(transaction) => Some(transaction),
+                NextAction::ReturnSavedResponse(saved) => {
+                    for message in saved.flash_messages {
+                        message.send();
+                    }
+                    return Ok(req.into_response(saved.response));
+                }
+            }
+        }
+        // Opened on demand, for handlers that extract `Idempotency` anyway.
+        None => None,
+    };
+    let idempotency = Idempotency {
+        pool,
+        transaction: Arc::new(Mutex::new(transaction)),
+        flash_messages: Rc::new(RefCell::new(Vec::new())),
+    };
+    req.extensions_mut().insert(idempotency.clone());
+
+    let response = next.call(req).await?;
+    response.request().extensions_mut().remove::<Idempotency>();
+    let transaction = idempotency.transaction.lock().await.take();
+    let status = response.status();
+    if !(status.is_success() || status.is_redirection()) {
+        // Dropping the transaction rolls back the handler's work and forgets the key.
+        return Ok(response.map_into_boxed_body());
+    }
+    let (request, response) = response.into_parts();
+    let response = match (&idempotency_key, transaction) {
+        (Some(idempotency_key), Some(transaction)) => {
+            let flash_messages = idempotency.flash_messages.borrow().clone();
+            save_response(transaction, idempotency_key, &scope, response.map_into_boxed_body(), &flash_messages)
+                .await
+                .map_err(e500)?
+        }
+        (Some(_), None) => {
+            return Err(e500(anyhow::anyhow!("The transaction recording the idempotency key is gone.")));
+        }
+        (None, Some(transaction)) => {
+            transaction
+                .commit()
+                .await
+                .context("Failed to commit the request's transaction")
+                .map_err(e500)?;
+            response.map_into_boxed_body()
+        }
+        (None, None) => response.map_into_boxed_body(),
+    };
+    Ok(ServiceResponse::new(request, response))
+}
+
+fn submitted_key(req: &ServiceRequest, body: &[u8]) -> Result<Option<IdempotencyKey>, IdempotencyError> {
+    let value = match req.headers().get(IDEMPOTENCY_KEY_HEADER) {
+        Some(value) => Some(
+            value
+                .to_str()
+                .map_err(|_| IdempotencyError::InvalidKey(anyhow::anyhow!("The Idempotency-Key header is not valid UTF-8.")))?
+                .to_owned(),
+        ),
+        None if req.content_type() == "application/x-www-form-urlencoded" => {
+            serde_urlencoded::from_bytes::<Vec<(String, String)>>(body)
+                .ok()
+                .and_then(|fields| {
+                    fields
+                        .into_iter()
+                        .find(|(name, _)| name == IDEMPOTENCY_KEY_FORM_FIELD)
+                        .map(|(_, value)| value)
+                })
+        }
+        None => None,
+    };
+    value
+        .map(|value| IdempotencyKey::try_from(value).map_err(IdempotencyError::InvalidKey))
+        .transpose()
+}
+
+pub type RequestTransaction = OwnedMappedMutexGuard<Option<Transaction<'static, Postgres>>, Transaction<'static, Postgres>>;
+
+/// What a handler behind `idempotent` gets to share the request's transaction.
+#[derive(Clone)]
+pub struct Idempotency {
+    pool: web::Data<PgPool>,
+    transaction: Arc<Mutex<Option<Transaction<'static, Postgres>>>>,
+    flash_messages: Rc<RefCell<Vec<FlashMessage>>>,
+}
+
+impl Idempotency {
+    /// The transaction the key is recorded in, or a fresh one for requests without a key.
+    /// Do not commit it: `idempotent` does, once the response has been saved.
+    pub async fn transaction(&self) -> Result<RequestTransaction, anyhow::Error> {
+        let mut transaction = self.transaction.clone().lock_owned().await;
+        if transaction.is_none() {
+            *transaction = Some(
+                self.pool
+                    .begin()
+                    .await
+                    .context("Failed to acquire a Postgres connection from the pool")?,
+            );
+        }
+        Ok(OwnedMutexGuard::map(transaction, |transaction| {
+            transaction.as_mut().expect("The transaction was just opened")
+        }))
+    }
+
+    /// Sends `message`, and sends it again whenever the response is replayed.
+    pub fn flash(&self, message: FlashMessage) {
+        self.flash_messages.borrow_mut().push(message.clone());
+        message.send();
+    }
+}
+
+impl FromRequest for Idempotency {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<Idempotency>()
+                .cloned()
+                .ok_or_else(|| e500(anyhow::anyhow!("The route is not wrapped by the `idempotent` middleware."))),
+        )
+    }
+}
@@ -1,5 +1,17 @@
 mod key;
-pub use key::IdempotencyKey;
+pub use key::{IdempotencyKey, IDEMPOTENCY_KEY_FORM_FIELD, IDEMPOTENCY_KEY_HEADER};
+
+mod fingerprint;
+pub use fingerprint::RequestFingerprint;
+
+mod scope;
+pub use scope::IdempotencyScope;
+
+mod middleware;
+pub use middleware::{idempotent, Idempotency, RequestTransaction};
+
+mod expiry;
+pub use expiry::{purge_expired_idempotency_records, run_idempotency_cleanup_until_stopped};
 
 mod persistence;
 pub use persistence::*;
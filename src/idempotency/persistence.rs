@@ -1,9 +1,15 @@
-use crate::idempotency::IdempotencyKey;
-use actix_web::body::to_bytes;
+use crate::idempotency::{IdempotencyKey, IdempotencyScope, RequestFingerprint};
+use crate::routes::error_chain_fmt;
+use actix_web::body::{to_bytes, BoxBody};
+use actix_web::http::header::HeaderMap;
 use actix_web::http::StatusCode;
-use actix_web::HttpResponse;
+use actix_web::{HttpResponse, ResponseError};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use chrono::Utc;
 use sqlx::{PgPool, Postgres, Transaction};
-use uuid::Uuid;
+use std::fmt::{Debug, Formatter};
+use std::time::Duration;
 
 #[derive(Debug, sqlx::Type)]
 #[sqlx(type_name = "header_pair")]
@@ -12,23 +18,32 @@ struct HeaderPairRecord {
     value: Vec<u8>,
 }
 
+/// A response recorded for an idempotency key, with the flash messages that went with it:
+/// they travel in a cookie set outside of the idempotency layer, so they are not part of
+/// the saved headers.
+pub struct SavedResponse {
+    pub response: HttpResponse,
+    pub flash_messages: Vec<FlashMessage>,
+}
+
 pub async fn get_saved_response(
     pool: &PgPool,
     idempotency_key: &IdempotencyKey,
-    user_id: Uuid,
-) -> Result<Option<HttpResponse>, anyhow::Error> {
+    scope: &IdempotencyScope,
+) -> Result<Option<SavedResponse>, anyhow::Error> {
     let saved_response = sqlx::query!(
         r#"
 SELECT
     response_status_code as "response_status_code!",
     response_headers as "response_headers!: Vec<HeaderPairRecord>",
-    response_body as "response_body!"
+    response_body as "response_body!",
+    flash_messages
 FROM idempotency
 WHERE
-    user_id = $1 AND
+    scope = $1 AND
 idempotency_key = $2
 "#,
-        user_id,
+        scope.to_string(),
         idempotency_key.as_ref()
     ).fetch_optional(pool)
         .await?;
@@ -39,7 +54,14 @@ idempotency_key = $2
         for HeaderPairRecord { name, value } in r.response_headers {
             response.append_header((name, value));
         }
-        Ok(Some(response.body(r.response_body)))
+        let flash_messages = match r.flash_messages {
+            Some(messages) => serde_json::from_str(&messages).context("Failed to parse the saved flash messages")?,
+            None => Vec::new(),
+        };
+        Ok(Some(SavedResponse {
+            response: response.body(r.response_body),
+            flash_messages,
+        }))
     } else {
         Ok(None)
     }
@@ -48,8 +70,9 @@ idempotency_key = $2
 pub async fn save_response(
     mut transaction: Transaction<'static, Postgres>,
     idempotency_key: &IdempotencyKey,
-    user_id: Uuid,
+    scope: &IdempotencyScope,
     http_response: HttpResponse,
+    flash_messages: &[FlashMessage],
 ) -> Result<HttpResponse, anyhow::Error> {
     let (response_head, body) = http_response.into_parts();
     // let body = to_bytes(body).await.unwrap();
@@ -71,16 +94,18 @@ UPDATE idempotency
 SET
     response_status_code = $3,
     response_headers = $4,
-    response_body = $5
+    response_body = $5,
+    flash_messages = $6
 WHERE
-    user_id = $1 AND
+    scope = $1 AND
     idempotency_key = $2
 "#,
-        user_id,
+        scope.to_string(),
         idempotency_key.as_ref(),
         status_code,
         headers,
-        body.as_ref()
+        body.as_ref(),
+        serde_json::to_string(flash_messages)?
     )
         .execute(&mut *transaction)
         .await?;
@@ -93,40 +118,203 @@ WHERE
 // #[allow(clippy::large_enum_variant)]
 pub enum NextAction {
     StartProcessing(Transaction<'static, Postgres>),
-    ReturnSavedResponse(HttpResponse),
+    ReturnSavedResponse(SavedResponse),
+}
+
+#[derive(thiserror::Error)]
+pub enum IdempotencyError {
+    #[error("{0}")]
+    InvalidKey(#[source] anyhow::Error),
+    #[error("The idempotency key was already used for a different request")]
+    KeyReused,
+    #[error("A request with the same idempotency key is still being processed")]
+    RequestInFlight,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl Debug for IdempotencyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for IdempotencyError {
+    fn error_response(&self) -> HttpResponse<BoxBody> {
+        match self {
+            IdempotencyError::InvalidKey(e) => HttpResponse::BadRequest().body(e.to_string()),
+            IdempotencyError::KeyReused => HttpResponse::UnprocessableEntity().body(
+                "This idempotency key was already used for a request with different content. \
+                Use a new key to submit a different request.",
+            ),
+            IdempotencyError::RequestInFlight => HttpResponse::Conflict().body(
+                "A request with this idempotency key is still being processed. \
+                Retry later to get its response.",
+            ),
+            IdempotencyError::UnexpectedError(_) => HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR),
+        }
+    }
 }
 
+/// Keys older than `ttl` are forgotten first, so reusing one starts over.
+///
+/// A duplicate that arrives while the original request is still being processed waits for
+/// it for at most `wait`, then gets `IdempotencyError::RequestInFlight`.
+/// A zero `wait` gives up straight away.
 pub async fn try_processing(
     pool: &PgPool,
     idempotency_key: &IdempotencyKey,
-    user_id: Uuid,
-) -> Result<NextAction, anyhow::Error> {
-    let mut transaction = pool.begin().await?;
+    scope: &IdempotencyScope,
+    fingerprint: &RequestFingerprint,
+    ttl: Duration,
+    wait: Duration,
+) -> Result<NextAction, IdempotencyError> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    lock_key(&mut transaction, idempotency_key, scope, wait).await?;
+    let expired_before = Utc::now() - chrono::Duration::from_std(ttl).context("The TTL is out of range")?;
+    sqlx::query!(
+        r#"
+DELETE FROM idempotency
+WHERE
+    scope = $1 AND
+    idempotency_key = $2 AND
+    created_at < $3
+"#,
+        scope.to_string(),
+        idempotency_key.as_ref(),
+        expired_before
+    )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to forget an expired idempotency key")?;
     let n_inserted_rows = sqlx::query!(
         r#"
 INSERT INTO idempotency(
+                        scope,
                         user_id,
                         idempotency_key,
+                        request_fingerprint,
                          created_at
 )
-VALUES ($1,$2, now())
+VALUES ($1, $2, $3, $4, now())
 ON CONFLICT DO NOTHING
 "#,
-        user_id,
-        idempotency_key.as_ref()
+        scope.to_string(),
+        scope.user_id(),
+        idempotency_key.as_ref(),
+        fingerprint.as_ref()
     )
         .execute(&mut *transaction)
-        .await?
+        .await
+        .context("Failed to store the idempotency key")?
         .rows_affected();
 
     if n_inserted_rows > 0 {
         Ok(NextAction::StartProcessing(transaction))
     } else {
-        let saved_response = get_saved_response(pool, idempotency_key, user_id)
+        let stored_fingerprint = sqlx::query_scalar!(
+            r#"
+SELECT request_fingerprint
+FROM idempotency
+WHERE scope = $1 AND idempotency_key = $2
+"#,
+            scope.to_string(),
+            idempotency_key.as_ref()
+        )
+            .fetch_one(&mut *transaction)
+            .await
+            .context("Failed to retrieve the request fingerprint")?;
+        if stored_fingerprint.is_some_and(|stored| stored != fingerprint.as_ref()) {
+            return Err(IdempotencyError::KeyReused);
+        }
+        let saved_response = get_saved_response(pool, idempotency_key, scope)
             .await?
             .ok_or_else(||
                 anyhow::anyhow!("We expected a saved response, we didn't find it")
             )?;
         Ok(NextAction::ReturnSavedResponse(saved_response))
     }
-}
\ No newline at end of file
+}
+
+/// Honours an RFC 7240 `Prefer: wait=<seconds>` header, without ever waiting longer than `max`.
+pub fn preferred_wait(headers: &HeaderMap, max: Duration) -> Duration {
+    headers
+        .get_all("Prefer")
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .find_map(|preference| preference.trim().strip_prefix("wait=")?.trim().parse::<u64>().ok())
+        .map_or(max, |seconds| Duration::from_secs(seconds).min(max))
+}
+
+/// Serialises requests that share a key with a transaction-scoped advisory lock, taken
+/// before the row is touched so that a duplicate never queues up on the row lock itself.
+/// The lock is released when the original request commits or rolls back.
+/// Two different keys that happen to hash alike only make each other wait.
+async fn lock_key(
+    transaction: &mut Transaction<'static, Postgres>,
+    idempotency_key: &IdempotencyKey,
+    scope: &IdempotencyScope,
+    wait: Duration,
+) -> Result<(), IdempotencyError> {
+    let lock_id = format!("{}:{}", scope, idempotency_key.as_ref());
+    if wait.is_zero() {
+        let acquired = sqlx::query_scalar!(
+            r#"SELECT pg_try_advisory_xact_lock(hashtextextended($1, 0)) AS "acquired!""#,
+            lock_id
+        )
+            .fetch_one(&mut **transaction)
+            .await
+            .context("Failed to lock the idempotency key")?;
+        return if acquired { Ok(()) } else { Err(IdempotencyError::RequestInFlight) };
+    }
+    sqlx::query!(
+        "SELECT set_config('lock_timeout', $1, true)",
+        format!("{}ms", wait.as_millis().max(1))
+    )
+        .fetch_one(&mut **transaction)
+        .await
+        .context("Failed to set the lock timeout")?;
+    let locked = sqlx::query!("SELECT pg_advisory_xact_lock(hashtextextended($1, 0))", lock_id)
+        .fetch_one(&mut **transaction)
+        .await;
+    match locked {
+        Ok(_) => {}
+        Err(sqlx::Error::Database(e)) if e.code().as_deref() == Some(LOCK_NOT_AVAILABLE) => {
+            return Err(IdempotencyError::RequestInFlight);
+        }
+        Err(e) => return Err(anyhow::Error::new(e).context("Failed to lock the idempotency key").into()),
+    }
+    sqlx::query!("SET LOCAL lock_timeout TO DEFAULT")
+        .execute(&mut **transaction)
+        .await
+        .context("Failed to reset the lock timeout")?;
+    Ok(())
+}
+
+/// SQLSTATE raised when `lock_timeout` expires.
+const LOCK_NOT_AVAILABLE: &str = "55P03";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::{HeaderName, HeaderValue};
+
+    fn prefer(value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(HeaderName::from_static("prefer"), HeaderValue::from_static(value));
+        headers
+    }
+
+    #[test]
+    fn the_wait_preference_is_capped_by_the_configured_maximum() {
+        let max = Duration::from_secs(10);
+        assert_eq!(preferred_wait(&HeaderMap::new(), max), max);
+        assert_eq!(preferred_wait(&prefer("wait=0"), max), Duration::ZERO);
+        assert_eq!(preferred_wait(&prefer("respond-async, wait=3"), max), Duration::from_secs(3));
+        assert_eq!(preferred_wait(&prefer("wait=600"), max), max);
+        assert_eq!(preferred_wait(&prefer("wait=soon"), max), max);
+    }
+}
@@ -0,0 +1,84 @@
+use crate::authentication::{bearer_token, UserId};
+use crate::utils::client_ip;
+use actix_web::dev::ServiceRequest;
+use actix_web::HttpMessage;
+use secrecy::ExposeSecret;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+/// Who an idempotency key belongs to: two callers can pick the same key without
+/// getting each other's responses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IdempotencyScope {
+    /// A logged-in user of the admin area.
+    User(Uuid),
+    /// An API client, identified by a digest of its bearer token.
+    ApiToken(String),
+    /// Anyone else, e.g. a visitor submitting the subscription form.
+    ClientIp(String),
+}
+
+impl IdempotencyScope {
+    /// The logged-in user if `reject_anonymous_users` ran before, then the bearer token,
+    /// then the client address.
+    pub fn of(req: &ServiceRequest) -> Self {
+        if let Some(user_id) = req.extensions().get::<UserId>() {
+            return Self::User(**user_id);
+        }
+        if let Ok(token) = bearer_token(req.headers()) {
+            return Self::api_token(token.expose_secret());
+        }
+        Self::ClientIp(client_ip(req.request()).unwrap_or_default())
+    }
+
+    /// Tokens are secrets: only their digest ends up in the database.
+    pub fn api_token(token: &str) -> Self {
+        Self::ApiToken(format!("{:x}", Sha256::digest(token.as_bytes())))
+    }
+
+    /// Set for user-scoped records, which are deleted along with their owner.
+    pub fn user_id(&self) -> Option<Uuid> {
+        match self {
+            Self::User(user_id) => Some(*user_id),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for IdempotencyScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::User(user_id) => write!(f, "user:{}", user_id),
+            Self::ApiToken(digest) => write!(f, "token:{}", digest),
+            Self::ClientIp(ip) => write!(f, "ip:{}", ip),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn the_logged_in_user_comes_first_then_the_token_then_the_address() {
+        let user_id = Uuid::new_v4();
+        let request = TestRequest::post()
+            .insert_header(("Authorization", "Bearer z2p_abc"))
+            .peer_addr("203.0.113.1:4000".parse().unwrap())
+            .to_srv_request();
+        assert_eq!(IdempotencyScope::of(&request), IdempotencyScope::api_token("z2p_abc"));
+        request.extensions_mut().insert(UserId(user_id));
+        assert_eq!(IdempotencyScope::of(&request), IdempotencyScope::User(user_id));
+
+        let request = TestRequest::post()
+            .peer_addr("203.0.113.1:4000".parse().unwrap())
+            .to_srv_request();
+        assert_eq!(IdempotencyScope::of(&request).to_string(), "ip:203.0.113.1");
+    }
+
+    #[test]
+    fn tokens_are_not_stored_in_clear() {
+        assert!(!IdempotencyScope::api_token("z2p_abc").to_string().contains("z2p_abc"));
+    }
+}
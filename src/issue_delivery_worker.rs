@@ -1,37 +1,193 @@
-use crate::configuration::Settings;
-use crate::domain::SubscriberEmail;
-use crate::email_client::EmailClient;
-use crate::startup::get_connection_pool;
-use sqlx::{PgPool, Postgres, Transaction};
+use crate::configuration::{Settings, WorkerSettings};
+use crate::domain::{IssueTemplate, MergeValues, SubscriberEmail, UnsubscribeToken};
+use crate::email_client::EmailTransport;
+use crate::shutdown::Shutdown;
+use crate::utils::html_escape;
+use rand::Rng;
+use secrecy::SecretString;
+use sqlx::postgres::{PgListener, PgPoolOptions};
+use sqlx::{PgConnection, PgPool, Postgres, Transaction};
+use std::sync::Arc;
 use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinSet;
 use tracing::field::display;
 use tracing::Span;
 use uuid::Uuid;
 
+/// Delivers queued emails until `shutdown` is requested, with `worker.concurrency` executors
+/// working through the queue side by side. The tasks in progress at that point are finished
+/// first; if the process gives up waiting, their transactions are rolled back and the tasks
+/// go back to the queue.
 pub async fn run_worker_until_stopped(
-    configuration: Settings
+    configuration: Settings,
+    shutdown: Shutdown,
 ) -> Result<(), anyhow::Error> {
-    let connection_pool = get_connection_pool(&configuration.database);
+    let concurrency = configuration.worker.concurrency;
+    if concurrency == 0 {
+        anyhow::bail!("The worker needs at least one executor.");
+    }
+    // One connection for each executor's transaction, plus the one listening for new tasks.
+    let connection_pool = PgPoolOptions::new()
+        .acquire_timeout(Duration::from_secs(2))
+        .max_connections(concurrency as u32 + 1)
+        .connect_lazy_with(configuration.database.with_db());
+    let email_client = configuration.email_client.client();
 
+    let executor = Executor {
+        pool: connection_pool,
+        email_client,
+        settings: Arc::new(configuration.worker),
+        base_url: Arc::from(configuration.application.base_url),
+        hmac_secret: configuration.application.hmac_secret,
+    };
+    // Listening before the first look at the queue, so that nothing enqueued in between is missed.
+    let notifications = QueueNotifications::listen(executor.pool.clone()).await;
+    let (wakeups, _) = watch::channel(());
+    let wakeups = Arc::new(wakeups);
+    let forwarding = tokio::spawn(notifications.forward(
+        wakeups.clone(),
+        executor.settings.queue_poll_interval(),
+        shutdown.clone(),
+    ));
 
-    let email_client = configuration.email_client.client();
+    let mut executors = JoinSet::new();
+    for _ in 0..concurrency {
+        executors.spawn(executor.clone().run(wakeups.subscribe(), shutdown.clone()));
+    }
+    while let Some(outcome) = executors.join_next().await {
+        // `run` only returns once the shutdown is requested: anything else is a panic.
+        if let Err(e) = outcome {
+            if shutdown.is_requested() {
+                continue;
+            }
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "A delivery executor crashed. Starting another one in its place."
+            );
+            executors.spawn(executor.clone().run(wakeups.subscribe(), shutdown.clone()));
+        }
+    }
+    forwarding.abort();
+    Ok(())
+}
+
+/// One of the worker's executors. They share nothing but the pool: `dequeue_task` skips the
+/// rows other executors have locked, so each task is delivered by exactly one of them.
+#[derive(Clone)]
+struct Executor {
+    pool: PgPool,
+    email_client: Arc<dyn EmailTransport>,
+    settings: Arc<WorkerSettings>,
+    base_url: Arc<str>,
+    hmac_secret: SecretString,
+}
 
-    worker_loop(connection_pool, email_client).await
+impl Executor {
+    async fn run(self, mut wakeups: watch::Receiver<()>, shutdown: Shutdown) {
+        while !shutdown.is_requested() {
+            // Marking the wake-ups seen before looking at the queue, so that tasks enqueued
+            // after it turns out empty still wake this executor up.
+            wakeups.borrow_and_update();
+            match try_execute_task(
+                &self.pool,
+                self.email_client.as_ref(),
+                &self.settings,
+                &self.base_url,
+                &self.hmac_secret,
+            )
+                .await
+            {
+                Ok(ExecutionOutcome::EmptyQueue) => {
+                    // Falls back to polling if the notifications stop being forwarded.
+                    tokio::select! {
+                        Ok(()) = wakeups.changed() => {}
+                        _ = shutdown.sleep(self.settings.queue_poll_interval()) => {}
+                    }
+                }
+                Err(_) => {
+                    shutdown.sleep(Duration::from_secs(1)).await;
+                }
+                Ok(ExecutionOutcome::TaskCompleted) => {}
+            }
+        }
+    }
 }
 
-async fn worker_loop(
+/// The channel `issue_delivery_queue` notifies on, see its `notify_issue_delivery_queue` triggers.
+const QUEUE_CHANNEL: &str = "issue_delivery_queue";
+
+/// Notifications that tasks have been enqueued. They are a shortcut, not a guarantee: the ones
+/// sent while the connection is down are lost, so executors also poll the queue.
+struct QueueNotifications {
     pool: PgPool,
-    email_client: EmailClient,
-) -> Result<(), anyhow::Error> {
-    loop {
-        match try_execute_task(&pool, &email_client).await {
-            Ok(ExecutionOutcome::EmptyQueue) => {
-                tokio::time::sleep(Duration::from_secs(10)).await;
+    listener: Option<PgListener>,
+}
+
+impl QueueNotifications {
+    async fn listen(pool: PgPool) -> Self {
+        let mut notifications = Self { pool, listener: None };
+        notifications.connect().await;
+        notifications
+    }
+
+    async fn connect(&mut self) {
+        let listener = async {
+            let mut listener = PgListener::connect_with(&self.pool).await?;
+            listener.listen(QUEUE_CHANNEL).await?;
+            Ok::<_, sqlx::Error>(listener)
+        };
+        self.listener = match listener.await {
+            Ok(listener) => Some(listener),
+            Err(e) => {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to listen for enqueued tasks. Polling the queue until the next attempt."
+                );
+                None
+            }
+        };
+    }
+
+    /// Wakes up every executor whenever tasks are enqueued, until `shutdown` is requested.
+    async fn forward(mut self, wakeups: Arc<watch::Sender<()>>, retry_interval: Duration, shutdown: Shutdown) {
+        while !shutdown.is_requested() {
+            if self.wait(retry_interval, &shutdown).await {
+                wakeups.send_replace(());
             }
-            Err(_) => {
-                tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    }
+
+    /// Returns `true` when tasks are enqueued, `false` after `timeout` at the latest.
+    async fn wait(&mut self, timeout: Duration, shutdown: &Shutdown) -> bool {
+        if self.listener.is_none() {
+            self.connect().await;
+        }
+        let Some(listener) = self.listener.as_mut() else {
+            shutdown.sleep(timeout).await;
+            return false;
+        };
+        // `recv` reconnects by itself after a dropped connection; it only fails if it cannot.
+        let received = tokio::select! {
+            received = listener.recv() => Some(received),
+            _ = shutdown.sleep(timeout) => None,
+        };
+        match received {
+            Some(Ok(_)) => true,
+            Some(Err(e)) => {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Lost the connection listening for enqueued tasks and failed to reconnect."
+                );
+                self.listener = None;
+                // Not straight back to listening, which would spin if the database is down.
+                shutdown.sleep(Duration::from_secs(1)).await;
+                false
             }
-            Ok(ExecutionOutcome::TaskCompleted) => {}
+            None => false,
         }
     }
 }
@@ -45,69 +201,155 @@ pub enum ExecutionOutcome {
     skip_all,
     fields(
 newsletter_issue_id = tracing::field::Empty,
-subscriber_emial= tracing::field::Empty
+subscriber_email = tracing::field::Empty,
+n_attempts = tracing::field::Empty
     ),
     err
 )]
 pub async fn try_execute_task(
     pool: &PgPool,
-    email_client: &EmailClient,
+    email_client: &dyn EmailTransport,
+    settings: &WorkerSettings,
+    base_url: &str,
+    hmac_secret: &SecretString,
 ) -> Result<ExecutionOutcome, anyhow::Error> {
-    match dequeue_task(pool).await? {
-        Some((transaction, issue_id, email)) => {
-            Span::current()
-                .record("newsletter_issue_id", &display(issue_id))
-                .record("subscriber_email", &display(&email));
-
-
-            match SubscriberEmail::parse(email.clone()) {
-                Ok(email) => {
-                    let issue = get_issue(pool, issue_id).await?;
-                    if let Err(e) = email_client.send_email(
-                        &email,
-                        &issue.title,
-                        &issue.text_content,
-                        &issue.html_content,
-                    ).await {
-                        tracing::error!(
-                        error.cause_chain = ?e,
-                        error.message = %e,
-                        "Failed to deliver issue to a confirmed subscriber. \
-                        Skipping."
-                    )
-                    }
-                }
-                Err(e) => {
-                    tracing::error!(
+    let Some((mut transaction, task)) = dequeue_task(pool).await? else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    Span::current()
+        .record("newsletter_issue_id", display(task.issue_id))
+        .record("subscriber_email", display(&task.email))
+        .record("n_attempts", task.n_attempts);
+
+    let email = match SubscriberEmail::parse(task.email.clone()) {
+        Ok(email) => email,
+        Err(e) => {
+            tracing::error!(
+                error.message = %e,
+                "Dead-lettering a confirmed subscriber. \
+                Their stored contact details are invalid."
+            );
+            // Retrying cannot fix an invalid address.
+            dead_letter_task(transaction, &task, &e).await?;
+            return Ok(ExecutionOutcome::TaskCompleted);
+        }
+    };
+    let subscriber_name = match get_confirmed_subscriber_name(&mut transaction, task.subscriber_id).await? {
+        Some(subscriber_name) => subscriber_name,
+        None => {
+            tracing::info!("Skipping a subscriber who is no longer confirmed.");
+            delete_task(transaction, task.issue_id, &task.email).await?;
+            return Ok(ExecutionOutcome::TaskCompleted);
+        }
+    };
+    let issue = get_issue(&mut transaction, task.issue_id).await?;
+    let unsubscribe_link = format!(
+        "{}/subscriptions/unsubscribe?token={}",
+        base_url,
+        UnsubscribeToken::generate(task.subscriber_id, hmac_secret).as_ref()
+    );
+    let values = MergeValues {
+        name: &subscriber_name,
+        email: email.as_ref(),
+        unsubscribe_url: &unsubscribe_link,
+    };
+    let title = personalize(&issue.title, |t| t.render_text(&values));
+    let html_content = format!(
+        "{}<p><a href=\"{}\">Unsubscribe</a></p>",
+        personalize(&issue.html_content, |t| t.render_html(&values)),
+        html_escape(&unsubscribe_link)
+    );
+    let text_content = format!(
+        "{}\n\nUnsubscribe: {}",
+        personalize(&issue.text_content, |t| t.render_text(&values)),
+        unsubscribe_link
+    );
+    let list_unsubscribe = format!("<{}>", unsubscribe_link);
+    match email_client
+        .send_email_with_headers(
+            &email,
+            &title,
+            &html_content,
+            &text_content,
+            &[
+                ("List-Unsubscribe", &list_unsubscribe),
+                ("List-Unsubscribe-Post", "List-Unsubscribe=One-Click"),
+            ],
+        )
+        .await
+    {
+        Ok(()) => delete_task(transaction, task.issue_id, &task.email).await?,
+        Err(e) => {
+            let n_attempts = task.n_attempts + 1;
+            if n_attempts >= settings.max_delivery_attempts {
+                tracing::error!(
                     error.cause_chain = ?e,
-                        error.message = %e,
-                        "Skipping a confirmed subscriber. \
-                        Their stored contact details are invalid."
-                )
-                }
+                    error.message = %e,
+                    "Failed to deliver issue to a confirmed subscriber. \
+                    Giving up after {} attempts.",
+                    n_attempts
+                );
+                dead_letter_task(transaction, &task, &e.to_string()).await?;
+            } else {
+                let delay = retry_delay(settings, n_attempts);
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to deliver issue to a confirmed subscriber. \
+                    Retrying in {:?}.",
+                    delay
+                );
+                reschedule_task(transaction, &task, delay, &e.to_string()).await?;
             }
-            delete_task(transaction, issue_id, &email).await?;
-            Ok(ExecutionOutcome::TaskCompleted)
         }
-        None => {
-            Ok(ExecutionOutcome::EmptyQueue)
+    }
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+/// Merge tags are validated when an issue is published, so a parse failure can only come from
+/// content stored before they existed: send it unchanged rather than failing the delivery.
+fn personalize(content: &str, render: impl FnOnce(&IssueTemplate) -> String) -> String {
+    match IssueTemplate::parse(content) {
+        Ok(template) => render(&template),
+        Err(e) => {
+            tracing::warn!(error.message = %e, "Sending issue content without personalization.");
+            content.to_owned()
         }
     }
-    // 531 页有练习未完成
+}
+
+/// Exponential backoff with "equal jitter": the delay doubles with every failed attempt
+/// (capped at `retry_max_delay`) and the actual wait is picked at random in its upper half.
+fn retry_delay(settings: &WorkerSettings, n_attempts: i16) -> Duration {
+    let exponent = n_attempts.saturating_sub(1).clamp(0, 31) as u32;
+    let delay = settings
+        .retry_base_delay()
+        .saturating_mul(2u32.saturating_pow(exponent))
+        .min(settings.retry_max_delay());
+    let half = delay / 2;
+    let jitter = rand::thread_rng().gen_range(0..=half.as_millis() as u64);
+    half + Duration::from_millis(jitter)
 }
 
 type PgTransaction = Transaction<'static, Postgres>;
 
+struct Task {
+    issue_id: Uuid,
+    email: String,
+    subscriber_id: Uuid,
+    n_attempts: i16,
+}
 
 #[tracing::instrument(skip_all)]
 async fn dequeue_task(
     pool: &PgPool
-) -> Result<Option<(PgTransaction, Uuid, String)>, anyhow::Error> {
+) -> Result<Option<(PgTransaction, Task)>, anyhow::Error> {
     let mut transaction = pool.begin().await?;
     let r = sqlx::query!(
         r#"
-SELECT issue_delivery_queue.newsletter_issue_id, issue_delivery_queue.subscriber_email
+SELECT newsletter_issue_id, subscriber_email, subscriber_id, n_attempts
 FROM issue_delivery_queue
+WHERE execute_after <= now()
 FOR UPDATE
 SKIP LOCKED
 LIMIT 1
@@ -118,8 +360,12 @@ LIMIT 1
     if let Some(r) = r {
         Ok(Some((
             transaction,
-            r.newsletter_issue_id,
-            r.subscriber_email
+            Task {
+                issue_id: r.newsletter_issue_id,
+                email: r.subscriber_email,
+                subscriber_id: r.subscriber_id,
+                n_attempts: r.n_attempts,
+            }
         )))
     } else {
         Ok(None)
@@ -147,6 +393,82 @@ WHERE newsletter_issue_id = $1 AND
     Ok(())
 }
 
+async fn reschedule_task(
+    mut transaction: PgTransaction,
+    task: &Task,
+    delay: Duration,
+    error: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+UPDATE issue_delivery_queue
+SET
+    n_attempts = n_attempts + 1,
+    execute_after = now() + make_interval(secs => $3),
+    last_error = $4
+WHERE newsletter_issue_id = $1 AND
+      subscriber_email = $2
+"#,
+        task.issue_id,
+        task.email,
+        delay.as_secs_f64(),
+        error
+    )
+        .execute(&mut *transaction)
+        .await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+async fn dead_letter_task(
+    mut transaction: PgTransaction,
+    task: &Task,
+    error: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+INSERT INTO issue_delivery_dead_letters (
+    newsletter_issue_id,
+    subscriber_email,
+    n_attempts,
+    last_error,
+    failed_at
+)
+VALUES ($1, $2, $3, $4, now())
+ON CONFLICT (newsletter_issue_id, subscriber_email) DO UPDATE
+SET
+    n_attempts = EXCLUDED.n_attempts,
+    last_error = EXCLUDED.last_error,
+    failed_at = EXCLUDED.failed_at
+"#,
+        task.issue_id,
+        task.email,
+        task.n_attempts + 1,
+        error
+    )
+        .execute(&mut *transaction)
+        .await?;
+    delete_task(transaction, task.issue_id, &task.email).await
+}
+
+/// Subscribers may have left between enqueueing and delivery: only confirmed ones get the issue.
+async fn get_confirmed_subscriber_name(
+    connection: &mut PgConnection,
+    subscriber_id: Uuid,
+) -> Result<Option<String>, anyhow::Error> {
+    let r = sqlx::query!(
+        r#"
+SELECT name
+FROM subscriptions
+WHERE id = $1 AND status = 'confirmed'
+"#,
+        subscriber_id
+    )
+        .fetch_optional(connection)
+        .await?;
+    Ok(r.map(|r| r.name))
+}
+
 struct NewsletterIssue {
     title: String,
     text_content: String,
@@ -154,7 +476,7 @@ struct NewsletterIssue {
 }
 
 async fn get_issue(
-    pool: &PgPool,
+    connection: &mut PgConnection,
     issue_id: Uuid,
 ) -> Result<NewsletterIssue, anyhow::Error> {
     let issue = sqlx::query_as!(
@@ -166,7 +488,43 @@ WHERE
     newsletter_issue_id = $1
 "#,
         issue_id
-    ).fetch_one(pool)
+    ).fetch_one(connection)
         .await?;
     Ok(issue)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::retry_delay;
+    use crate::configuration::WorkerSettings;
+    use std::time::Duration;
+
+    fn settings() -> WorkerSettings {
+        WorkerSettings {
+            max_delivery_attempts: 5,
+            retry_base_delay_milliseconds: 1000,
+            retry_max_delay_seconds: 10,
+            scheduler_poll_interval_seconds: 30,
+            queue_poll_interval_seconds: 30,
+            concurrency: 1,
+        }
+    }
+
+    #[test]
+    fn retry_delay_grows_exponentially() {
+        let settings = settings();
+        for (n_attempts, expected) in [(1, 1), (2, 2), (3, 4), (4, 8)] {
+            let expected = Duration::from_secs(expected);
+            let delay = retry_delay(&settings, n_attempts);
+            assert!(delay >= expected / 2 && delay <= expected, "{:?} for attempt {}", delay, n_attempts);
+        }
+    }
+
+    #[test]
+    fn retry_delay_is_capped() {
+        let settings = settings();
+        let delay = retry_delay(&settings, i16::MAX);
+        assert!(delay <= settings.retry_max_delay());
+        assert!(delay >= settings.retry_max_delay() / 2);
+    }
+}
@@ -4,8 +4,14 @@ pub mod startup;
 pub mod telemetry;
 pub mod domain;
 pub mod email_client;
+pub mod audit;
 pub mod authentication;
+pub mod cli;
 pub mod session_state;
+pub mod session_store;
+pub mod shutdown;
 pub mod utils;
 pub mod idempotency;
-pub mod issue_delivery_worker;
\ No newline at end of file
+pub mod issue_delivery_worker;
+pub mod markdown;
+pub mod newsletter_scheduler;
\ No newline at end of file
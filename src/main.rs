@@ -1,42 +1,106 @@
-use std::fmt::{Debug, Display};
-use tokio::task::JoinError;
+use clap::{Parser, Subcommand};
+use std::collections::HashMap;
+use std::future::Future;
+use tokio::task::{Id, JoinError, JoinSet};
 use zero2prod_my::configuration::get_configuration;
+use zero2prod_my::idempotency::run_idempotency_cleanup_until_stopped;
 use zero2prod_my::issue_delivery_worker::run_worker_until_stopped;
+use zero2prod_my::newsletter_scheduler::run_scheduler_until_stopped;
+use zero2prod_my::shutdown::{shutdown_channel, termination_signal};
 use zero2prod_my::startup::Application;
 use zero2prod_my::telemetry::{get_subscriber, init_subscriber};
 
+#[derive(Parser)]
+#[command(name = "zero2prod", version)]
+struct Cli {
+    #[command(subcommand)]
+    mode: Option<Mode>,
+}
+
+#[derive(Subcommand, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// Only the HTTP API.
+    Serve,
+    /// Only the background tasks: newsletter delivery, scheduling and idempotency cleanup.
+    Worker,
+    /// The API and the background tasks in one process. The default.
+    All,
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let mode = Cli::parse().mode.unwrap_or(Mode::All);
     let subscriber = get_subscriber("zero2prod".into(), "info".into(), std::io::stdout);
     init_subscriber(subscriber);
     let configuration = get_configuration().expect("Failed to read configuration");
-    // 需注意陷阱\ 下面的方法是 可并发不可并行.有一个任务阻塞了,都会阻塞.
-    // let application = Application::build(configuration.clone())
-    //     .await?
-    //     .run_until_stopped();
-    //
-    // let worker = run_worker_until_stopped(configuration);
+    let (shutdown_trigger, shutdown) = shutdown_channel();
+
+    // 需注意陷阱\ 不 spawn 的 future 是 可并发不可并行.有一个任务阻塞了,都会阻塞.
+    let mut tasks = Tasks::default();
+    if mode != Mode::Worker {
+        let application = Application::build(configuration.clone()).await?;
+        let shutdown = shutdown.clone();
+        tasks.spawn("API", async move { Ok(application.run_until_stopped(shutdown).await?) });
+    }
+    if mode != Mode::Serve {
+        tasks.spawn("Background worker", run_worker_until_stopped(configuration.clone(), shutdown.clone()));
+        tasks.spawn("Newsletter scheduler", run_scheduler_until_stopped(configuration.clone(), shutdown.clone()));
+        tasks.spawn(
+            "Idempotency cleanup",
+            run_idempotency_cleanup_until_stopped(configuration.clone(), shutdown.clone()),
+        );
+    }
 
+    // When one task stops on its own, the others are stopped too rather than left running alone.
+    tokio::select! {
+        signal = termination_signal() => {
+            signal?;
+            tracing::info!("Shutting down");
+        }
+        Some(outcome) = tasks.join_next() => tasks.report_exit(outcome),
+    }
+    shutdown_trigger.trigger();
+    let timeout = configuration.application.shutdown_timeout();
+    let stopped = tokio::time::timeout(timeout, async {
+        while let Some(outcome) = tasks.join_next().await {
+            tasks.report_exit(outcome);
+        }
+    })
+        .await;
+    if stopped.is_err() {
+        tracing::warn!("Aborting the tasks that did not stop within {:?}", timeout);
+        tasks.set.shutdown().await;
+    }
+    Ok(())
+}
 
-    let application = Application::build(configuration.clone())
-        .await?
-        .run_until_stopped();
+#[derive(Default)]
+struct Tasks {
+    set: JoinSet<Result<(), anyhow::Error>>,
+    names: HashMap<Id, &'static str>,
+}
 
-    let application_task = tokio::spawn(application);
+impl Tasks {
+    fn spawn(&mut self, name: &'static str, task: impl Future<Output = Result<(), anyhow::Error>> + Send + 'static) {
+        let handle = self.set.spawn(task);
+        self.names.insert(handle.id(), name);
+    }
 
-    let worker = run_worker_until_stopped(configuration);
+    async fn join_next(&mut self) -> Option<Result<(Id, Result<(), anyhow::Error>), JoinError>> {
+        self.set.join_next_with_id().await
+    }
 
-    let worker_task = tokio::spawn(worker);
-    tokio::select! {
-        o = application_task =>report_exit("API", o),
-        o = worker_task=>report_exit("Background worker", o),
+    fn report_exit(&self, outcome: Result<(Id, Result<(), anyhow::Error>), JoinError>) {
+        match outcome {
+            Ok((id, outcome)) => report_exit(self.names[&id], Ok(outcome)),
+            Err(e) => report_exit(self.names[&e.id()], Err(e)),
+        }
     }
-    Ok(())
 }
 
 fn report_exit(
     task_name: &str,
-    outcome: Result<Result<(), impl Debug + Display>, JoinError>,
+    outcome: Result<Result<(), anyhow::Error>, JoinError>,
 ) {
     match outcome {
         Ok(Ok(())) => {
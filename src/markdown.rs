@@ -0,0 +1,226 @@
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use unicode_segmentation::UnicodeSegmentation;
+
+fn parser(source: &str) -> Parser<'_> {
+    Parser::new_ext(source, Options::ENABLE_STRIKETHROUGH)
+}
+
+/// Renders Markdown to HTML, sanitized so that authors cannot smuggle scripts or
+/// tracking markup into an issue.
+pub fn to_html(source: &str) -> String {
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, parser(source));
+    // Link targets get percent-encoded: undo it for the braces of merge tags
+    // such as `[unsubscribe]({{unsubscribe_url}})`.
+    ammonia::clean(&html)
+        .replace("%7B%7B", "{{")
+        .replace("%7D%7D", "}}")
+}
+
+/// Renders Markdown as readable plain text: headings are underlined and links
+/// become numbered footnotes listed at the end.
+pub fn to_text(source: &str) -> String {
+    let mut renderer = TextRenderer::default();
+    for event in parser(source) {
+        renderer.handle(event);
+    }
+    renderer.finish()
+}
+
+#[derive(Default)]
+struct TextRenderer {
+    out: String,
+    footnotes: Vec<String>,
+    /// Where the output of each open heading, quote or code block starts.
+    block_starts: Vec<usize>,
+    links: Vec<String>,
+    lists: Vec<Option<u64>>,
+}
+
+impl TextRenderer {
+    fn handle(&mut self, event: Event) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) | Event::Code(text) => self.out.push_str(&text),
+            Event::SoftBreak | Event::HardBreak => self.out.push('\n'),
+            Event::Rule => {
+                self.out.push_str("----------");
+                self.end_block();
+            }
+            Event::TaskListMarker(done) => self.out.push_str(if done { "[x] " } else { "[ ] " }),
+            // Raw HTML has no sensible plain-text rendering.
+            _ => {}
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Heading { .. } | Tag::BlockQuote(_) | Tag::CodeBlock(_) => {
+                self.block_starts.push(self.out.len())
+            }
+            Tag::Link { dest_url, .. } | Tag::Image { dest_url, .. } => {
+                self.links.push(dest_url.into_string())
+            }
+            Tag::List(first_number) => {
+                if !self.lists.is_empty() {
+                    self.end_line();
+                }
+                self.lists.push(first_number)
+            }
+            Tag::Item => {
+                let depth = self.lists.len().saturating_sub(1);
+                self.out.push_str(&"  ".repeat(depth));
+                match self.lists.last_mut() {
+                    Some(Some(n)) => {
+                        self.out.push_str(&format!("{}. ", n));
+                        *n += 1;
+                    }
+                    _ => self.out.push_str("- "),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Paragraph => {
+                if self.lists.is_empty() {
+                    self.end_block()
+                } else {
+                    self.end_line()
+                }
+            }
+            TagEnd::Heading(level) => {
+                let start = self.block_starts.pop().unwrap_or_default();
+                let width = self.out[start..].graphemes(true).count();
+                let underline = if level == HeadingLevel::H1 { "=" } else { "-" };
+                self.out.push('\n');
+                self.out.push_str(&underline.repeat(width));
+                self.end_block();
+            }
+            TagEnd::BlockQuote(_) => {
+                let start = self.block_starts.pop().unwrap_or_default();
+                let quoted = self.out.split_off(start);
+                for line in quoted.trim_end().lines() {
+                    self.out.push_str("> ");
+                    self.out.push_str(line);
+                    self.out.push('\n');
+                }
+                self.end_block();
+            }
+            TagEnd::CodeBlock => {
+                let start = self.block_starts.pop().unwrap_or_default();
+                let code = self.out.split_off(start);
+                for line in code.trim_end().lines() {
+                    self.out.push_str("    ");
+                    self.out.push_str(line);
+                    self.out.push('\n');
+                }
+                self.end_block();
+            }
+            TagEnd::Link | TagEnd::Image => {
+                let Some(dest_url) = self.links.pop() else {
+                    return;
+                };
+                // Autolinks already show their target.
+                if !self.out.ends_with(&dest_url) {
+                    self.footnotes.push(dest_url);
+                    self.out.push_str(&format!(" [{}]", self.footnotes.len()));
+                }
+            }
+            TagEnd::Item => self.end_line(),
+            TagEnd::List(_) => {
+                self.lists.pop();
+                if self.lists.is_empty() {
+                    self.end_block();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn end_line(&mut self) {
+        if !self.out.is_empty() && !self.out.ends_with('\n') {
+            self.out.push('\n');
+        }
+    }
+
+    fn end_block(&mut self) {
+        let trimmed = self.out.trim_end_matches('\n').len();
+        self.out.truncate(trimmed);
+        self.out.push_str("\n\n");
+    }
+
+    fn finish(mut self) -> String {
+        let trimmed = self.out.trim_end().len();
+        self.out.truncate(trimmed);
+        if !self.footnotes.is_empty() {
+            self.out.push_str("\n\n");
+            for (i, dest_url) in self.footnotes.iter().enumerate() {
+                self.out.push_str(&format!("[{}] {}\n", i + 1, dest_url));
+            }
+            let trimmed = self.out.trim_end().len();
+            self.out.truncate(trimmed);
+        }
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{to_html, to_text};
+
+    #[test]
+    fn scripts_are_stripped_from_the_html() {
+        let html = to_html("Hello <script>alert('hi')</script>**world**");
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("<strong>world</strong>"));
+    }
+
+    #[test]
+    fn headings_are_underlined_in_the_text() {
+        let text = to_text("# Title\n\n## Section\n\nBody");
+        assert_eq!(text, "Title\n=====\n\nSection\n-------\n\nBody");
+    }
+
+    #[test]
+    fn links_become_footnotes_in_the_text() {
+        let text = to_text("Read [the docs](https://example.com/docs) and [the blog](https://example.com/blog).");
+        assert_eq!(
+            text,
+            "Read the docs [1] and the blog [2].\n\n\
+            [1] https://example.com/docs\n\
+            [2] https://example.com/blog"
+        );
+    }
+
+    #[test]
+    fn autolinks_do_not_get_a_footnote() {
+        let text = to_text("See <https://example.com>");
+        assert_eq!(text, "See https://example.com");
+    }
+
+    #[test]
+    fn lists_are_rendered_in_the_text() {
+        let text = to_text("Intro\n\n- one\n- two\n  1. nested\n\nOutro");
+        assert_eq!(text, "Intro\n\n- one\n- two\n  1. nested\n\nOutro");
+    }
+
+    #[test]
+    fn quotes_and_code_are_rendered_in_the_text() {
+        let text = to_text("> quoted\n\n```\nlet x = 1;\n```");
+        assert_eq!(text, "> quoted\n\n    let x = 1;");
+    }
+
+    #[test]
+    fn merge_tags_survive_rendering() {
+        let source = "Hi {{ name }}, [unsubscribe]({{unsubscribe_url}})";
+        let html = to_html(source);
+        let text = to_text(source);
+        assert!(html.contains("Hi {{ name }}"), "{}", html);
+        assert!(html.contains(r#"href="{{unsubscribe_url}}""#), "{}", html);
+        assert!(text.contains("[1] {{unsubscribe_url}}"), "{}", text);
+    }
+}
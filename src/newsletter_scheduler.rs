@@ -0,0 +1,85 @@
+use crate::configuration::Settings;
+use crate::routes::enqueue_delivery_tasks;
+use crate::shutdown::Shutdown;
+use crate::startup::get_connection_pool;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::time::Duration;
+use tracing::field::display;
+use tracing::Span;
+
+pub async fn run_scheduler_until_stopped(
+    configuration: Settings,
+    shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    scheduler_loop(connection_pool, configuration.worker.scheduler_poll_interval(), shutdown).await
+}
+
+async fn scheduler_loop(
+    pool: PgPool,
+    poll_interval: Duration,
+    shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
+    while !shutdown.is_requested() {
+        match try_release_scheduled_issue(&pool).await {
+            Ok(ReleaseOutcome::NothingDue) => {
+                shutdown.sleep(poll_interval).await;
+            }
+            Err(_) => {
+                shutdown.sleep(Duration::from_secs(1)).await;
+            }
+            Ok(ReleaseOutcome::IssueReleased) => {}
+        }
+    }
+    Ok(())
+}
+
+pub enum ReleaseOutcome {
+    IssueReleased,
+    NothingDue,
+}
+
+/// Fans out one scheduled issue whose `send_at` has passed.
+/// `FOR UPDATE SKIP LOCKED` lets several instances poll the same table
+/// without releasing an issue twice.
+#[tracing::instrument(
+    skip_all,
+    fields(newsletter_issue_id = tracing::field::Empty),
+    err
+)]
+pub async fn try_release_scheduled_issue(pool: &PgPool) -> Result<ReleaseOutcome, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let r = sqlx::query!(
+        r#"
+        SELECT newsletter_issue_id
+        FROM newsletter_issues
+        WHERE status = 'scheduled' AND send_at <= now()
+        ORDER BY send_at
+        FOR UPDATE
+        SKIP LOCKED
+        LIMIT 1
+        "#
+    )
+        .fetch_optional(&mut *transaction)
+        .await?;
+    let Some(r) = r else {
+        return Ok(ReleaseOutcome::NothingDue);
+    };
+    Span::current().record("newsletter_issue_id", display(r.newsletter_issue_id));
+    enqueue_delivery_tasks(&mut transaction, r.newsletter_issue_id)
+        .await
+        .context("Failed to enqueue delivery tasks")?;
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'published', published_at = now()::text
+        WHERE newsletter_issue_id = $1
+        "#,
+        r.newsletter_issue_id
+    )
+        .execute(&mut *transaction)
+        .await?;
+    transaction.commit().await?;
+    Ok(ReleaseOutcome::IssueReleased)
+}
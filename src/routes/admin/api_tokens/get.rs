@@ -0,0 +1,122 @@
+use crate::authentication::{ApiScope, CsrfToken, UserId};
+use crate::utils::{e500, html_escape};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+
+const DATE_FORMAT: &str = "%Y-%m-%d %H:%M UTC";
+
+pub async fn list_api_tokens(
+    flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_token.form_field();
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let tokens = crate::authentication::list_api_tokens(**user_id, &pool)
+        .await
+        .map_err(e500)?;
+    let mut rows_html = String::new();
+    for token in &tokens {
+        let status = if token.revoked_at.is_some() {
+            "revoked".to_string()
+        } else if token.is_active() {
+            format!(
+                r#"<form action="/admin/api-tokens/{id}/revoke" method="post">
+                    {csrf_field}
+                    <button type="submit">Revoke</button>
+                </form>"#,
+                id = token.api_token_id
+            )
+        } else {
+            "expired".to_string()
+        };
+        writeln!(
+            rows_html,
+            r#"<tr>
+            <td>{name}</td>
+            <td>{scopes}</td>
+            <td>{created_at}</td>
+            <td>{expires_at}</td>
+            <td>{last_used_at}</td>
+            <td>{status}</td>
+        </tr>"#,
+            name = html_escape(&token.name),
+            scopes = html_escape(&token.scopes.join(", ")),
+            created_at = token.created_at.format(DATE_FORMAT),
+            expires_at = format_optional_date(token.expires_at, "never"),
+            last_used_at = format_optional_date(token.last_used_at, "never used"),
+        )
+        .unwrap();
+    }
+    let mut scopes_html = String::new();
+    for scope in ApiScope::ALL {
+        writeln!(
+            scopes_html,
+            r#"<label><input type="checkbox" name="scopes" value="{scope}" checked> {scope}</label><br>"#,
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>API tokens</title>
+</head>
+<body>
+    {msg_html}
+    <p>API tokens let scripts call the JSON API as you, with <code>Authorization: Bearer &lt;token&gt;</code>.</p>
+    <table>
+        <tr>
+            <th>Name</th>
+            <th>Scopes</th>
+            <th>Created</th>
+            <th>Expires</th>
+            <th>Last used</th>
+            <th></th>
+        </tr>
+        {rows_html}
+    </table>
+    <p>Create a new token:</p>
+    <form action="/admin/api-tokens" method="post">
+        {csrf_field}
+        <label>Name
+            <input
+                type="text"
+                placeholder="What the token is used for"
+                name="name"
+            >
+        </label>
+        <br>
+        {scopes_html}
+        <label>Expires
+            <select name="expires_in_days">
+                <option value="30">in 30 days</option>
+                <option value="90" selected>in 90 days</option>
+                <option value="365">in a year</option>
+                <option value="">never</option>
+            </select>
+        </label>
+        <br>
+        <button type="submit">Create token</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+fn format_optional_date(date: Option<DateTime<Utc>>, none: &str) -> String {
+    date.map(|d| d.format(DATE_FORMAT).to_string())
+        .unwrap_or_else(|| none.to_owned())
+}
@@ -0,0 +1,136 @@
+use crate::audit::{record_audit_event, AuditAction};
+use crate::authentication::{ApiScope, Role, UserId};
+use crate::utils::{client_ip, e500, html_escape, see_other};
+use actix_web::http::header::{CacheControl, CacheDirective, ContentType};
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Tokens cannot be made to last longer than this, except by explicitly picking "never".
+const MAX_EXPIRY_DAYS: i64 = 3650;
+
+/// The form is read as raw pairs because `scopes` is a group of checkboxes,
+/// i.e. a repeated field, which `serde_urlencoded` cannot collect into a struct.
+#[tracing::instrument(name = "Create an API token", skip(form, pool, user_id, role, request))]
+pub async fn create_api_token(
+    form: web::Form<Vec<(String, String)>>,
+    user_id: web::ReqData<UserId>,
+    role: web::ReqData<Role>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut name = String::new();
+    let mut scopes = Vec::new();
+    let mut expires_in_days = String::new();
+    for (field, value) in form.into_inner() {
+        match field.as_str() {
+            "name" => name = value,
+            "scopes" => match ApiScope::try_from(value) {
+                Ok(scope) if !scopes.contains(&scope) => scopes.push(scope),
+                Ok(_) => {}
+                Err(_) => {
+                    FlashMessage::error("Please pick scopes from the list.").send();
+                    return Ok(see_other("/admin/api-tokens"));
+                }
+            },
+            "expires_in_days" => expires_in_days = value,
+            _ => {}
+        }
+    }
+    let name = name.trim();
+    if name.is_empty() {
+        FlashMessage::error("Please give the token a name.").send();
+        return Ok(see_other("/admin/api-tokens"));
+    }
+    if scopes.is_empty() {
+        FlashMessage::error("Please pick at least one scope.").send();
+        return Ok(see_other("/admin/api-tokens"));
+    }
+    let role = role.into_inner();
+    if let Some(scope) = scopes.iter().find(|s| role < s.required_role()) {
+        FlashMessage::error(format!(
+            "You need the {} role to grant the {} scope.",
+            scope.required_role(),
+            scope
+        ))
+        .send();
+        return Ok(see_other("/admin/api-tokens"));
+    }
+    let expires_at = match expires_in_days.trim() {
+        "" => None,
+        days => match days.parse::<i64>() {
+            Ok(days) if (1..=MAX_EXPIRY_DAYS).contains(&days) => {
+                Some(chrono::Utc::now() + chrono::Duration::days(days))
+            }
+            _ => {
+                FlashMessage::error("Please pick an expiry from the list.").send();
+                return Ok(see_other("/admin/api-tokens"));
+            }
+        },
+    };
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let (api_token_id, token) =
+        crate::authentication::create_api_token(**user_id, name, &scopes, expires_at, &mut *transaction)
+            .await
+            .map_err(e500)?;
+    record_audit_event(
+        &mut *transaction,
+        **user_id,
+        AuditAction::CreateApiToken,
+        Some(api_token_id),
+        client_ip(&request).as_deref(),
+    )
+        .await
+        .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>API token created</title>
+</head>
+<body>
+    <p>The API token {name} has been created.</p>
+    <p>Your new token is <code id="api-token">{token}</code></p>
+    <p>It will not be shown again: store it somewhere safe now.</p>
+    <p><a href="/admin/api-tokens">&lt;- Back</a></p>
+</body>
+</html>"#,
+            name = html_escape(name),
+        )))
+}
+
+#[tracing::instrument(name = "Revoke an API token", skip(pool, user_id, request))]
+pub async fn revoke_api_token(
+    api_token_id: web::Path<Uuid>,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let api_token_id = api_token_id.into_inner();
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let revoked = crate::authentication::revoke_api_token(**user_id, api_token_id, &mut *transaction)
+        .await
+        .map_err(e500)?;
+    if revoked {
+        record_audit_event(
+            &mut *transaction,
+            **user_id,
+            AuditAction::RevokeApiToken,
+            Some(api_token_id),
+            client_ip(&request).as_deref(),
+        )
+            .await
+            .map_err(e500)?;
+        transaction.commit().await.map_err(e500)?;
+        FlashMessage::info("The API token has been revoked.").send();
+    } else {
+        FlashMessage::error("The API token could not be found - it may have been revoked already.").send();
+    }
+    Ok(see_other("/admin/api-tokens"))
+}
@@ -0,0 +1,211 @@
+use crate::audit::{list_audit_entries, AuditAction, AuditEntry, AuditFilter};
+use crate::utils::{e400, e500, html_escape};
+use actix_web::http::header::{ContentDisposition, ContentType, DispositionParam, DispositionType};
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, NaiveDate, TimeDelta, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+
+const PAGE_SIZE: i64 = 50;
+
+/// The filter form. Every field can be left empty, which is what the browser submits
+/// for an untouched input.
+#[derive(serde::Deserialize, serde::Serialize, Default)]
+pub struct AuditQuery {
+    #[serde(default)]
+    actor: String,
+    #[serde(default)]
+    action: String,
+    /// First day to include, as `YYYY-MM-DD`.
+    #[serde(default)]
+    from: String,
+    /// Last day to include, as `YYYY-MM-DD`.
+    #[serde(default)]
+    to: String,
+    #[serde(default, skip_serializing)]
+    page: Option<i64>,
+}
+
+impl AuditQuery {
+    fn filter(&self) -> Result<AuditFilter, anyhow::Error> {
+        let actor = self.actor.trim();
+        let action = self.action.trim();
+        Ok(AuditFilter {
+            actor_username: (!actor.is_empty()).then(|| actor.to_owned()),
+            action: (!action.is_empty())
+                .then(|| AuditAction::try_from(action.to_owned()))
+                .transpose()?,
+            since: parse_day(&self.from)?,
+            until: parse_day(&self.to)?.map(|day| day + TimeDelta::days(1)),
+        })
+    }
+
+    /// The filter as a query string, to carry it over to the other pages and the export.
+    fn filter_query_string(&self) -> String {
+        serde_urlencoded::to_string(self).unwrap_or_default()
+    }
+}
+
+/// Midnight UTC at the start of `day`.
+fn parse_day(day: &str) -> Result<Option<DateTime<Utc>>, anyhow::Error> {
+    let day = day.trim();
+    if day.is_empty() {
+        return Ok(None);
+    }
+    let day = NaiveDate::parse_from_str(day, "%Y-%m-%d")
+        .with_context(|| format!("{} is not a valid date", day))?;
+    Ok(Some(day.and_hms_opt(0, 0, 0).unwrap().and_utc()))
+}
+
+pub async fn audit_log(
+    query: web::Query<AuditQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let filter = query.filter().map_err(e400)?;
+    let page = query.page.unwrap_or(1).max(1);
+    // One more than a page, to know whether there is a next one.
+    let mut entries = list_audit_entries(&pool, &filter, Some(PAGE_SIZE + 1), (page - 1) * PAGE_SIZE)
+        .await
+        .map_err(e500)?;
+    let has_next_page = entries.len() as i64 > PAGE_SIZE;
+    entries.truncate(PAGE_SIZE as usize);
+
+    let mut rows_html = String::new();
+    for entry in &entries {
+        writeln!(
+            rows_html,
+            r#"<tr>
+            <td>{occurred_at}</td>
+            <td>{actor}</td>
+            <td>{action}</td>
+            <td>{target_id}</td>
+            <td>{ip}</td>
+        </tr>"#,
+            occurred_at = entry.occurred_at.to_rfc3339(),
+            actor = html_escape(&actor(entry)),
+            action = html_escape(&entry.action),
+            target_id = entry.target_id.map(|id| id.to_string()).unwrap_or_else(|| "-".into()),
+            ip = html_escape(entry.ip.as_deref().unwrap_or("-")),
+        )
+        .unwrap();
+    }
+    if entries.is_empty() {
+        rows_html.push_str(r#"<tr><td colspan="5">No matching entries.</td></tr>"#);
+    }
+
+    let mut action_options = String::from(r#"<option value="">Any</option>"#);
+    for action in AuditAction::ALL {
+        let selected = if query.action == action.as_str() { " selected" } else { "" };
+        write!(action_options, r#"<option value="{action}"{selected}>{action}</option>"#).unwrap();
+    }
+    let filter_query_string = query.filter_query_string();
+    let mut pagination_html = String::new();
+    if page > 1 {
+        write!(
+            pagination_html,
+            r#"<a href="/admin/audit?{}&amp;page={}">&lt;- Newer</a> "#,
+            html_escape(&filter_query_string),
+            page - 1
+        )
+        .unwrap();
+    }
+    if has_next_page {
+        write!(
+            pagination_html,
+            r#"<a href="/admin/audit?{}&amp;page={}">Older -&gt;</a>"#,
+            html_escape(&filter_query_string),
+            page + 1
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Audit log</title>
+</head>
+<body>
+    <form action="/admin/audit" method="get">
+        <label>User <input type="text" name="actor" value="{actor}"></label>
+        <label>Action <select name="action">{action_options}</select></label>
+        <label>From <input type="date" name="from" value="{from}"></label>
+        <label>To <input type="date" name="to" value="{to}"></label>
+        <button type="submit">Filter</button>
+    </form>
+    <p><a href="/admin/audit/export?{export_query}">Export as CSV</a></p>
+    <table>
+        <tr>
+            <th>When</th>
+            <th>User</th>
+            <th>Action</th>
+            <th>Target</th>
+            <th>IP</th>
+        </tr>
+        {rows_html}
+    </table>
+    <p>{pagination_html}</p>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+            actor = html_escape(&query.actor),
+            from = html_escape(&query.from),
+            to = html_escape(&query.to),
+            export_query = html_escape(&filter_query_string),
+        )))
+}
+
+/// Every entry matching the filter, not just one page of them.
+pub async fn export_audit_log(
+    query: web::Query<AuditQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let filter = query.filter().map_err(e400)?;
+    let entries = list_audit_entries(&pool, &filter, None, 0).await.map_err(e500)?;
+    let mut csv = String::from("occurred_at,actor_id,actor,action,target_id,ip\r\n");
+    for entry in &entries {
+        let fields = [
+            entry.occurred_at.to_rfc3339(),
+            entry.actor_id.to_string(),
+            entry.actor_username.clone().unwrap_or_default(),
+            entry.action.clone(),
+            entry.target_id.map(|id| id.to_string()).unwrap_or_default(),
+            entry.ip.clone().unwrap_or_default(),
+        ];
+        let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+        csv.push_str(&fields.join(","));
+        csv.push_str("\r\n");
+    }
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename("audit-log.csv".into())],
+        })
+        .body(csv))
+}
+
+fn actor(entry: &AuditEntry) -> String {
+    entry
+        .actor_username
+        .clone()
+        .unwrap_or_else(|| format!("{} (deleted)", entry.actor_id))
+}
+
+/// Quotes the field when needed. Fields that a spreadsheet would evaluate as a formula
+/// are prefixed with `'`, since usernames are chosen by the users themselves.
+fn csv_field(field: &str) -> String {
+    let field = if field.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{}", field)
+    } else {
+        field.to_owned()
+    };
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field
+    }
+}
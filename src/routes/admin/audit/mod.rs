@@ -0,0 +1,2 @@
+mod get;
+pub use get::{audit_log, export_audit_log};
@@ -1,5 +1,5 @@
-use crate::authentication::UserId;
-use crate::utils::e500;
+use crate::authentication::{CsrfToken, Role, UserId};
+use crate::utils::{e500, html_escape};
 use actix_web::http::header::ContentType;
 use actix_web::{web, HttpResponse};
 use anyhow::Context;
@@ -9,9 +9,26 @@ use uuid::Uuid;
 
 pub async fn admin_dashboard(
     user_id: web::ReqData<UserId>,
+    role: web::ReqData<Role>,
+    csrf_token: web::ReqData<CsrfToken>,
     pool: web::Data<PgPool>
 ) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_token.form_field();
     let username = get_username(&user_id.0, &pool).await.map_err(e500)?;
+    let username = html_escape(&username);
+    let role = role.into_inner();
+    let manage_users = if role == Role::Owner {
+        r#"<li><a href="/admin/users">Manage users</a></li>
+        <li><a href="/admin/login-attempts">Failed logins</a></li>
+        <li><a href="/admin/audit">Audit log</a></li>"#
+    } else {
+        ""
+    };
+    let api_tokens = if role >= Role::Editor {
+        r#"<li><a href="/admin/api-tokens">API tokens</a></li>"#
+    } else {
+        ""
+    };
 
     Ok(HttpResponse::Ok().content_type(ContentType::html()).body(format!(
         r#"<!DOCTYPE html>
@@ -21,12 +38,21 @@ pub async fn admin_dashboard(
     <title>Admin dashboard</title>
 </head>
 <body>
-    <p>Welcome {username}!</p>
+    <p>Welcome {username}! You are signed in as {role}.</p>
     <p>Available actions:</p>
     <ol>
+        <li><a href="/admin/newsletters">Send a newsletter issue</a></li>
+        <li><a href="/admin/newsletters/drafts">Newsletter drafts</a></li>
+        <li><a href="/admin/newsletters/issues">Newsletter issues</a></li>
         <li><a href="/admin/password">Change password</a></li>
+        <li><a href="/admin/two-factor">Two-factor authentication</a></li>
+        <li><a href="/admin/sessions">Active sessions</a></li>
+        {api_tokens}
+        <li><a href="/admin/deliveries">Failed deliveries</a></li>
+        {manage_users}
         <li>
           <form name="logoutForm" action="/admin/logout" method="post">
+            {csrf_field}
             <input type="submit" value="Logout">
           </form>
         </li>
@@ -0,0 +1,111 @@
+use crate::authentication::CsrfToken;
+use crate::utils::{e500, html_escape};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+struct DeadLetter {
+    newsletter_issue_id: Uuid,
+    title: String,
+    subscriber_email: String,
+    n_attempts: i16,
+    last_error: String,
+    failed_at: DateTime<Utc>,
+}
+
+pub async fn failed_deliveries(
+    flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_token.form_field();
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let dead_letters = get_dead_letters(&pool).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for d in &dead_letters {
+        writeln!(
+            rows_html,
+            r#"<tr>
+            <td>{title}</td>
+            <td>{email}</td>
+            <td>{n_attempts}</td>
+            <td>{last_error}</td>
+            <td>{failed_at}</td>
+            <td>
+                <form action="/admin/deliveries/requeue" method="post">
+                    {csrf_field}
+                    <input hidden type="text" name="newsletter_issue_id" value="{issue_id}">
+                    <input hidden type="text" name="subscriber_email" value="{email}">
+                    <button type="submit">Re-queue</button>
+                </form>
+            </td>
+        </tr>"#,
+            title = html_escape(&d.title),
+            email = html_escape(&d.subscriber_email),
+            n_attempts = d.n_attempts,
+            last_error = html_escape(&d.last_error),
+            failed_at = d.failed_at.to_rfc3339(),
+            issue_id = d.newsletter_issue_id,
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Failed deliveries</title>
+</head>
+<body>
+    {msg_html}
+    <p>{count} deliveries gave up after exhausting their retries.</p>
+    <table>
+        <tr>
+            <th>Issue</th>
+            <th>Subscriber</th>
+            <th>Attempts</th>
+            <th>Last error</th>
+            <th>Failed at</th>
+            <th></th>
+        </tr>
+        {rows_html}
+    </table>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+            count = dead_letters.len(),
+        )))
+}
+
+#[tracing::instrument(name = "Get dead-lettered deliveries", skip(pool))]
+async fn get_dead_letters(pool: &PgPool) -> Result<Vec<DeadLetter>, anyhow::Error> {
+    let dead_letters = sqlx::query_as!(
+        DeadLetter,
+        r#"
+SELECT
+    d.newsletter_issue_id,
+    i.title,
+    d.subscriber_email,
+    d.n_attempts,
+    d.last_error,
+    d.failed_at
+FROM issue_delivery_dead_letters d
+JOIN newsletter_issues i USING (newsletter_issue_id)
+ORDER BY d.failed_at DESC
+"#
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to retrieve dead-lettered deliveries.")?;
+    Ok(dead_letters)
+}
@@ -0,0 +1,83 @@
+use crate::audit::{record_audit_event, AuditAction};
+use crate::authentication::UserId;
+use crate::utils::{client_ip, e500, html_escape, see_other};
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+}
+
+#[tracing::instrument(
+    name = "Re-queue a failed delivery",
+    skip(form, user_id, pool, request),
+    fields(newsletter_issue_id = %form.newsletter_issue_id)
+)]
+pub async fn requeue_failed_delivery(
+    form: web::Form<FormData>,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let requeued = requeue_dead_letter(&mut *transaction, form.newsletter_issue_id, &form.subscriber_email)
+        .await
+        .map_err(e500)?;
+    if requeued {
+        record_audit_event(
+            &mut *transaction,
+            **user_id,
+            AuditAction::RequeueDelivery,
+            Some(form.newsletter_issue_id),
+            client_ip(&request).as_deref(),
+        )
+            .await
+            .map_err(e500)?;
+        transaction.commit().await.map_err(e500)?;
+        FlashMessage::info(format!(
+            "The delivery to {} has been re-queued.",
+            html_escape(&form.subscriber_email)
+        ))
+        .send();
+    } else {
+        FlashMessage::error("The failed delivery could not be found.").send();
+    }
+    Ok(see_other("/admin/deliveries"))
+}
+
+/// Moves a dead letter back into `issue_delivery_queue` with a fresh retry budget.
+/// Returns `false` if there was no such dead letter, or if its subscriber has since been
+/// deleted: that dead letter has nowhere to go and stays where it is.
+async fn requeue_dead_letter(
+    executor: impl PgExecutor<'_>,
+    newsletter_issue_id: Uuid,
+    subscriber_email: &str,
+) -> Result<bool, anyhow::Error> {
+    let requeued = sqlx::query!(
+        r#"
+WITH revived AS (
+    DELETE FROM issue_delivery_dead_letters d
+    USING subscriptions s
+    WHERE d.newsletter_issue_id = $1
+      AND d.subscriber_email = $2
+      AND s.email = d.subscriber_email
+    RETURNING d.newsletter_issue_id, d.subscriber_email, s.id
+)
+INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email, subscriber_id)
+SELECT newsletter_issue_id, subscriber_email, id FROM revived
+ON CONFLICT DO NOTHING
+"#,
+        newsletter_issue_id,
+        subscriber_email
+    )
+        .execute(executor)
+        .await
+        .context("Failed to re-enqueue the dead-lettered delivery")?
+        .rows_affected();
+    Ok(requeued > 0)
+}
@@ -0,0 +1,78 @@
+use crate::authentication::{get_failed_login_counters, CsrfToken};
+use crate::utils::{e500, html_escape};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+pub async fn failed_logins(
+    flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_token.form_field();
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let now = chrono::Utc::now();
+    let counters = get_failed_login_counters(&pool).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for counter in &counters {
+        let locked_until = match counter.locked_until {
+            Some(locked_until) if locked_until > now => locked_until.to_rfc3339(),
+            _ => "-".to_string(),
+        };
+        writeln!(
+            rows_html,
+            r#"<tr>
+            <td>{scope}</td>
+            <td>{subject}</td>
+            <td>{failures}</td>
+            <td>{last_failure_at}</td>
+            <td>{locked_until}</td>
+            <td>
+                <form action="/admin/login-attempts/clear" method="post">
+                    {csrf_field}
+                    <input hidden type="text" name="scope" value="{scope}">
+                    <input hidden type="text" name="subject" value="{subject}">
+                    <button type="submit">Clear</button>
+                </form>
+            </td>
+        </tr>"#,
+            scope = counter.scope,
+            subject = html_escape(&counter.subject),
+            failures = counter.failures,
+            last_failure_at = counter.last_failure_at.to_rfc3339(),
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Failed logins</title>
+</head>
+<body>
+    {msg_html}
+    <p>Failed login attempts, per username and per client IP:</p>
+    <table>
+        <tr>
+            <th>Scope</th>
+            <th>Subject</th>
+            <th>Failures</th>
+            <th>Last failure</th>
+            <th>Locked until</th>
+            <th></th>
+        </tr>
+        {rows_html}
+    </table>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
@@ -0,0 +1,4 @@
+mod get;
+pub use get::failed_logins;
+mod post;
+pub use post::clear_failed_login;
@@ -0,0 +1,42 @@
+use crate::audit::{record_audit_event, AuditAction};
+use crate::authentication::{clear_failed_logins, ThrottleScope, UserId};
+use crate::utils::{client_ip, e400, e500, see_other};
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct ClearFormData {
+    scope: String,
+    subject: String,
+}
+
+/// Lifts a lockout early, e.g. for a colleague who fat-fingered their password.
+#[tracing::instrument(
+    name = "Clear failed login counter",
+    skip(form, user_id, pool, request),
+    fields(scope = %form.scope)
+)]
+pub async fn clear_failed_login(
+    form: web::Form<ClearFormData>,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let ClearFormData { scope, subject } = form.0;
+    let scope = ThrottleScope::try_from(scope).map_err(e400)?;
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    clear_failed_logins(scope, &subject, &mut *transaction).await.map_err(e500)?;
+    record_audit_event(
+        &mut *transaction,
+        **user_id,
+        AuditAction::ClearLoginAttempts,
+        None,
+        client_ip(&request).as_deref(),
+    )
+        .await
+        .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    FlashMessage::info("The failed login counter has been cleared.").send();
+    Ok(see_other("/admin/login-attempts"))
+}
@@ -1,10 +1,21 @@
+use crate::audit::{record_audit_event, AuditAction};
+use crate::authentication::UserId;
 use crate::session_state::TypedSession;
-use crate::utils::see_other;
-use actix_web::HttpResponse;
+use crate::utils::{client_ip, e500, see_other};
+use actix_web::{web, HttpRequest, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
 
-pub async fn log_out(session: TypedSession) -> Result<HttpResponse, actix_web::Error> {
+pub async fn log_out(
+        session: TypedSession,
+        user_id: web::ReqData<UserId>,
+        pool: web::Data<PgPool>,
+        request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+        record_audit_event(pool.get_ref(), user_id.0, AuditAction::LogOut, None, client_ip(&request).as_deref())
+                .await
+                .map_err(e500)?;
         session.log_out();
         FlashMessage::info("You have successfully logged out.").send();
         Ok(see_other("/login"))
-}
\ No newline at end of file
+}
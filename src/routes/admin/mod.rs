@@ -1,9 +1,23 @@
+mod api_tokens;
+mod audit;
 mod dashboard;
+mod deliveries;
 mod password;
+mod sessions;
+mod login_attempts;
 mod logout;
 mod newsletters;
+mod two_factor;
+mod users;
 
-pub use dashboard::admin_dashboard;
+pub use api_tokens::*;
+pub use audit::*;
+pub use dashboard::{admin_dashboard, get_username};
+pub use deliveries::*;
+pub use login_attempts::*;
 pub use logout::*;
 pub use newsletters::*;
 pub use password::*;
+pub use sessions::*;
+pub use two_factor::*;
+pub use users::*;
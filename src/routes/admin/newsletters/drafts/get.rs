@@ -0,0 +1,255 @@
+use crate::authentication::CsrfToken;
+use crate::utils::{e500, html_escape};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+struct Draft {
+    newsletter_issue_id: Uuid,
+    title: String,
+    text_content: String,
+    html_content: String,
+    markdown_content: Option<String>,
+    updated_at: DateTime<Utc>,
+}
+
+pub async fn list_drafts(
+    flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let drafts = get_drafts(&pool).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for draft in &drafts {
+        writeln!(
+            rows_html,
+            r#"<tr>
+            <td><a href="/admin/newsletters/drafts/{id}">{title}</a></td>
+            <td>{updated_at}</td>
+        </tr>"#,
+            id = draft.newsletter_issue_id,
+            title = html_escape(&draft.title),
+            updated_at = draft.updated_at.format("%Y-%m-%d %H:%M UTC"),
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Newsletter drafts</title>
+</head>
+<body>
+    {msg_html}
+    <table>
+        <tr>
+            <th>Title</th>
+            <th>Last edited</th>
+        </tr>
+        {rows_html}
+    </table>
+    <p>Start a new draft:</p>
+    {form_html}
+    <p><a href="/admin/newsletters">Publish a new issue</a></p>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+            form_html = draft_content_form(&csrf_token, "/admin/newsletters/drafts", "", "", "", ""),
+        )))
+}
+
+pub async fn draft_form(
+    newsletter_issue_id: web::Path<Uuid>,
+    flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(draft) = get_draft(&pool, *newsletter_issue_id).await.map_err(e500)? else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let idempotency_key = Uuid::new_v4().to_string();
+    let csrf_field = csrf_token.form_field();
+    let id = draft.newsletter_issue_id;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Edit draft</title>
+</head>
+<body>
+    {msg_html}
+    {form_html}
+    <p><a href="/admin/newsletters/drafts/{id}/preview">Preview</a></p>
+    <form action="/admin/newsletters/drafts/{id}/publish" method="post">
+        {csrf_field}
+        <label>Send at (UTC, leave empty to send now):<br>
+            <input
+                type="datetime-local"
+                name="send_at"
+            >
+        </label>
+        <br>
+        <input hidden type="text" name="idempotency_key" value="{idempotency_key}">
+        <button type="submit">Publish</button>
+    </form>
+    <form action="/admin/newsletters/drafts/{id}/delete" method="post">
+        {csrf_field}
+        <button type="submit">Delete draft</button>
+    </form>
+    <p><a href="/admin/newsletters/drafts">&lt;- Back</a></p>
+</body>
+</html>"#,
+            form_html = draft_content_form(
+                &csrf_token,
+                &format!("/admin/newsletters/drafts/{}", id),
+                &draft.title,
+                draft.markdown_content.as_deref().unwrap_or_default(),
+                &draft.text_content,
+                &draft.html_content,
+            ),
+        )))
+}
+
+/// Shows the draft as a subscriber would receive it.
+/// The HTML body is rendered inside a sandboxed iframe so it cannot affect the admin page.
+pub async fn preview_draft(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(draft) = get_draft(&pool, *newsletter_issue_id).await.map_err(e500)? else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Preview: {title}</title>
+</head>
+<body>
+    <h1>{title}</h1>
+    <h2>HTML</h2>
+    <iframe sandbox srcdoc="{html_content}" width="800" height="600"></iframe>
+    <h2>Plain text</h2>
+    <pre>{text_content}</pre>
+    <p><a href="/admin/newsletters/drafts/{id}">&lt;- Back</a></p>
+</body>
+</html>"#,
+            title = html_escape(&draft.title),
+            html_content = html_escape(&draft.html_content),
+            text_content = html_escape(&draft.text_content),
+            id = draft.newsletter_issue_id,
+        )))
+}
+
+fn draft_content_form(
+    csrf_token: &CsrfToken,
+    action: &str,
+    title: &str,
+    markdown_content: &str,
+    text_content: &str,
+    html_content: &str,
+) -> String {
+    format!(
+        r#"<form action="{action}" method="post">
+        {csrf_field}
+        <label>Title:<br>
+            <input
+                type="text"
+                placeholder="Enter the issue title"
+                name="title"
+                value="{title}"
+            >
+        </label>
+        <br>
+        <label>Markdown content (when filled in, the plain text and HTML content are generated from it):<br>
+            <textarea
+                placeholder="Enter the content in Markdown"
+                name="markdown_content"
+                rows="20"
+                cols="50"
+            >{markdown_content}</textarea>
+        </label>
+        <br>
+        <label>Plain text content:<br>
+            <textarea
+                placeholder="Enter the content in plain text"
+                name="text_content"
+                rows="20"
+                cols="50"
+            >{text_content}</textarea>
+        </label>
+        <br>
+        <label>HTML content:<br>
+            <textarea
+                placeholder="Enter the content in HTML format"
+                name="html_content"
+                rows="20"
+                cols="50"
+            >{html_content}</textarea>
+        </label>
+        <br>
+        <button type="submit">Save draft</button>
+    </form>"#,
+        csrf_field = csrf_token.form_field(),
+        title = html_escape(title),
+        markdown_content = html_escape(markdown_content),
+        text_content = html_escape(text_content),
+        html_content = html_escape(html_content),
+    )
+}
+
+#[tracing::instrument(name = "Get newsletter drafts", skip(pool))]
+async fn get_drafts(pool: &PgPool) -> Result<Vec<Draft>, anyhow::Error> {
+    let drafts = sqlx::query_as!(
+        Draft,
+        r#"
+SELECT newsletter_issue_id, title, text_content, html_content, markdown_content, updated_at
+FROM newsletter_issues
+WHERE status = 'draft'
+ORDER BY updated_at DESC
+"#
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to retrieve newsletter drafts.")?;
+    Ok(drafts)
+}
+
+#[tracing::instrument(name = "Get a newsletter draft", skip(pool))]
+async fn get_draft(pool: &PgPool, newsletter_issue_id: Uuid) -> Result<Option<Draft>, anyhow::Error> {
+    let draft = sqlx::query_as!(
+        Draft,
+        r#"
+SELECT newsletter_issue_id, title, text_content, html_content, markdown_content, updated_at
+FROM newsletter_issues
+WHERE newsletter_issue_id = $1 AND status = 'draft'
+"#,
+        newsletter_issue_id
+    )
+        .fetch_optional(pool)
+        .await
+        .context("Failed to retrieve the newsletter draft.")?;
+    Ok(draft)
+}
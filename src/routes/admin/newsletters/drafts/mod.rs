@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::{draft_form, list_drafts, preview_draft};
+pub use post::{create_draft, delete_draft, publish_draft, update_draft};
@@ -0,0 +1,227 @@
+use crate::audit::{record_audit_event, AuditAction};
+use crate::authentication::UserId;
+use crate::idempotency::Idempotency;
+use crate::routes::admin::newsletters::post::{
+    enqueue_delivery_tasks, future_send_at, insert_newsletter_issue, invalid_content_message,
+    success_message, validate_merge_tags, IssueContent,
+};
+use crate::utils::{client_ip, e400, e500, see_other};
+use actix_web::web::ReqData;
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::{Connection, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct DraftFormData {
+    title: String,
+    text_content: String,
+    html_content: String,
+    markdown_content: Option<String>,
+}
+
+impl DraftFormData {
+    fn content(self) -> (String, IssueContent) {
+        let content = IssueContent::from_form(self.text_content, self.html_content, self.markdown_content);
+        (self.title, content)
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct PublishDraftFormData {
+    send_at: Option<String>,
+}
+
+#[tracing::instrument(name = "Create a newsletter draft", skip(form, pool))]
+pub async fn create_draft(
+    form: web::Form<DraftFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id = Uuid::new_v4();
+    let (title, content) = form.0.content();
+    sqlx::query!(
+        r#"
+INSERT INTO newsletter_issues(
+                              newsletter_issue_id,
+                              title,
+                              text_content,
+                              html_content,
+                              markdown_content,
+                              status
+)
+VALUES ($1, $2, $3, $4, $5, 'draft')
+"#,
+        newsletter_issue_id,
+        title,
+        content.text_content,
+        content.html_content,
+        content.markdown_content
+    )
+        .execute(pool.get_ref())
+        .await
+        .context("Failed to store the newsletter draft")
+        .map_err(e500)?;
+    FlashMessage::info("The draft has been saved.").send();
+    Ok(see_other(&format!("/admin/newsletters/drafts/{}", newsletter_issue_id)))
+}
+
+#[tracing::instrument(name = "Update a newsletter draft", skip(form, pool))]
+pub async fn update_draft(
+    newsletter_issue_id: web::Path<Uuid>,
+    form: web::Form<DraftFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (title, content) = form.0.content();
+    let updated = sqlx::query!(
+        r#"
+UPDATE newsletter_issues
+SET
+    title = $2,
+    text_content = $3,
+    html_content = $4,
+    markdown_content = $5,
+    updated_at = now()
+WHERE newsletter_issue_id = $1 AND status = 'draft'
+"#,
+        *newsletter_issue_id,
+        title,
+        content.text_content,
+        content.html_content,
+        content.markdown_content
+    )
+        .execute(pool.get_ref())
+        .await
+        .context("Failed to update the newsletter draft")
+        .map_err(e500)?
+        .rows_affected();
+    if updated == 0 {
+        draft_not_found_message().send();
+        return Ok(see_other("/admin/newsletters/drafts"));
+    }
+    FlashMessage::info("The draft has been saved.").send();
+    Ok(see_other(&format!("/admin/newsletters/drafts/{}", newsletter_issue_id)))
+}
+
+#[tracing::instrument(name = "Delete a newsletter draft", skip(pool))]
+pub async fn delete_draft(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let deleted = sqlx::query!(
+        r#"
+DELETE FROM newsletter_issues
+WHERE newsletter_issue_id = $1 AND status = 'draft'
+"#,
+        *newsletter_issue_id
+    )
+        .execute(pool.get_ref())
+        .await
+        .context("Failed to delete the newsletter draft")
+        .map_err(e500)?
+        .rows_affected();
+    if deleted == 0 {
+        draft_not_found_message().send();
+    } else {
+        FlashMessage::info("The draft has been deleted.").send();
+    }
+    Ok(see_other("/admin/newsletters/drafts"))
+}
+
+#[tracing::instrument(
+    name = "Publish a newsletter draft",
+    skip(form, user_id, idempotency),
+    fields(user_id=%*user_id)
+)]
+pub async fn publish_draft(
+    newsletter_issue_id: web::Path<Uuid>,
+    form: web::Form<PublishDraftFormData>,
+    user_id: ReqData<UserId>,
+    idempotency: Idempotency,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let send_at = future_send_at(form.0.send_at.as_deref()).map_err(e400)?;
+    let mut transaction = idempotency.transaction().await.map_err(e500)?;
+    // A savepoint, so that a draft that turns out to be invalid can be put back.
+    let mut publishing = transaction
+        .begin()
+        .await
+        .context("Failed to start publishing the draft")
+        .map_err(e500)?;
+    let Some(draft) = take_draft(&mut publishing, *newsletter_issue_id)
+        .await
+        .map_err(e500)?
+    else {
+        idempotency.flash(draft_not_found_message());
+        return Ok(see_other("/admin/newsletters/drafts"));
+    };
+    let content = IssueContent {
+        text_content: draft.text_content,
+        html_content: draft.html_content,
+        markdown_content: draft.markdown_content,
+    };
+    if let Err(e) = validate_merge_tags(&draft.title, &content) {
+        // Dropping the savepoint keeps the draft.
+        drop(publishing);
+        idempotency.flash(invalid_content_message(&e));
+        return Ok(see_other(&format!("/admin/newsletters/drafts/{}", newsletter_issue_id)));
+    }
+    let issue_id = insert_newsletter_issue(&mut publishing, &draft.title, &content, send_at)
+        .await
+        .context("Failed to store newsletter issue details")
+        .map_err(e500)?;
+    if send_at.is_none() {
+        enqueue_delivery_tasks(&mut publishing, issue_id)
+            .await
+            .context("Failed to enqueue delivery tasks")
+            .map_err(e500)?;
+    }
+    record_audit_event(
+        &mut *publishing,
+        user_id.0,
+        AuditAction::publishing(send_at),
+        Some(issue_id),
+        client_ip(&request).as_deref(),
+    )
+        .await
+        .map_err(e500)?;
+    publishing
+        .commit()
+        .await
+        .context("Failed to publish the draft")
+        .map_err(e500)?;
+    idempotency.flash(success_message(send_at));
+    Ok(see_other("/admin/newsletters"))
+}
+
+fn draft_not_found_message() -> FlashMessage {
+    FlashMessage::error("The draft could not be found - it may have already been published.")
+}
+
+struct DraftContent {
+    title: String,
+    text_content: String,
+    html_content: String,
+    markdown_content: Option<String>,
+}
+
+/// Removes the draft so that publishing it goes through `insert_newsletter_issue`
+/// like any other issue. Rolled back together with the rest of the publish transaction.
+async fn take_draft(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<DraftContent>, anyhow::Error> {
+    let draft = sqlx::query_as!(
+        DraftContent,
+        r#"
+DELETE FROM newsletter_issues
+WHERE newsletter_issue_id = $1 AND status = 'draft'
+RETURNING title, text_content, html_content, markdown_content
+"#,
+        newsletter_issue_id
+    )
+        .fetch_optional(&mut **transaction)
+        .await
+        .context("Failed to retrieve the newsletter draft")?;
+    Ok(draft)
+}
@@ -1,12 +1,15 @@
+use crate::authentication::CsrfToken;
 use actix_web::http::header::ContentType;
-use actix_web::HttpResponse;
+use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
 use std::fmt::Write;
 use uuid::Uuid;
 
 pub async fn publish_newsletter_form(
     flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
 ) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_token.form_field();
     let mut msg_html = String::new();
     for m in flash_messages.iter() {
         writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
@@ -24,6 +27,7 @@ pub async fn publish_newsletter_form(
 <body>
     {msg_html}
     <form action="/admin/newsletters" method="post">
+        {csrf_field}
         <label>Title:<br>
             <input
                 type="text"
@@ -32,6 +36,15 @@ pub async fn publish_newsletter_form(
             >
         </label>
         <br>
+        <label>Markdown content (when filled in, the plain text and HTML content are generated from it):<br>
+            <textarea
+                placeholder="Enter the content in Markdown"
+                name="markdown_content"
+                rows="20"
+                cols="50"
+            ></textarea>
+        </label>
+        <br>
         <label>Plain text content:<br>
             <textarea
                 placeholder="Enter the content in plain text"
@@ -50,10 +63,19 @@ pub async fn publish_newsletter_form(
             ></textarea>
         </label>
         <br>
+        <label>Send at (UTC, leave empty to send now):<br>
+            <input
+                type="datetime-local"
+                name="send_at"
+            >
+        </label>
+        <br>
         <input hidden type="text" name="idempotency_key" value="{idempotency_key}">
         <button type="submit">Publish</button>
         
     </form>
+    <p><a href="/admin/newsletters/drafts">Drafts</a></p>
+    <p><a href="/admin/newsletters/issues">Scheduled and past issues</a></p>
     <p><a href="/admin/dashboard">&lt;- Back</a></p>
 </body>
 </html>"#,
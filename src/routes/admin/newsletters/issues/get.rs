@@ -0,0 +1,112 @@
+use crate::authentication::CsrfToken;
+use crate::routes::admin::newsletters::post::SEND_AT_DISPLAY_FORMAT;
+use crate::utils::{e500, html_escape};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+struct IssueSummary {
+    newsletter_issue_id: Uuid,
+    title: String,
+    status: String,
+    send_at: Option<DateTime<Utc>>,
+    published_at: Option<String>,
+}
+
+pub async fn newsletter_issues(
+    flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_token.form_field();
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let issues = get_issues(&pool).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for issue in &issues {
+        let actions = if issue.status == "scheduled" {
+            format!(
+                r#"<form action="/admin/newsletters/issues/{id}/reschedule" method="post">
+                    {csrf_field}
+                    <input type="datetime-local" name="send_at" required>
+                    <button type="submit">Reschedule</button>
+                </form>
+                <form action="/admin/newsletters/issues/{id}/cancel" method="post">
+                    {csrf_field}
+                    <button type="submit">Cancel</button>
+                </form>"#,
+                id = issue.newsletter_issue_id
+            )
+        } else {
+            String::new()
+        };
+        writeln!(
+            rows_html,
+            r#"<tr>
+            <td>{title}</td>
+            <td>{status}</td>
+            <td>{send_at}</td>
+            <td>{published_at}</td>
+            <td>{actions}</td>
+        </tr>"#,
+            title = html_escape(&issue.title),
+            status = issue.status,
+            send_at = issue
+                .send_at
+                .map(|s| s.format(SEND_AT_DISPLAY_FORMAT).to_string())
+                .unwrap_or_default(),
+            published_at = html_escape(issue.published_at.as_deref().unwrap_or_default()),
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Newsletter issues</title>
+</head>
+<body>
+    {msg_html}
+    <table>
+        <tr>
+            <th>Title</th>
+            <th>Status</th>
+            <th>Send at</th>
+            <th>Published at</th>
+            <th></th>
+        </tr>
+        {rows_html}
+    </table>
+    <p><a href="/admin/newsletters">Publish a new issue</a></p>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+#[tracing::instrument(name = "Get newsletter issues", skip(pool))]
+async fn get_issues(pool: &PgPool) -> Result<Vec<IssueSummary>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        IssueSummary,
+        r#"
+SELECT newsletter_issue_id, title, status, send_at, published_at
+FROM newsletter_issues
+WHERE status <> 'draft'
+ORDER BY COALESCE(send_at, published_at::timestamptz) DESC
+"#
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to retrieve newsletter issues.")?;
+    Ok(issues)
+}
@@ -0,0 +1,109 @@
+use crate::audit::{record_audit_event, AuditAction};
+use crate::authentication::UserId;
+use crate::routes::admin::newsletters::post::{parse_send_at, SEND_AT_DISPLAY_FORMAT};
+use crate::utils::{client_ip, e400, e500, see_other};
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct RescheduleFormData {
+    send_at: String,
+}
+
+#[tracing::instrument(name = "Reschedule a newsletter issue", skip(form, pool, user_id, request))]
+pub async fn reschedule_newsletter_issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    form: web::Form<RescheduleFormData>,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let send_at = parse_send_at(&form.send_at)
+        .map_err(e400)?
+        .ok_or_else(|| e400("A scheduled issue needs a date and time to be sent at"))?;
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let updated = update_scheduled_issue(&mut *transaction, *newsletter_issue_id, Some(send_at))
+        .await
+        .map_err(e500)?;
+    if updated {
+        record_audit_event(
+            &mut *transaction,
+            **user_id,
+            AuditAction::RescheduleNewsletter,
+            Some(*newsletter_issue_id),
+            client_ip(&request).as_deref(),
+        )
+            .await
+            .map_err(e500)?;
+        transaction.commit().await.map_err(e500)?;
+        FlashMessage::info(format!(
+            "The newsletter issue has been rescheduled for {}.",
+            send_at.format(SEND_AT_DISPLAY_FORMAT)
+        ))
+        .send();
+    } else {
+        not_scheduled_message().send();
+    }
+    Ok(see_other("/admin/newsletters/issues"))
+}
+
+#[tracing::instrument(name = "Cancel a newsletter issue", skip(pool, user_id, request))]
+pub async fn cancel_newsletter_issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let updated = update_scheduled_issue(&mut *transaction, *newsletter_issue_id, None)
+        .await
+        .map_err(e500)?;
+    if updated {
+        record_audit_event(
+            &mut *transaction,
+            **user_id,
+            AuditAction::CancelNewsletter,
+            Some(*newsletter_issue_id),
+            client_ip(&request).as_deref(),
+        )
+            .await
+            .map_err(e500)?;
+        transaction.commit().await.map_err(e500)?;
+        FlashMessage::info("The newsletter issue has been cancelled.").send();
+    } else {
+        not_scheduled_message().send();
+    }
+    Ok(see_other("/admin/newsletters/issues"))
+}
+
+fn not_scheduled_message() -> FlashMessage {
+    FlashMessage::error("The newsletter issue is no longer scheduled - it may have already gone out.")
+}
+
+/// Moves a still-scheduled issue to a new `send_at`, or cancels it when `send_at` is `None`.
+/// The `status = 'scheduled'` guard makes this a no-op once the scheduler has published it.
+async fn update_scheduled_issue(
+    executor: impl PgExecutor<'_>,
+    newsletter_issue_id: Uuid,
+    send_at: Option<DateTime<Utc>>,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+UPDATE newsletter_issues
+SET
+    send_at = COALESCE($2, send_at),
+    status = CASE WHEN $2::timestamptz IS NULL THEN 'cancelled' ELSE 'scheduled' END
+WHERE newsletter_issue_id = $1 AND status = 'scheduled'
+"#,
+        newsletter_issue_id,
+        send_at
+    )
+        .execute(executor)
+        .await
+        .context("Failed to update the scheduled newsletter issue")?;
+    Ok(result.rows_affected() > 0)
+}
@@ -1,5 +1,10 @@
+mod drafts;
 mod get;
+mod issues;
 mod post;
 
+pub use drafts::*;
 pub use get::publish_newsletter_form;
-pub use post::publish_newsletter;
+pub use issues::*;
+pub use post::{enqueue_delivery_tasks, publish_newsletter};
+pub(crate) use post::{insert_newsletter_issue, validate_merge_tags, IssueContent};
@@ -1,11 +1,15 @@
+use crate::audit::{record_audit_event, AuditAction};
 use crate::authentication::UserId;
-use crate::idempotency::{save_response, try_processing, IdempotencyKey, NextAction};
-use crate::utils::{e400, e500, see_other};
+use crate::idempotency::Idempotency;
+use crate::domain::IssueTemplate;
+use crate::markdown;
+use crate::utils::{client_ip, e400, e500, html_escape, see_other};
 use actix_web::web::ReqData;
-use actix_web::{web, HttpResponse};
+use actix_web::{web, HttpRequest, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
 use anyhow::Context;
-use sqlx::{Executor, PgPool, Postgres, Transaction};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use sqlx::{Executor, Postgres, Transaction};
 use uuid::Uuid;
 
 #[derive(serde::Deserialize)]
@@ -13,60 +17,143 @@ pub struct FormData {
     title: String,
     text_content: String,
     html_content: String,
-    idempotency_key: String,
+    markdown_content: Option<String>,
+    send_at: Option<String>,
+}
+
+/// The bodies of an issue: either rendered from the Markdown source the author wrote,
+/// or hand-written as plain text and HTML.
+pub(crate) struct IssueContent {
+    pub(crate) text_content: String,
+    pub(crate) html_content: String,
+    pub(crate) markdown_content: Option<String>,
+}
+
+impl IssueContent {
+    pub(crate) fn from_form(
+        text_content: String,
+        html_content: String,
+        markdown_content: Option<String>,
+    ) -> Self {
+        match markdown_content.filter(|m| !m.trim().is_empty()) {
+            Some(markdown_content) => Self {
+                text_content: markdown::to_text(&markdown_content),
+                html_content: markdown::to_html(&markdown_content),
+                markdown_content: Some(markdown_content),
+            },
+            None => Self {
+                text_content,
+                html_content,
+                markdown_content: None,
+            },
+        }
+    }
 }
 
 #[tracing::instrument(
     name = "Publish a newsletter issue",
-    skip(form, pool, user_id),
+    skip(form, user_id, idempotency, request),
     fields(user_id=%*user_id)
 )]
 pub async fn publish_newsletter(
     form: web::Form<FormData>,
     user_id: ReqData<UserId>,
-    pool: web::Data<PgPool>,
+    idempotency: Idempotency,
+    request: HttpRequest,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let user_id = user_id.into_inner();
-    let FormData { title, text_content, html_content, idempotency_key } = form.0;
-    let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
-    let mut transaction = match try_processing(&pool, &idempotency_key, *user_id)
-        .await
-        .map_err(e500)?
-    {
-        NextAction::StartProcessing(t) => { t }
-        NextAction::ReturnSavedResponse(response) => {
-            success_message().send();
-            return Ok(response);
-        }
-    };
-    let issue_id = insert_newsletter_issue(&mut transaction, &title, &text_content, &html_content)
+    let FormData { title, text_content, html_content, markdown_content, send_at } = form.0;
+    let content = IssueContent::from_form(text_content, html_content, markdown_content);
+    let send_at = future_send_at(send_at.as_deref()).map_err(e400)?;
+    if let Err(e) = validate_merge_tags(&title, &content) {
+        idempotency.flash(invalid_content_message(&e));
+        return Ok(see_other("/admin/newsletters"));
+    }
+    let mut transaction = idempotency.transaction().await.map_err(e500)?;
+    let issue_id = insert_newsletter_issue(&mut transaction, &title, &content, send_at)
         .await
         .context("Failed to store newsletter issue details")
         .map_err(e500)?;
 
-    enqueue_delivery_tasks(&mut transaction, issue_id)
-        .await
-        .context("Failed to enqueue delivery tasks")
-        .map_err(e500)?;
-    success_message().send();
-    let response = see_other("/admin/newsletters");
-    let response = save_response(transaction, &idempotency_key, *user_id, response)
+    if send_at.is_none() {
+        enqueue_delivery_tasks(&mut transaction, issue_id)
+            .await
+            .context("Failed to enqueue delivery tasks")
+            .map_err(e500)?;
+    }
+    record_audit_event(
+        &mut **transaction,
+        user_id.0,
+        AuditAction::publishing(send_at),
+        Some(issue_id),
+        client_ip(&request).as_deref(),
+    )
         .await
         .map_err(e500)?;
-    Ok(response)
+    idempotency.flash(success_message(send_at));
+    Ok(see_other("/admin/newsletters"))
 }
-fn success_message() -> FlashMessage {
-    FlashMessage::info(
-        "The newsletter issue has been accepted - \
-        emails will go out shortly.",
-    )
+pub(super) fn success_message(send_at: Option<DateTime<Utc>>) -> FlashMessage {
+    match send_at {
+        None => FlashMessage::info(
+            "The newsletter issue has been accepted - \
+            emails will go out shortly.",
+        ),
+        Some(send_at) => FlashMessage::info(format!(
+            "The newsletter issue has been scheduled for {}.",
+            send_at.format(SEND_AT_DISPLAY_FORMAT)
+        )),
+    }
 }
 
-async fn insert_newsletter_issue(
+/// Catches unknown or malformed merge tags before anything is sent, rather than per subscriber.
+pub(crate) fn validate_merge_tags(title: &str, content: &IssueContent) -> Result<(), String> {
+    for (field, content) in [
+        ("title", title),
+        ("plain text content", content.text_content.as_str()),
+        ("HTML content", content.html_content.as_str()),
+    ] {
+        IssueTemplate::parse(content).map_err(|e| format!("The {} is invalid: {}", field, e))?;
+    }
+    Ok(())
+}
+
+pub(super) fn invalid_content_message(e: &str) -> FlashMessage {
+    FlashMessage::error(format!(
+        "The newsletter issue could not be published. {}",
+        html_escape(e)
+    ))
+}
+
+pub(super) const SEND_AT_DISPLAY_FORMAT: &str = "%Y-%m-%d %H:%M UTC";
+
+/// Parses the value of a `datetime-local` input, interpreted as UTC.
+/// An empty value means "send now".
+pub(super) fn parse_send_at(send_at: &str) -> Result<Option<DateTime<Utc>>, anyhow::Error> {
+    let send_at = send_at.trim();
+    if send_at.is_empty() {
+        return Ok(None);
+    }
+    let send_at = NaiveDateTime::parse_from_str(send_at, "%Y-%m-%dT%H:%M")
+        .or_else(|_| NaiveDateTime::parse_from_str(send_at, "%Y-%m-%dT%H:%M:%S"))
+        .with_context(|| format!("{} is not a valid date and time", send_at))?;
+    Ok(Some(send_at.and_utc()))
+}
+
+/// Like `parse_send_at`, but a time that has already passed also means "send now".
+pub(super) fn future_send_at(send_at: Option<&str>) -> Result<Option<DateTime<Utc>>, anyhow::Error> {
+    let send_at = match send_at {
+        Some(send_at) => parse_send_at(send_at)?,
+        None => None,
+    };
+    Ok(send_at.filter(|send_at| *send_at > Utc::now()))
+}
+
+/// Issues with a `send_at` are stored as `scheduled`; the scheduler publishes them when due.
+pub(crate) async fn insert_newsletter_issue(
     transaction: &mut Transaction<'_, Postgres>,
     title: &str,
-    text_content: &str,
-    html_content: &str,
+    content: &IssueContent,
+    send_at: Option<DateTime<Utc>>,
 ) -> Result<Uuid, sqlx::Error> {
     let newsletter_issue_id = Uuid::new_v4();
     sqlx::query!(
@@ -76,14 +163,24 @@ INSERT INTO newsletter_issues(
                               title, 
                               text_content, 
                               html_content, 
-                              published_at
+                              markdown_content,
+                              published_at,
+                              status,
+                              send_at
+)
+VALUES (
+        $1, $2, $3, $4, $6,
+        CASE WHEN $5::timestamptz IS NULL THEN now()::text END,
+        CASE WHEN $5::timestamptz IS NULL THEN 'published' ELSE 'scheduled' END,
+        $5
 )
-VALUES ($1,$2,$3,$4,now())
 "#,
         newsletter_issue_id,
         title,
-        text_content,
-        html_content
+        content.text_content,
+        content.html_content,
+        send_at,
+        content.markdown_content
     )
         .execute(&mut **transaction)
         .await?;
@@ -93,7 +190,7 @@ VALUES ($1,$2,$3,$4,now())
 
 
 #[tracing::instrument(skip_all)]
-async fn enqueue_delivery_tasks(
+pub async fn enqueue_delivery_tasks(
     transaction: &mut Transaction<'_, Postgres>,
     newsletter_issue_id: Uuid,
 ) -> Result<(), sqlx::Error> {
@@ -101,11 +198,15 @@ async fn enqueue_delivery_tasks(
         r#"
         INSERT INTO issue_delivery_queue (
             newsletter_issue_id,
-            subscriber_email
+            subscriber_email,
+            subscriber_id
         )
-        SELECT $1, email
+        SELECT $1, email, id
         FROM subscriptions
-        WHERE status = 'confirmed'
+        WHERE status = 'confirmed' AND NOT EXISTS (
+            SELECT 1 FROM email_suppressions
+            WHERE lower(email_suppressions.email) = lower(subscriptions.email)
+        )
         "#,
         newsletter_issue_id,
     );
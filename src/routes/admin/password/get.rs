@@ -1,12 +1,17 @@
+use crate::authentication::CsrfToken;
 use actix_web::http::header::ContentType;
-use actix_web::{Error, HttpResponse};
+use actix_web::{web, Error, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
 use std::fmt::Write;
 
-pub async fn change_password_form(flash_message: IncomingFlashMessages) -> Result<HttpResponse, Error> {
+pub async fn change_password_form(
+    flash_message: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, Error> {
 
     // 这里后面可以我们自己尝试实现一个中间件,464 页课本.
 
+    let csrf_field = csrf_token.form_field();
     let mut msg_html = String::new();
 
     for m in flash_message.iter() {
@@ -26,6 +31,7 @@ pub async fn change_password_form(flash_message: IncomingFlashMessages) -> Resul
 <body>
 {msg_html}
     <form action="/admin/password" method="post">
+        {csrf_field}
         <label>Current password
             <input
                 type="password"
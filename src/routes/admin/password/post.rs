@@ -1,7 +1,10 @@
-use crate::authentication::{validate_credentials, AuthError, Credentials, UserId};
+use crate::audit::{record_audit_event, AuditAction};
+use crate::authentication::{validate_credentials, AuthError, Credentials, PasswordHashing, UserId};
 use crate::routes::admin::dashboard::get_username;
-use crate::utils::{e500, see_other};
-use actix_web::{web, HttpResponse};
+use crate::session_state::TypedSession;
+use crate::session_store::IndexedSessionStore;
+use crate::utils::{client_ip, e500, see_other};
+use actix_web::{web, HttpRequest, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
 use secrecy::{ExposeSecret, SecretString};
 use serde::Deserialize;
@@ -17,6 +20,10 @@ pub async fn change_password(
     form: web::Form<FormData>,
     user_id: web::ReqData<UserId>,
     pool: web::Data<PgPool>,
+    hashing: web::Data<PasswordHashing>,
+    session: TypedSession,
+    session_store: web::Data<IndexedSessionStore>,
+    request: HttpRequest,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
     if form.new_password.expose_secret() != form.new_password_check.expose_secret() {
@@ -33,18 +40,29 @@ pub async fn change_password(
         password: form.0.current_password,
     };
 
-    if let Err(e) = validate_credentials(credentials, &pool).await {
+    if let Err(e) = validate_credentials(credentials, &hashing, &pool).await {
         return match e {
             AuthError::InvalidCredentials(_) => {
                 FlashMessage::error("The current password is incorrect.").send();
                 Ok(see_other("/admin/password"))
             }
-            AuthError::InternalError(_) => { Err(e500(e).into()) }
+            AuthError::InternalError(_) => { Err(e500(e)) }
         };
     }
-    crate::authentication::change_password(user_id.0, form.0.new_password, &pool)
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    crate::authentication::change_password(user_id.0, form.0.new_password, &hashing, &mut *transaction)
         .await
         .map_err(e500)?;
+    record_audit_event(&mut *transaction, user_id.0, AuditAction::ChangePassword, None, client_ip(&request).as_deref())
+        .await
+        .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    // Whoever knew the old password is logged out, everywhere but here.
+    match session.get_metadata().map_err(e500)? {
+        Some(metadata) => session_store.revoke_other_user_sessions(user_id.0, metadata.id).await,
+        None => session_store.revoke_user_sessions(user_id.0).await,
+    }
+        .map_err(e500)?;
     FlashMessage::error("Your password has been changed.").send();
     Ok(see_other("/admin/password"))
 }
\ No newline at end of file
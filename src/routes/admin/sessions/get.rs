@@ -0,0 +1,88 @@
+use crate::authentication::{CsrfToken, UserId};
+use crate::session_state::TypedSession;
+use crate::session_store::IndexedSessionStore;
+use crate::utils::{e500, html_escape};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use std::fmt::Write;
+
+const DATE_FORMAT: &str = "%Y-%m-%d %H:%M UTC";
+
+pub async fn list_sessions(
+    flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+    session_store: web::Data<IndexedSessionStore>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_token.form_field();
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let current_session_id = session.get_metadata().map_err(e500)?.map(|m| m.id);
+    let sessions = session_store
+        .list_user_sessions(user_id.into_inner().0)
+        .await
+        .map_err(e500)?;
+    let mut rows_html = String::new();
+    for s in &sessions {
+        let action = if Some(s.id) == current_session_id {
+            "(this session)".to_string()
+        } else {
+            format!(
+                r#"<form action="/admin/sessions/{id}/revoke" method="post">
+                    {csrf_field}
+                    <button type="submit">Revoke</button>
+                </form>"#,
+                id = s.id
+            )
+        };
+        writeln!(
+            rows_html,
+            r#"<tr>
+            <td>{created_at}</td>
+            <td>{last_seen_at}</td>
+            <td>{ip}</td>
+            <td>{user_agent}</td>
+            <td>{action}</td>
+        </tr>"#,
+            created_at = s.created_at.format(DATE_FORMAT),
+            last_seen_at = s.last_seen_at.format(DATE_FORMAT),
+            ip = html_escape(s.ip.as_deref().unwrap_or("unknown")),
+            user_agent = html_escape(s.user_agent.as_deref().unwrap_or("unknown")),
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Active sessions</title>
+</head>
+<body>
+    {msg_html}
+    <p>You are logged in from:</p>
+    <table>
+        <tr>
+            <th>Signed in</th>
+            <th>Last seen</th>
+            <th>IP address</th>
+            <th>Browser</th>
+            <th></th>
+        </tr>
+        {rows_html}
+    </table>
+    <form action="/admin/sessions/revoke-all" method="post">
+        {csrf_field}
+        <button type="submit">Log out everywhere</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
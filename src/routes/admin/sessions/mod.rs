@@ -0,0 +1,4 @@
+mod get;
+pub use get::list_sessions;
+mod post;
+pub use post::{revoke_all_sessions, revoke_session};
@@ -0,0 +1,68 @@
+use crate::audit::{record_audit_event, AuditAction};
+use crate::authentication::UserId;
+use crate::session_state::TypedSession;
+use crate::session_store::IndexedSessionStore;
+use crate::utils::{client_ip, e500, see_other};
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Sessions live in Redis, so unlike database changes their revocation cannot share a
+/// transaction with its audit entry: the entry is recorded once the session is gone.
+#[tracing::instrument(name = "Revoke a session", skip(user_id, session, session_store, pool, request))]
+pub async fn revoke_session(
+    session_id: web::Path<Uuid>,
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+    session_store: web::Data<IndexedSessionStore>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner().0;
+    let session_id = session_id.into_inner();
+    if session.get_metadata().map_err(e500)?.is_some_and(|m| m.id == session_id) {
+        FlashMessage::error("Use the logout button to end the session you are using.").send();
+        return Ok(see_other("/admin/sessions"));
+    }
+    let revoked = session_store
+        .revoke_user_session(user_id, session_id)
+        .await
+        .map_err(e500)?;
+    if revoked {
+        record_audit_event(
+            pool.get_ref(),
+            user_id,
+            AuditAction::RevokeSession,
+            Some(session_id),
+            client_ip(&request).as_deref(),
+        )
+            .await
+            .map_err(e500)?;
+        FlashMessage::info("The session has been revoked.").send();
+    } else {
+        FlashMessage::error("The session could not be found - it may have expired already.").send();
+    }
+    Ok(see_other("/admin/sessions"))
+}
+
+#[tracing::instrument(name = "Log out everywhere", skip(user_id, session, session_store, pool, request))]
+pub async fn revoke_all_sessions(
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+    session_store: web::Data<IndexedSessionStore>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner().0;
+    session_store
+        .revoke_user_sessions(user_id)
+        .await
+        .map_err(e500)?;
+    record_audit_event(pool.get_ref(), user_id, AuditAction::RevokeAllSessions, None, client_ip(&request).as_deref())
+        .await
+        .map_err(e500)?;
+    session.log_out();
+    FlashMessage::info("You have been logged out of every session.").send();
+    Ok(see_other("/login"))
+}
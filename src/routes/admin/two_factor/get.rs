@@ -0,0 +1,93 @@
+use crate::authentication::{generate_totp_secret, get_totp_secret, otpauth_uri, remaining_recovery_codes, CsrfToken, UserId};
+use crate::routes::admin::dashboard::get_username;
+use crate::session_state::TypedSession;
+use crate::utils::{e500, html_escape};
+use actix_web::http::header::{CacheControl, CacheDirective, ContentType};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+pub async fn two_factor_settings(
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+    flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_token.form_field();
+    let user_id = user_id.into_inner().0;
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap()
+    }
+
+    let body_html = if get_totp_secret(user_id, &pool).await.map_err(e500)?.is_some() {
+        let remaining = remaining_recovery_codes(user_id, &pool).await.map_err(e500)?;
+        format!(
+            r#"<p>Two-factor authentication is <b>enabled</b>.</p>
+    <p>You have {remaining} unused recovery codes left.</p>
+    <form action="/admin/two-factor/disable" method="post">
+        {csrf_field}
+        <label>Current password
+            <input
+                type="password"
+                placeholder="Enter current password"
+                name="current_password"
+            >
+        </label>
+        <button type="submit">Disable two-factor authentication</button>
+    </form>"#
+        )
+    } else {
+        // Keep showing the same secret until it is confirmed, so that reloading the page
+        // does not invalidate what was already added to the authenticator app.
+        let secret = match session.get_pending_totp_secret().map_err(e500)? {
+            Some(secret) => secret,
+            None => {
+                let secret = generate_totp_secret();
+                session.insert_pending_totp_secret(&secret).map_err(e500)?;
+                secret
+            }
+        };
+        let username = get_username(&user_id, &pool).await.map_err(e500)?;
+        let uri = html_escape(&otpauth_uri(&username, &secret));
+        format!(
+            r#"<p>Two-factor authentication is <b>disabled</b>.</p>
+    <p>To enable it, add this account to your authenticator app by scanning or opening the link below:</p>
+    <p><a href="{uri}"><code id="otpauth-uri">{uri}</code></a></p>
+    <p>Or enter the secret manually: <code id="totp-secret">{secret}</code></p>
+    <form action="/admin/two-factor/enable" method="post">
+        {csrf_field}
+        <label>Code from the app
+            <input
+                type="text"
+                inputmode="numeric"
+                autocomplete="one-time-code"
+                placeholder="6-digit code"
+                name="code"
+            >
+        </label>
+        <button type="submit">Enable two-factor authentication</button>
+    </form>"#
+        )
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Two-factor authentication</title>
+</head>
+<body>
+    {msg_html}
+    {body_html}
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
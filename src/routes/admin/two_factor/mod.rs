@@ -0,0 +1,4 @@
+mod get;
+pub use get::two_factor_settings;
+mod post;
+pub use post::{enroll_two_factor, unenroll_two_factor};
@@ -0,0 +1,113 @@
+use crate::audit::{record_audit_event, AuditAction};
+use crate::authentication::{
+    disable_two_factor, enable_two_factor, validate_credentials, verify_enrollment_code, AuthError,
+    Credentials, PasswordHashing, UserId,
+};
+use crate::routes::admin::dashboard::get_username;
+use crate::session_state::TypedSession;
+use crate::utils::{client_ip, e500, see_other};
+use actix_web::http::header::{CacheControl, CacheDirective, ContentType};
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use secrecy::SecretString;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+#[derive(serde::Deserialize)]
+pub struct EnrollFormData {
+    code: String,
+}
+
+/// Stores the pending secret once the user proves their app generates matching codes,
+/// then shows the recovery codes - the only time they are ever displayed.
+#[tracing::instrument(name = "Enroll in two-factor authentication", skip(form, pool, session, user_id, request))]
+pub async fn enroll_two_factor(
+    form: web::Form<EnrollFormData>,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner().0;
+    let Some(secret) = session.get_pending_totp_secret().map_err(e500)? else {
+        FlashMessage::error("Your enrollment has expired, please try again.").send();
+        return Ok(see_other("/admin/two-factor"));
+    };
+    if !verify_enrollment_code(&secret, &form.code) {
+        FlashMessage::error("The code does not match - check the clock of your device and try again.").send();
+        return Ok(see_other("/admin/two-factor"));
+    }
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let recovery_codes = enable_two_factor(user_id, &secret, &mut transaction)
+        .await
+        .map_err(e500)?;
+    record_audit_event(&mut *transaction, user_id, AuditAction::EnableTwoFactor, None, client_ip(&request).as_deref())
+        .await
+        .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    session.remove_pending_totp_secret();
+
+    let mut codes_html = String::new();
+    for code in &recovery_codes {
+        writeln!(codes_html, r#"<li><code class="recovery-code">{}</code></li>"#, code).unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Two-factor authentication enabled</title>
+</head>
+<body>
+    <p>Two-factor authentication is now enabled.</p>
+    <p>Keep these recovery codes somewhere safe. Each of them can be used once to log in without your authenticator app:</p>
+    <ul>
+        {codes_html}
+    </ul>
+    <p>They will not be shown again.</p>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+#[derive(serde::Deserialize)]
+pub struct UnenrollFormData {
+    current_password: SecretString,
+}
+
+#[tracing::instrument(name = "Disable two-factor authentication", skip(form, pool, user_id, hashing, request))]
+pub async fn unenroll_two_factor(
+    form: web::Form<UnenrollFormData>,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    hashing: web::Data<PasswordHashing>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner().0;
+    let username = get_username(&user_id, &pool).await.map_err(e500)?;
+    let credentials = Credentials {
+        username,
+        password: form.0.current_password,
+    };
+    if let Err(e) = validate_credentials(credentials, &hashing, &pool).await {
+        return match e {
+            AuthError::InvalidCredentials(_) => {
+                FlashMessage::error("The current password is incorrect.").send();
+                Ok(see_other("/admin/two-factor"))
+            }
+            AuthError::InternalError(_) => Err(e500(e)),
+        };
+    }
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    disable_two_factor(user_id, &mut transaction).await.map_err(e500)?;
+    record_audit_event(&mut *transaction, user_id, AuditAction::DisableTwoFactor, None, client_ip(&request).as_deref())
+        .await
+        .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    FlashMessage::info("Two-factor authentication has been disabled.").send();
+    Ok(see_other("/admin/two-factor"))
+}
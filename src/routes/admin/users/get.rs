@@ -0,0 +1,133 @@
+use crate::authentication::{CsrfToken, UserId};
+use crate::utils::{e500, html_escape};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+struct UserSummary {
+    user_id: Uuid,
+    username: String,
+    role: String,
+    is_active: bool,
+}
+
+pub async fn list_users(
+    flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_token.form_field();
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let users = get_users(&pool).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for user in &users {
+        // Owners cannot lock themselves out.
+        let actions = if user.user_id == **user_id {
+            "(you)".to_string()
+        } else {
+            let (toggle_action, toggle_label) = if user.is_active {
+                ("deactivate", "Deactivate")
+            } else {
+                ("activate", "Reactivate")
+            };
+            format!(
+                r#"<form action="/admin/users/{id}/{toggle_action}" method="post">
+                    {csrf_field}
+                    <button type="submit">{toggle_label}</button>
+                </form>
+                <form action="/admin/users/{id}/delete" method="post">
+                    {csrf_field}
+                    <button type="submit">Delete</button>
+                </form>"#,
+                id = user.user_id
+            )
+        };
+        writeln!(
+            rows_html,
+            r#"<tr>
+            <td>{username}</td>
+            <td>{role}</td>
+            <td>{status}</td>
+            <td>{actions}</td>
+        </tr>"#,
+            username = html_escape(&user.username),
+            role = user.role,
+            status = if user.is_active { "active" } else { "deactivated" },
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Admin users</title>
+</head>
+<body>
+    {msg_html}
+    <table>
+        <tr>
+            <th>Username</th>
+            <th>Role</th>
+            <th>Status</th>
+            <th></th>
+        </tr>
+        {rows_html}
+    </table>
+    <p>Invite a new user:</p>
+    <form action="/admin/users" method="post">
+        {csrf_field}
+        <label>Username
+            <input
+                type="text"
+                placeholder="Enter their username"
+                name="username"
+            >
+        </label>
+        <label>Email (used for password resets)
+            <input
+                type="email"
+                placeholder="Enter their email address"
+                name="email"
+            >
+        </label>
+        <label>Role
+            <select name="role">
+                <option value="viewer">Viewer - read-only access</option>
+                <option value="editor">Editor - can draft and publish issues</option>
+                <option value="owner">Owner - can also manage users</option>
+            </select>
+        </label>
+        <button type="submit">Invite</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+#[tracing::instrument(name = "Get admin users", skip(pool))]
+async fn get_users(pool: &PgPool) -> Result<Vec<UserSummary>, anyhow::Error> {
+    let users = sqlx::query_as!(
+        UserSummary,
+        r#"
+SELECT user_id, username, role, is_active
+FROM users
+ORDER BY username
+"#
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to retrieve admin users.")?;
+    Ok(users)
+}
@@ -0,0 +1,236 @@
+use crate::audit::{record_audit_event, AuditAction};
+use crate::authentication::{create_user, PasswordHashing, Role, UserId};
+use crate::domain::SubscriberEmail;
+use crate::utils::{client_ip, e500, html_escape, see_other};
+use actix_web::http::header::{CacheControl, CacheDirective, ContentType};
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use rand::Rng;
+use secrecy::SecretString;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct InviteFormData {
+    username: String,
+    #[serde(default)]
+    email: Option<String>,
+    role: String,
+}
+
+/// Creates the account with a random temporary password, shown only in this response.
+/// The owner passes it on and the new user changes it from `/admin/password`.
+#[tracing::instrument(
+    name = "Invite an admin user",
+    skip(form, user_id, pool, hashing, request),
+    fields(username = %form.username)
+)]
+pub async fn invite_user(
+    form: web::Form<InviteFormData>,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    hashing: web::Data<PasswordHashing>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let InviteFormData { username, email, role } = form.0;
+    let username = username.trim();
+    if username.is_empty() {
+        FlashMessage::error("The username cannot be empty.").send();
+        return Ok(see_other("/admin/users"));
+    }
+    // The email address is optional, but without it the user cannot reset a forgotten password.
+    let email = match email.as_deref().map(str::trim).filter(|e| !e.is_empty()) {
+        None => None,
+        Some(email) => match SubscriberEmail::parse(email.to_owned()) {
+            Ok(email) => Some(email),
+            Err(_) => {
+                FlashMessage::error("Please enter a valid email address.").send();
+                return Ok(see_other("/admin/users"));
+            }
+        },
+    };
+    let Ok(role) = Role::try_from(role) else {
+        FlashMessage::error("Please pick one of the available roles.").send();
+        return Ok(see_other("/admin/users"));
+    };
+    let password = generate_temporary_password();
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let created = create_user(
+        username,
+        email.as_ref().map(AsRef::as_ref),
+        SecretString::from(password.clone()),
+        role,
+        &hashing,
+        &mut *transaction,
+    )
+        .await
+        .map_err(e500)?;
+    let Some(invited_user_id) = created else {
+        let message = match email {
+            None => format!("The username {} is already taken.", html_escape(username)),
+            Some(_) => format!(
+                "The username {} or the email address is already taken.",
+                html_escape(username)
+            ),
+        };
+        FlashMessage::error(message).send();
+        return Ok(see_other("/admin/users"));
+    };
+    record_audit_event(
+        &mut *transaction,
+        **user_id,
+        AuditAction::InviteUser,
+        Some(invited_user_id),
+        client_ip(&request).as_deref(),
+    )
+        .await
+        .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>User invited</title>
+</head>
+<body>
+    <p>{username} has been invited as {role}.</p>
+    <p>Their temporary password is <code id="temporary-password">{password}</code></p>
+    <p>It will not be shown again: pass it on and ask them to change it after logging in.</p>
+    <p><a href="/admin/users">&lt;- Back</a></p>
+</body>
+</html>"#,
+            username = html_escape(username),
+        )))
+}
+
+#[tracing::instrument(name = "Deactivate an admin user", skip(pool, user_id, request))]
+pub async fn deactivate_user(
+    target_user_id: web::Path<Uuid>,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    set_user_active(*target_user_id, **user_id, false, &pool, &request).await
+}
+
+#[tracing::instrument(name = "Reactivate an admin user", skip(pool, user_id, request))]
+pub async fn activate_user(
+    target_user_id: web::Path<Uuid>,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    set_user_active(*target_user_id, **user_id, true, &pool, &request).await
+}
+
+async fn set_user_active(
+    target_user_id: Uuid,
+    user_id: Uuid,
+    is_active: bool,
+    pool: &PgPool,
+    request: &HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    if target_user_id == user_id {
+        FlashMessage::error("You cannot deactivate your own account.").send();
+        return Ok(see_other("/admin/users"));
+    }
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let updated = sqlx::query!(
+        r#"UPDATE users SET is_active = $2 WHERE user_id = $1"#,
+        target_user_id,
+        is_active
+    )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to update the user's status")
+        .map_err(e500)?
+        .rows_affected();
+    if updated == 0 {
+        user_not_found_message().send();
+        return Ok(see_other("/admin/users"));
+    }
+    let action = if is_active { AuditAction::ActivateUser } else { AuditAction::DeactivateUser };
+    record_audit_event(
+        &mut *transaction,
+        user_id,
+        action,
+        Some(target_user_id),
+        client_ip(request).as_deref(),
+    )
+        .await
+        .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    if is_active {
+        FlashMessage::info("The user has been reactivated.").send();
+    } else {
+        FlashMessage::info("The user has been deactivated.").send();
+    }
+    Ok(see_other("/admin/users"))
+}
+
+#[tracing::instrument(name = "Delete an admin user", skip(pool, user_id, request))]
+pub async fn delete_user(
+    target_user_id: web::Path<Uuid>,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    if *target_user_id == **user_id {
+        FlashMessage::error("You cannot delete your own account.").send();
+        return Ok(see_other("/admin/users"));
+    }
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let deleted = delete_user_rows(&mut transaction, *target_user_id)
+        .await
+        .map_err(e500)?;
+    if deleted {
+        record_audit_event(
+            &mut *transaction,
+            **user_id,
+            AuditAction::DeleteUser,
+            Some(*target_user_id),
+            client_ip(&request).as_deref(),
+        )
+            .await
+            .map_err(e500)?;
+        transaction.commit().await.map_err(e500)?;
+        FlashMessage::info("The user has been deleted.").send();
+    } else {
+        user_not_found_message().send();
+    }
+    Ok(see_other("/admin/users"))
+}
+
+fn user_not_found_message() -> FlashMessage {
+    FlashMessage::error("The user could not be found.")
+}
+
+/// Saved idempotent responses reference their user, so they have to go first.
+async fn delete_user_rows(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    sqlx::query!(r#"DELETE FROM idempotency WHERE user_id = $1"#, user_id)
+        .execute(&mut **transaction)
+        .await
+        .context("Failed to delete the user's idempotency records")?;
+    let deleted = sqlx::query!(r#"DELETE FROM users WHERE user_id = $1"#, user_id)
+        .execute(&mut **transaction)
+        .await
+        .context("Failed to delete the user")?
+        .rows_affected();
+    Ok(deleted > 0)
+}
+
+fn generate_temporary_password() -> String {
+    let mut rng = rand::thread_rng();
+    std::iter::repeat_with(|| rng.sample(rand::distributions::Alphanumeric))
+        .map(char::from)
+        .take(20)
+        .collect()
+}
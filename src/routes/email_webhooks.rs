@@ -0,0 +1,158 @@
+use crate::routes::{basic_authentication, error_chain_fmt};
+use crate::startup::WebhookSecret;
+use actix_web::body::BoxBody;
+use actix_web::http::header::HeaderValue;
+use actix_web::http::{header, StatusCode};
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::Utc;
+use secrecy::ExposeSecret;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::fmt::{Debug, Formatter};
+
+/// The fields we need from Postmark's bounce and spam complaint webhook payloads.
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct PostmarkEvent {
+    record_type: String,
+    #[serde(rename = "Type")]
+    kind: Option<String>,
+    email: String,
+    description: Option<String>,
+}
+
+/// Why an address ended up on the suppression list.
+/// It doubles as the status of the matching `subscriptions` row.
+#[derive(Debug, Clone, Copy)]
+enum SuppressionReason {
+    Bounced,
+    Complained,
+}
+
+impl SuppressionReason {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SuppressionReason::Bounced => "bounced",
+            SuppressionReason::Complained => "complained",
+        }
+    }
+}
+
+impl PostmarkEvent {
+    /// Soft bounces and other transient failures are left to the delivery retries.
+    fn suppression_reason(&self) -> Option<SuppressionReason> {
+        match (self.record_type.as_str(), self.kind.as_deref()) {
+            ("Bounce", Some("HardBounce" | "BadEmailAddress")) => Some(SuppressionReason::Bounced),
+            ("SpamComplaint", _) => Some(SuppressionReason::Complained),
+            _ => None,
+        }
+    }
+}
+
+#[derive(thiserror::Error)]
+pub enum WebhookError {
+    #[error("Authentication failed")]
+    AuthError(#[source] anyhow::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl Debug for WebhookError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for WebhookError {
+    fn error_response(&self) -> HttpResponse<BoxBody> {
+        match self {
+            WebhookError::UnexpectedError(_) => HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR),
+            WebhookError::AuthError(_) => {
+                let mut response = HttpResponse::new(StatusCode::UNAUTHORIZED);
+                let header_value = HeaderValue::from_str(r#"Basic realm="webhooks""#).unwrap();
+                response
+                    .headers_mut()
+                    .insert(header::WWW_AUTHENTICATE, header_value);
+                response
+            }
+        }
+    }
+}
+
+#[tracing::instrument(
+    name = "Handle a Postmark webhook event",
+    skip(event, pool, webhook_secret, request),
+    fields(
+        record_type = %event.record_type,
+        subscriber_email = %event.email
+    )
+)]
+pub async fn postmark_webhook(
+    event: web::Json<PostmarkEvent>,
+    pool: web::Data<PgPool>,
+    webhook_secret: web::Data<WebhookSecret>,
+    request: HttpRequest,
+) -> Result<HttpResponse, WebhookError> {
+    let credentials = basic_authentication(request.headers()).map_err(WebhookError::AuthError)?;
+    // Compare digests rather than the secrets themselves to keep the comparison time constant.
+    let expected = Sha256::digest(webhook_secret.0.expose_secret().as_bytes());
+    let provided = Sha256::digest(credentials.password.expose_secret().as_bytes());
+    if expected != provided {
+        return Err(WebhookError::AuthError(anyhow::anyhow!("Invalid webhook secret.")));
+    }
+    let Some(reason) = event.suppression_reason() else {
+        tracing::info!("Ignoring an event that does not require suppressing the address.");
+        return Ok(HttpResponse::Ok().finish());
+    };
+    suppress_email(&pool, &event, reason)
+        .await
+        .context("Failed to add the address to the suppression list.")?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[tracing::instrument(name = "Suppress an email address", skip(pool, event))]
+async fn suppress_email(
+    pool: &PgPool,
+    event: &PostmarkEvent,
+    reason: SuppressionReason,
+) -> Result<(), sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        r#"
+INSERT INTO email_suppressions (email, reason, details, suppressed_at)
+VALUES ($1, $2, $3, $4)
+ON CONFLICT (email) DO UPDATE
+SET
+    reason = EXCLUDED.reason,
+    details = EXCLUDED.details,
+    suppressed_at = EXCLUDED.suppressed_at
+"#,
+        event.email.to_lowercase(),
+        reason.as_str(),
+        event.description,
+        Utc::now()
+    )
+        .execute(&mut *transaction)
+        .await?;
+    sqlx::query!(
+        r#"UPDATE subscriptions SET status = $2 WHERE lower(email) = lower($1)"#,
+        event.email,
+        reason.as_str()
+    )
+        .execute(&mut *transaction)
+        .await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// Whether the email provider told us to stop mailing this address.
+pub async fn is_suppressed(pool: &PgPool, email: &str) -> Result<bool, sqlx::Error> {
+    let r = sqlx::query!(
+        r#"SELECT 1 AS "found!" FROM email_suppressions WHERE email = lower($1)"#,
+        email
+    )
+        .fetch_optional(pool)
+        .await?;
+    Ok(r.is_some())
+}
@@ -1,12 +1,16 @@
+use crate::authentication::CsrfToken;
 use actix_web::cookie::time::Duration;
 use actix_web::cookie::Cookie;
 use actix_web::http::header::ContentType;
-use actix_web::HttpResponse;
+use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
 use std::fmt::Write;
 
 
-pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
+pub async fn login_form(
+    flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> HttpResponse {
     let mut error_html = String::new();
 
     // 过滤 error 层级
@@ -32,6 +36,7 @@ pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
 <body>
 {}
 <form action="/login" method="post">
+    {}
     <label>Username
         <input
                 type="text"
@@ -49,7 +54,8 @@ pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
 
     <button type="submit">Login</button>
 </form>
+<p><a href="/login/forgot">Forgot your password?</a></p>
 </body>
 </html>
-        "#, error_html))
+        "#, error_html, csrf_token.form_field()))
 }
\ No newline at end of file
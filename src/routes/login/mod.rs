@@ -1,3 +1,4 @@
 pub mod post;
 pub mod get;
-
+mod two_factor;
+pub use two_factor::{two_factor_form, verify_two_factor};
@@ -1,10 +1,16 @@
-use crate::authentication::{validate_credentials, AuthError, Credentials};
+use crate::audit::{record_audit_event, AuditAction};
+use crate::authentication::{
+    check_login_lockout, get_totp_secret, record_failed_login, record_successful_login,
+    validate_credentials, AuthError, Credentials, PasswordHashing,
+};
+use crate::configuration::LoginThrottleSettings;
 use crate::routes::error_chain_fmt;
 use crate::session_state::TypedSession;
+use crate::utils::client_ip;
 use actix_web::error::InternalError;
 use actix_web::http::header::LOCATION;
 use actix_web::http::StatusCode;
-use actix_web::{web, HttpResponse, ResponseError};
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
 use actix_web_flash_messages::FlashMessage;
 use secrecy::SecretString;
 use sqlx::PgPool;
@@ -23,31 +29,74 @@ fn login_redirect(e: LoginError) -> InternalError<LoginError> {
     InternalError::from_response(e, response)
 }
 #[tracing::instrument(
-    skip(form, pool, session),
+    skip(form, pool, session, request, throttle_settings, hashing),
     fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
 )]
-pub async fn login(form: web::Form<FormData>, pool: web::Data<PgPool>, session: TypedSession) -> Result<HttpResponse, InternalError<LoginError>> {
+pub async fn login(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+    request: HttpRequest,
+    throttle_settings: web::Data<LoginThrottleSettings>,
+    hashing: web::Data<PasswordHashing>,
+) -> Result<HttpResponse, InternalError<LoginError>> {
+    let username = form.0.username;
+    tracing::Span::current().record("username", tracing::field::display(&username));
+    let client_ip = client_ip(&request);
+    if check_login_lockout(&username, client_ip.as_deref(), &pool)
+        .await
+        .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?
+        .is_some()
+    {
+        return Err(login_redirect(LoginError::TooManyAttempts));
+    }
     let credentials = Credentials {
-        username: form.0.username,
+        username: username.clone(),
         password: form.0.password,
     };
-    match validate_credentials(credentials, &pool).await {
+    match validate_credentials(credentials, &hashing, &pool).await {
         Ok(user_id) => {
             tracing::Span::current()
-                .record("user_id", &tracing::field::display(&user_id));
+                .record("user_id", tracing::field::display(&user_id));
+
+            let two_factor_enabled = get_totp_secret(user_id, &pool)
+                .await
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?
+                .is_some();
+            session.renew();
+            if two_factor_enabled {
+                // The user is only logged in once the second step succeeds, and until then
+                // the failed attempts counter keeps counting wrong codes too.
+                session.insert_two_factor_user_id(user_id).map_err(
+                    |e| login_redirect(LoginError::UnexpectedError(e.into()))
+                )?;
+                return Ok(HttpResponse::SeeOther()
+                    .insert_header((LOCATION, "/login/two-factor"))
+                    .finish());
+            }
 
+            record_successful_login(&username, &pool)
+                .await
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
             let response = HttpResponse::SeeOther()
                 .insert_header((LOCATION, "/admin/dashboard"))
                 .finish();
-            session.renew();
-            session.insert_user_id(user_id).map_err(
+            session.log_in(user_id, &request).map_err(
                 |e| login_redirect(LoginError::UnexpectedError(e.into()))
             )?;
+            record_audit_event(pool.get_ref(), user_id, AuditAction::LogIn, None, client_ip.as_deref())
+                .await
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
             Ok(response)
         }
         Err(e) => {
             let e = match e {
-                AuthError::InvalidCredentials(_) => { LoginError::AuthError(e.into()) }
+                AuthError::InvalidCredentials(_) => {
+                    record_failed_login(&username, client_ip.as_deref(), &throttle_settings, &pool)
+                        .await
+                        .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+                    LoginError::AuthError(e.into())
+                }
                 AuthError::InternalError(_) => { LoginError::UnexpectedError(e.into()) }
             };
             Err(login_redirect(e))
@@ -59,6 +108,8 @@ pub async fn login(form: web::Form<FormData>, pool: web::Data<PgPool>, session:
 pub enum LoginError {
     #[error("Authentication failed")]
     AuthError(#[source] anyhow::Error),
+    #[error("Too many failed login attempts, please try again later")]
+    TooManyAttempts,
     #[error("Something went wrong")]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -0,0 +1,108 @@
+use crate::audit::{record_audit_event, AuditAction};
+use crate::authentication::{
+    check_login_lockout, record_failed_login, record_successful_login, verify_second_factor,
+    CsrfToken,
+};
+use crate::configuration::LoginThrottleSettings;
+use crate::routes::admin::get_username;
+use crate::session_state::TypedSession;
+use crate::utils::{client_ip, e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use sqlx::PgPool;
+use std::fmt::Write;
+
+pub async fn two_factor_form(
+    session: TypedSession,
+    flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_token.form_field();
+    if session.get_two_factor_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap()
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Two-factor authentication</title>
+</head>
+<body>
+{msg_html}
+    <form action="/login/two-factor" method="post">
+        {csrf_field}
+        <label>Authentication code
+            <input
+                type="text"
+                inputmode="numeric"
+                autocomplete="one-time-code"
+                placeholder="6-digit code or a recovery code"
+                name="code"
+            >
+        </label>
+        <button type="submit">Verify</button>
+    </form>
+    <p><a href="/login">&lt;- Back to login</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+#[derive(serde::Deserialize)]
+pub struct TwoFactorFormData {
+    code: String,
+}
+
+#[tracing::instrument(
+    name = "Verify two-factor login",
+    skip(form, pool, session, request, throttle_settings),
+    fields(user_id=tracing::field::Empty)
+)]
+pub async fn verify_two_factor(
+    form: web::Form<TwoFactorFormData>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+    request: HttpRequest,
+    throttle_settings: web::Data<LoginThrottleSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(user_id) = session.get_two_factor_user_id().map_err(e500)? else {
+        return Ok(see_other("/login"));
+    };
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+    // Wrong codes count against the same limits as wrong passwords:
+    // six digits would not survive unlimited guessing.
+    let username = get_username(&user_id, &pool).await.map_err(e500)?;
+    let client_ip = client_ip(&request);
+    if check_login_lockout(&username, client_ip.as_deref(), &pool)
+        .await
+        .map_err(e500)?
+        .is_some()
+    {
+        session.remove_two_factor_user_id();
+        FlashMessage::error("Too many failed login attempts, please try again later").send();
+        return Ok(see_other("/login"));
+    }
+    if !verify_second_factor(user_id, &form.code, &pool).await.map_err(e500)? {
+        record_failed_login(&username, client_ip.as_deref(), &throttle_settings, &pool)
+            .await
+            .map_err(e500)?;
+        FlashMessage::error("The authentication code is not valid.").send();
+        return Ok(see_other("/login/two-factor"));
+    }
+    record_successful_login(&username, &pool).await.map_err(e500)?;
+    session.renew();
+    session.remove_two_factor_user_id();
+    session.log_in(user_id, &request).map_err(e500)?;
+    record_audit_event(pool.get_ref(), user_id, AuditAction::LogIn, None, client_ip.as_deref())
+        .await
+        .map_err(e500)?;
+    Ok(see_other("/admin/dashboard"))
+}
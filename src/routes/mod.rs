@@ -1,15 +1,21 @@
+mod email_webhooks;
 mod health_check;
 mod subscriptions;
 mod subscription_confirm;
+mod subscription_unsubscribe;
 mod newsletters;
 mod home;
 mod login;
+mod password_reset;
 mod admin;
 
+pub use email_webhooks::*;
 pub use health_check::*;
 pub use subscriptions::*;
 pub use subscription_confirm::*;
+pub use subscription_unsubscribe::*;
 pub use newsletters::*;
 pub use home::*;
 pub use login::*;
+pub use password_reset::*;
 pub use admin::*;
\ No newline at end of file
@@ -1,7 +1,8 @@
-use crate::authentication::{validate_credentials, AuthError, Credentials};
-use crate::domain::SubscriberEmail;
-use crate::email_client::EmailClient;
-use crate::routes::error_chain_fmt;
+use crate::audit::{record_audit_event, AuditAction};
+use crate::authentication::{bearer_token, validate_api_token, ApiScope, AuthError, Credentials};
+use crate::idempotency::Idempotency;
+use crate::routes::{enqueue_delivery_tasks, error_chain_fmt, insert_newsletter_issue, validate_merge_tags, IssueContent};
+use crate::utils::client_ip;
 use actix_web::body::BoxBody;
 use actix_web::http::header::{HeaderMap, HeaderValue};
 use actix_web::http::{header, StatusCode};
@@ -14,10 +15,6 @@ use serde::Deserialize;
 use sqlx::PgPool;
 use std::fmt::{Debug, Formatter};
 
-struct ConfirmedSubscriber {
-    email: SubscriberEmail,
-}
-
 #[derive(Deserialize)]
 pub struct BodyData {
     title: String,
@@ -34,6 +31,10 @@ pub struct Content {
 pub enum PublishError {
     #[error("Authentication failed")]
     AuthError(#[source] anyhow::Error),
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("The API token does not have the {0} scope")]
+    MissingScope(ApiScope),
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -48,96 +49,80 @@ impl ResponseError for PublishError {
     fn error_response(&self) -> HttpResponse<BoxBody> {
         match self {
             PublishError::UnexpectedError(_) => { HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR) }
+            PublishError::ValidationError(e) => HttpResponse::BadRequest().body(e.clone()),
             PublishError::AuthError(_) => {
                 let mut response = HttpResponse::new(StatusCode::UNAUTHORIZED);
-                let header_value = HeaderValue::from_str(r#"Basic realm="publish""#).unwrap();
+                let header_value = HeaderValue::from_str(r#"Bearer realm="publish""#).unwrap();
 
                 response.headers_mut()
                     .insert(header::WWW_AUTHENTICATE, header_value);
                 response
             }
+            PublishError::MissingScope(scope) => {
+                let mut response = HttpResponse::new(StatusCode::FORBIDDEN);
+                let header_value = HeaderValue::from_str(&format!(
+                    r#"Bearer realm="publish", error="insufficient_scope", scope="{}""#,
+                    scope
+                ))
+                    .unwrap();
+                response.headers_mut()
+                    .insert(header::WWW_AUTHENTICATE, header_value);
+                response
+            }
         }
     }
 }
 
 #[tracing::instrument(
     name = "Publish a newsletter issue to all subscribers",
-    skip(data, pool, email_client, request),
+    skip(data, pool, request, idempotency),
     fields(
-       username = tracing::field::Empty,
        user_id = tracing::field::Empty
     )
 )]
 pub async fn publish_newsletters(
     data: web::Json<BodyData>,
     pool: web::Data<PgPool>,
-    email_client: web::Data<EmailClient>,
-    request: HttpRequest)
+    request: HttpRequest,
+    idempotency: Idempotency)
     -> Result<HttpResponse, PublishError> {
-    let credentials = basic_authentication(request.headers()).map_err(PublishError::AuthError)?;
-
-    tracing::Span::current().record(
-        "username",
-        &tracing::field::display(&credentials.username),
-    );
-    let user_id = validate_credentials(credentials, &pool).await.map_err(|e| match e {
-        AuthError::InvalidCredentials(_) => { PublishError::AuthError(e.into()) }
-        AuthError::InternalError(_) => { PublishError::UnexpectedError(e.into()) }
+    let token = bearer_token(request.headers()).map_err(PublishError::AuthError)?;
+    let caller = validate_api_token(token, &pool).await.map_err(|e| match e {
+        AuthError::InvalidCredentials(_) => PublishError::AuthError(e.into()),
+        AuthError::InternalError(_) => PublishError::UnexpectedError(e.into()),
     })?;
     tracing::Span::current().record(
         "user_id",
-        &tracing::field::display(&user_id),
+        tracing::field::display(&caller.user_id),
     );
-
-    let subscribers = get_subscribers(&pool).await?;
-    for subscriber in subscribers {
-        match subscriber {
-            Ok(subscriber) => {
-                email_client
-                    .send_email(
-                        &subscriber.email,
-                        &data.title,
-                        &data.content.html,
-                        &data.content.text,
-                    )
-                    .await
-                    .with_context(|| {
-                        format!("Failed to send newsletter issue to {}", subscriber.email.as_ref())
-                    })?;
-            }
-            Err(err) => {
-                tracing::warn!(
-                    error.cause_chain = ?err,
-                    "Skipping a confirmed subscriber. \
-                    Their stored contact details are invalid",
-                );
-            }
-        }
+    if !caller.is_allowed_to(ApiScope::PublishNewsletters) {
+        return Err(PublishError::MissingScope(ApiScope::PublishNewsletters));
     }
-    Ok(HttpResponse::Ok().finish())
-}
 
-async fn get_subscribers(pool: &PgPool) -> Result<Vec<Result<ConfirmedSubscriber, anyhow::Error>>, anyhow::Error> {
-    let confirmed_subscribers = sqlx::query!(
-r#"
-        SELECT email
-        FROM subscriptions
-        WHERE status = 'confirmed'
-        "#
-)
-        .fetch_all(pool)
-        .await?
-        .into_iter()
-        .map(|r| match SubscriberEmail::parse(r.email) {
-            Ok(email) => Ok(ConfirmedSubscriber { email }),
-            Err(err) => Err(anyhow::anyhow!(err)),
-        })
-        .collect();
-    Ok(confirmed_subscribers)
+    let BodyData { title, content } = data.0;
+    let content = IssueContent::from_form(content.text, content.html, None);
+    validate_merge_tags(&title, &content).map_err(PublishError::ValidationError)?;
+    let mut transaction = idempotency.transaction().await?;
+    let issue_id = insert_newsletter_issue(&mut transaction, &title, &content, None)
+        .await
+        .context("Failed to store newsletter issue details")?;
+    enqueue_delivery_tasks(&mut transaction, issue_id)
+        .await
+        .context("Failed to enqueue delivery tasks")?;
+    record_audit_event(
+        &mut **transaction,
+        caller.user_id,
+        AuditAction::PublishNewsletter,
+        Some(issue_id),
+        client_ip(&request).as_deref(),
+    )
+        .await?;
+    Ok(HttpResponse::Accepted().json(serde_json::json!({
+        "newsletter_issue_id": issue_id
+    })))
 }
 
-
-fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Error> {
+pub(crate) fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Error> {
     let header_value = headers
         .get("Authorization")
         .context("The 'Authorization' header was missing")?
@@ -0,0 +1,4 @@
+mod get;
+pub use get::{forgot_password_form, reset_password_form};
+mod post;
+pub use post::{request_password_reset, reset_password};
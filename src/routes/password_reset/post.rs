@@ -0,0 +1,134 @@
+use crate::audit::{record_audit_event, AuditAction};
+use crate::authentication::{
+    change_password, discard_password_reset_tokens, issue_password_reset_token,
+    redeem_password_reset_token, PasswordHashing, PASSWORD_RESET_TOKEN_TTL,
+};
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailTransport;
+use crate::session_store::IndexedSessionStore;
+use crate::startup::ApplicationBaseUrl;
+use crate::utils::{client_ip, e500, see_other};
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use secrecy::{ExposeSecret, SecretString};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct ForgotPasswordFormData {
+    email: String,
+}
+
+/// Always answers with the same message, so the form cannot be used to find out
+/// which email addresses belong to an admin account.
+#[tracing::instrument(name = "Request a password reset", skip(form, pool, email_client, base_url))]
+pub async fn request_password_reset(
+    form: web::Form<ForgotPasswordFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<dyn EmailTransport>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if let Ok(email) = SubscriberEmail::parse(form.0.email.trim().to_owned())
+        && let Some(user_id) = get_active_user_id_by_email(&email, &pool).await.map_err(e500)?
+    {
+        let token = issue_password_reset_token(user_id, &pool).await.map_err(e500)?;
+        if let Err(e) = send_password_reset_email(email_client.as_ref(), &email, &base_url.0, &token).await {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to send the password reset email",
+            );
+        }
+    }
+    FlashMessage::info(
+        "If an account exists for that address, a link to reset your password has been sent to it."
+    ).send();
+    Ok(see_other("/login"))
+}
+
+#[derive(serde::Deserialize)]
+pub struct ResetPasswordFormData {
+    token: String,
+    new_password: SecretString,
+    new_password_check: SecretString,
+}
+
+#[tracing::instrument(name = "Reset a forgotten password", skip(form, pool, session_store, hashing, request))]
+pub async fn reset_password(
+    form: web::Form<ResetPasswordFormData>,
+    pool: web::Data<PgPool>,
+    session_store: web::Data<IndexedSessionStore>,
+    hashing: web::Data<PasswordHashing>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let ResetPasswordFormData { token, new_password, new_password_check } = form.0;
+    if new_password.expose_secret() != new_password_check.expose_secret() {
+        FlashMessage::error(
+            "You entered two different new passwords - the field values must match."
+        ).send();
+        let query = serde_urlencoded::to_string([("token", &token)]).map_err(e500)?;
+        return Ok(see_other(&format!("/login/reset?{}", query)));
+    }
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let Some(user_id) = redeem_password_reset_token(&token, &mut *transaction).await.map_err(e500)? else {
+        // An expired token is still used up.
+        transaction.commit().await.map_err(e500)?;
+        FlashMessage::error(
+            "This password reset link is invalid or has expired. Please request a new one."
+        ).send();
+        return Ok(see_other("/login/forgot"));
+    };
+    change_password(user_id, new_password, &hashing, &mut *transaction).await.map_err(e500)?;
+    discard_password_reset_tokens(user_id, &mut *transaction).await.map_err(e500)?;
+    record_audit_event(&mut *transaction, user_id, AuditAction::ResetPassword, None, client_ip(&request).as_deref())
+        .await
+        .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    session_store.revoke_user_sessions(user_id).await.map_err(e500)?;
+    FlashMessage::info("Your password has been reset. You can now log in with your new password.").send();
+    Ok(see_other("/login"))
+}
+
+#[tracing::instrument(name = "Get active user by email", skip(email, pool))]
+async fn get_active_user_id_by_email(
+    email: &SubscriberEmail,
+    pool: &PgPool,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT user_id FROM users WHERE lower(email) = lower($1) AND is_active"#,
+        email.as_ref()
+    )
+        .fetch_optional(pool)
+        .await
+        .context("Failed to look up the user by email")?;
+    Ok(row.map(|r| r.user_id))
+}
+
+async fn send_password_reset_email(
+    email_client: &dyn EmailTransport,
+    recipient: &SubscriberEmail,
+    base_url: &str,
+    token: &str,
+) -> Result<(), anyhow::Error> {
+    let reset_link = format!("{}/login/reset?token={}", base_url, token);
+    let minutes = PASSWORD_RESET_TOKEN_TTL.num_minutes();
+    email_client
+        .send_email(
+            recipient,
+            "Reset your password",
+            &format!(
+                "Someone asked to reset the password of your admin account. <br />\
+                Click <a href=\"{reset_link}\">here</a> to choose a new one. \
+                The link can be used once and expires in {minutes} minutes. <br />\
+                If it was not you, you can safely ignore this email."
+            ),
+            &format!(
+                "Someone asked to reset the password of your admin account.\n\
+                Visit {reset_link} to choose a new one. \
+                The link can be used once and expires in {minutes} minutes.\n\
+                If it was not you, you can safely ignore this email."
+            ),
+        )
+        .await
+}
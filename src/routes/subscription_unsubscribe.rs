@@ -0,0 +1,127 @@
+use crate::domain::UnsubscribeToken;
+use crate::routes::error_chain_fmt;
+use crate::startup::HmacSecret;
+use crate::utils::html_escape;
+use actix_web::http::header::ContentType;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct UnsubscribeParameters {
+    token: String,
+}
+
+#[derive(thiserror::Error)]
+pub enum UnsubscribeError {
+    #[error("The unsubscribe link is invalid.")]
+    InvalidToken(#[source] anyhow::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for UnsubscribeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for UnsubscribeError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            UnsubscribeError::InvalidToken(_) => StatusCode::UNAUTHORIZED,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// The link in the email body. It only asks for confirmation: mail scanners and link
+/// prefetchers follow links in delivered emails, and must not unsubscribe anybody.
+#[tracing::instrument(name = "Ask to confirm unsubscribing", skip(parameters, hmac_secret))]
+pub async fn unsubscribe_form(
+    parameters: web::Query<UnsubscribeParameters>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, UnsubscribeError> {
+    UnsubscribeToken::verify(&parameters.token, &hmac_secret.0)
+        .map_err(UnsubscribeError::InvalidToken)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Unsubscribe</title>
+</head>
+<body>
+    <p>Do you want to stop receiving our newsletter?</p>
+    <form action="/subscriptions/unsubscribe?token={}" method="post">
+        <button type="submit">Unsubscribe</button>
+    </form>
+</body>
+</html>"#,
+            html_escape(&parameters.token)
+        )))
+}
+
+/// Serves both the confirmation form and RFC 8058 one-click requests, which mail clients
+/// send with a `List-Unsubscribe=One-Click` form body we do not need to read.
+#[tracing::instrument(name = "Unsubscribe a subscriber", skip(parameters, pool, hmac_secret))]
+pub async fn unsubscribe(
+    parameters: web::Query<UnsubscribeParameters>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, UnsubscribeError> {
+    let subscriber_id = UnsubscribeToken::verify(&parameters.token, &hmac_secret.0)
+        .map_err(UnsubscribeError::InvalidToken)?;
+    let found = mark_subscriber_as_unsubscribed(&pool, subscriber_id)
+        .await
+        .context("Failed to update the subscriber's status to `unsubscribed`.")?;
+    if !found {
+        return Err(UnsubscribeError::InvalidToken(anyhow::anyhow!(
+            "There is no subscriber associated with the provided token."
+        )));
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Unsubscribed</title>
+</head>
+<body>
+    <p>You have been unsubscribed. You will not receive any further issues.</p>
+</body>
+</html>"#,
+        ))
+}
+
+/// Returns `false` if there is no such subscriber.
+/// Addresses that bounced or complained keep their status: it is what keeps them off the
+/// mailing list for good, whereas an unsubscribed subscriber may subscribe again.
+#[tracing::instrument(name = "Mark subscriber as unsubscribed", skip(pool))]
+async fn mark_subscriber_as_unsubscribed(pool: &PgPool, subscriber_id: Uuid) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE subscriptions SET status = 'unsubscribed'
+        WHERE id = $1 AND status NOT IN ('bounced', 'complained')
+        "#,
+        subscriber_id,
+    )
+        .execute(pool)
+        .await?;
+    if result.rows_affected() > 0 {
+        return Ok(true);
+    }
+    let row = sqlx::query!(
+        r#"SELECT EXISTS(SELECT 1 FROM subscriptions WHERE id = $1) AS "exists!""#,
+        subscriber_id,
+    )
+        .fetch_one(pool)
+        .await?;
+    Ok(row.exists)
+}
@@ -1,12 +1,12 @@
 use crate::domain::NewSubscriber;
-use crate::email_client::EmailClient;
+use crate::email_client::EmailTransport;
+use crate::routes::is_suppressed;
 use crate::startup::ApplicationBaseUrl;
 use actix_web::http::StatusCode;
 use actix_web::{web, HttpResponse, ResponseError};
 use anyhow::Context;
 use chrono::Utc;
 use rand::Rng;
-use reqwest::Error;
 use serde::Deserialize;
 use sqlx::{Executor, PgPool, Postgres, Transaction};
 use std::fmt::{Debug, Display, Formatter};
@@ -25,9 +25,17 @@ subscriber_email = %form.email,
 subscriber_name = form.name
     )
 )]
-pub async fn subscribe(web::Form(form): web::Form<FormData>, pool: web::Data<PgPool>, email_client: web::Data<EmailClient>, base_url: web::Data<ApplicationBaseUrl>)
+pub async fn subscribe(web::Form(form): web::Form<FormData>, pool: web::Data<PgPool>, email_client: web::Data<dyn EmailTransport>, base_url: web::Data<ApplicationBaseUrl>)
                        -> Result<HttpResponse, SubscribeError> {
-    let subscriber_form = form.try_into().map_err(SubscribeError::ValidationError)?;
+    let subscriber_form: NewSubscriber = form.try_into().map_err(SubscribeError::ValidationError)?;
+    if is_suppressed(&pool, subscriber_form.email.as_ref())
+        .await
+        .context("Failed to check the suppression list")?
+    {
+        // Do not tell an anonymous caller which addresses bounced or complained.
+        tracing::warn!("Not sending a confirmation email to a suppressed address.");
+        return Ok(HttpResponse::Ok().finish());
+    }
     let token = generate_subscription_token();
     let mut transaction = pool.begin().await.context("Failed to acquire a Postgres connection from the pool")?;
 
@@ -35,7 +43,7 @@ pub async fn subscribe(web::Form(form): web::Form<FormData>, pool: web::Data<PgP
 
     store_token(&mut transaction, subscriber_id, &token).await.context("Failed to store the confirmation token for a new subscriber.")?;
     transaction.commit().await.context("Failed to commit SQL transaction to store a new subscriber.")?;
-    send_confirmation_email(&email_client, subscriber_form, &base_url.0, token).await.context("Failed to send a confirmation email")?;
+    send_confirmation_email(email_client.as_ref(), subscriber_form, &base_url.0, token).await.context("Failed to send a confirmation email")?;
 
     Ok(HttpResponse::Ok().finish())
 
@@ -45,7 +53,7 @@ pub async fn subscribe(web::Form(form): web::Form<FormData>, pool: web::Data<PgP
     name = "Send a confirmation email to a new subscriber",
     skip(email_client, subscriber_form)
 )]
-async fn send_confirmation_email(email_client: &EmailClient, subscriber_form: NewSubscriber, base_url: &str, token: String) -> Result<(), Error> {
+async fn send_confirmation_email(email_client: &dyn EmailTransport, subscriber_form: NewSubscriber, base_url: &str, token: String) -> Result<(), anyhow::Error> {
     let confirmation_link = format!("{}/subscriptions/confirm?subscription_token={}", base_url, token);
     email_client
         .send_email(
@@ -1,12 +1,56 @@
+use crate::authentication::generate_csrf_token;
+use crate::utils::client_ip;
 use actix_session::{Session, SessionExt, SessionGetError, SessionInsertError};
 use actix_web::dev::Payload;
+use actix_web::http::header::USER_AGENT;
 use actix_web::{FromRequest, HttpRequest};
+use chrono::{DateTime, Utc};
 use std::future::{ready, Ready};
 use uuid::Uuid;
 
+/// How often `last_seen_at` is refreshed. Every refresh is a write to the session store,
+/// so it is not done on every single request.
+const LAST_SEEN_RESOLUTION: chrono::Duration = chrono::Duration::minutes(1);
+
+/// Describes a logged-in session on the `/admin/sessions` page. `id` is a public handle
+/// for the session: the session key itself is the cookie value and must stay secret.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct SessionMetadata {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl SessionMetadata {
+    pub fn new(request: &HttpRequest) -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::new_v4(),
+            created_at: now,
+            last_seen_at: now,
+            ip: client_ip(request),
+            user_agent: user_agent(request),
+        }
+    }
+}
+
+fn user_agent(request: &HttpRequest) -> Option<String> {
+    request
+        .headers()
+        .get(USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .map(ToOwned::to_owned)
+}
+
 pub struct TypedSession(Session);
 impl TypedSession {
-    const USER_ID_KEY: &'static str = "user_id";
+    pub(crate) const USER_ID_KEY: &'static str = "user_id";
+    const TWO_FACTOR_USER_ID_KEY: &'static str = "two_factor_user_id";
+    const PENDING_TOTP_SECRET_KEY: &'static str = "pending_totp_secret";
+    pub(crate) const METADATA_KEY: &'static str = "metadata";
+    const CSRF_TOKEN_KEY: &'static str = "csrf_token";
 
     pub fn renew(&self) {
         self.0.renew();
@@ -20,6 +64,70 @@ impl TypedSession {
         self.0.get(Self::USER_ID_KEY)
     }
 
+    /// Marks the session as logged in, recording where it was opened from.
+    /// The CSRF token is replaced so that one seen before logging in cannot be used afterwards.
+    pub fn log_in(&self, user_id: Uuid, request: &HttpRequest) -> Result<(), SessionInsertError> {
+        self.insert_csrf_token(&generate_csrf_token())?;
+        self.insert_user_id(user_id)?;
+        self.0.insert(Self::METADATA_KEY, SessionMetadata::new(request))
+    }
+
+    pub fn get_metadata(&self) -> Result<Option<SessionMetadata>, SessionGetError> {
+        self.0.get(Self::METADATA_KEY)
+    }
+
+    /// Refreshes when and from where the session was last used.
+    /// Sessions opened before metadata was recorded get some on their next request.
+    pub fn touch(&self, request: &HttpRequest) -> Result<(), actix_web::Error> {
+        let now = Utc::now();
+        let metadata = match self.get_metadata()? {
+            Some(metadata) if now - metadata.last_seen_at < LAST_SEEN_RESOLUTION => return Ok(()),
+            Some(metadata) => SessionMetadata {
+                last_seen_at: now,
+                ip: client_ip(request),
+                user_agent: user_agent(request),
+                ..metadata
+            },
+            None => SessionMetadata::new(request),
+        };
+        self.0.insert(Self::METADATA_KEY, metadata)?;
+        Ok(())
+    }
+
+    /// Remembers a user who passed the password check but still owes a second factor.
+    pub fn insert_two_factor_user_id(&self, user_id: Uuid) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::TWO_FACTOR_USER_ID_KEY, user_id)
+    }
+
+    pub fn get_two_factor_user_id(&self) -> Result<Option<Uuid>, SessionGetError> {
+        self.0.get(Self::TWO_FACTOR_USER_ID_KEY)
+    }
+
+    pub fn remove_two_factor_user_id(&self) {
+        self.0.remove(Self::TWO_FACTOR_USER_ID_KEY);
+    }
+
+    /// The TOTP secret shown during enrollment, kept until the user confirms it with a code.
+    pub fn insert_pending_totp_secret(&self, secret: &str) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::PENDING_TOTP_SECRET_KEY, secret)
+    }
+
+    pub fn get_pending_totp_secret(&self) -> Result<Option<String>, SessionGetError> {
+        self.0.get(Self::PENDING_TOTP_SECRET_KEY)
+    }
+
+    pub fn remove_pending_totp_secret(&self) {
+        self.0.remove(Self::PENDING_TOTP_SECRET_KEY);
+    }
+
+    pub fn insert_csrf_token(&self, token: &str) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::CSRF_TOKEN_KEY, token)
+    }
+
+    pub fn get_csrf_token(&self) -> Result<Option<String>, SessionGetError> {
+        self.0.get(Self::CSRF_TOKEN_KEY)
+    }
+
     pub fn log_out(self) {
         self.0.purge()
     }
@@ -0,0 +1,205 @@
+use crate::session_state::{SessionMetadata, TypedSession};
+use actix_session::storage::{LoadError, RedisSessionStore, SaveError, SessionKey, SessionStore, UpdateError};
+use actix_web::cookie::time::Duration;
+use anyhow::Context;
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+type SessionState = HashMap<String, String>;
+
+/// A `RedisSessionStore` that also keeps, for every logged-in user, the set of session keys
+/// that belong to them. That index is what makes it possible to list a user's sessions and
+/// to log them out everywhere, e.g. after their password has been reset.
+#[derive(Clone)]
+pub struct IndexedSessionStore {
+    inner: RedisSessionStore,
+    redis: ConnectionManager,
+}
+
+impl IndexedSessionStore {
+    pub async fn new(redis_uri: &str) -> Result<Self, anyhow::Error> {
+        let inner = RedisSessionStore::new(redis_uri).await?;
+        let client = redis::Client::open(redis_uri)?;
+        let redis = ConnectionManager::new(client).await?;
+        Ok(Self { inner, redis })
+    }
+
+    /// The user's live sessions that carry metadata, most recently used first.
+    #[tracing::instrument(name = "List the sessions of a user", skip(self))]
+    pub async fn list_user_sessions(&self, user_id: Uuid) -> Result<Vec<SessionMetadata>, anyhow::Error> {
+        let mut sessions: Vec<SessionMetadata> = self
+            .user_sessions(user_id)
+            .await?
+            .into_iter()
+            .filter_map(|(_, metadata)| metadata)
+            .collect();
+        sessions.sort_by_key(|s| std::cmp::Reverse(s.last_seen_at));
+        Ok(sessions)
+    }
+
+    /// Deletes the session of `user_id` identified by `session_id`.
+    /// Returns `false` if there is no such session.
+    #[tracing::instrument(name = "Revoke a session", skip(self))]
+    pub async fn revoke_user_session(&self, user_id: Uuid, session_id: Uuid) -> Result<bool, anyhow::Error> {
+        for (session_key, metadata) in self.user_sessions(user_id).await? {
+            if metadata.is_some_and(|m| m.id == session_id) {
+                self.delete(&session_key).await?;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Deletes every session of `user_id` except the one identified by `keep_session_id`.
+    #[tracing::instrument(name = "Revoke the other sessions of a user", skip(self))]
+    pub async fn revoke_other_user_sessions(&self, user_id: Uuid, keep_session_id: Uuid) -> Result<(), anyhow::Error> {
+        for (session_key, metadata) in self.user_sessions(user_id).await? {
+            if metadata.is_none_or(|m| m.id != keep_session_id) {
+                self.delete(&session_key).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Loads every indexed session of `user_id`, dropping the ones that expired from the index.
+    async fn user_sessions(&self, user_id: Uuid) -> Result<Vec<(SessionKey, Option<SessionMetadata>)>, anyhow::Error> {
+        let index_key = index_key(user_id);
+        let mut redis = self.redis.clone();
+        let session_keys: Vec<String> = redis
+            .smembers(&index_key)
+            .await
+            .context("Failed to list the user's sessions")?;
+        let mut sessions = Vec::new();
+        for raw_key in session_keys {
+            let Ok(session_key) = SessionKey::try_from(raw_key.clone()) else {
+                continue;
+            };
+            match self.inner.load(&session_key).await? {
+                Some(state) => sessions.push((session_key, session_metadata(&state))),
+                None => redis
+                    .srem::<_, _, ()>(&index_key, raw_key)
+                    .await
+                    .context("Failed to drop an expired session from its index")?,
+            }
+        }
+        Ok(sessions)
+    }
+
+    /// Deletes every session currently bound to `user_id`.
+    #[tracing::instrument(name = "Revoke all sessions of a user", skip(self))]
+    pub async fn revoke_user_sessions(&self, user_id: Uuid) -> Result<(), anyhow::Error> {
+        let index_key = index_key(user_id);
+        let mut redis = self.redis.clone();
+        let session_keys: Vec<String> = redis
+            .smembers(&index_key)
+            .await
+            .context("Failed to list the user's sessions")?;
+        for session_key in session_keys {
+            if let Ok(session_key) = SessionKey::try_from(session_key) {
+                self.inner.delete(&session_key).await?;
+            }
+        }
+        redis
+            .del::<_, ()>(&index_key)
+            .await
+            .context("Failed to clear the user's session index")?;
+        Ok(())
+    }
+
+    async fn index_session(
+        &self,
+        session_key: &SessionKey,
+        state: &SessionState,
+        ttl: &Duration,
+    ) -> Result<(), anyhow::Error> {
+        let Some(user_id) = session_user_id(state) else {
+            return Ok(());
+        };
+        let index_key = index_key(user_id);
+        let mut redis = self.redis.clone();
+        redis
+            .sadd::<_, _, ()>(&index_key, session_key.as_ref())
+            .await
+            .context("Failed to index the session")?;
+        // The index only has to outlive the most recently touched session.
+        redis
+            .expire::<_, ()>(&index_key, ttl.whole_seconds())
+            .await
+            .context("Failed to set the session index expiry")?;
+        Ok(())
+    }
+}
+
+impl SessionStore for IndexedSessionStore {
+    async fn load(&self, session_key: &SessionKey) -> Result<Option<SessionState>, LoadError> {
+        self.inner.load(session_key).await
+    }
+
+    async fn save(&self, session_state: SessionState, ttl: &Duration) -> Result<SessionKey, SaveError> {
+        let session_key = self.inner.save(session_state.clone(), ttl).await?;
+        self.index_session(&session_key, &session_state, ttl)
+            .await
+            .map_err(SaveError::Other)?;
+        Ok(session_key)
+    }
+
+    async fn update(
+        &self,
+        session_key: SessionKey,
+        mut session_state: SessionState,
+        ttl: &Duration,
+    ) -> Result<SessionKey, UpdateError> {
+        // `RedisSessionStore` recreates a session that vanished while the request was in flight.
+        // If it vanished because it was revoked, it must not come back logged in.
+        if session_user_id(&session_state).is_some()
+            && self
+                .inner
+                .load(&session_key)
+                .await
+                .map_err(|e| UpdateError::Other(e.into()))?
+                .is_none()
+        {
+            session_state.remove(TypedSession::USER_ID_KEY);
+        }
+        let session_key = self.inner.update(session_key, session_state.clone(), ttl).await?;
+        self.index_session(&session_key, &session_state, ttl)
+            .await
+            .map_err(UpdateError::Other)?;
+        Ok(session_key)
+    }
+
+    async fn update_ttl(&self, session_key: &SessionKey, ttl: &Duration) -> Result<(), anyhow::Error> {
+        self.inner.update_ttl(session_key, ttl).await
+    }
+
+    async fn delete(&self, session_key: &SessionKey) -> Result<(), anyhow::Error> {
+        let state = self.inner.load(session_key).await?;
+        if let Some(user_id) = state.as_ref().and_then(session_user_id) {
+            self.redis
+                .clone()
+                .srem::<_, _, ()>(index_key(user_id), session_key.as_ref())
+                .await
+                .context("Failed to remove the session from its index")?;
+        }
+        self.inner.delete(session_key).await
+    }
+}
+
+fn index_key(user_id: Uuid) -> String {
+    format!("user_sessions:{}", user_id)
+}
+
+/// Session values are stored JSON-encoded, see `actix_session::Session::insert`.
+fn session_user_id(state: &SessionState) -> Option<Uuid> {
+    state
+        .get(TypedSession::USER_ID_KEY)
+        .and_then(|value| serde_json::from_str(value).ok())
+}
+
+fn session_metadata(state: &SessionState) -> Option<SessionMetadata> {
+    state
+        .get(TypedSession::METADATA_KEY)
+        .and_then(|value| serde_json::from_str(value).ok())
+}
@@ -0,0 +1,90 @@
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// Tells the long-running tasks that the process is shutting down, so that they stop at
+/// a point where nothing is left half done.
+#[derive(Clone)]
+pub struct Shutdown(watch::Receiver<bool>);
+
+/// Requests the shutdown. Dropping it requests it as well, so that nothing outlives its owner.
+pub struct ShutdownTrigger(watch::Sender<bool>);
+
+pub fn shutdown_channel() -> (ShutdownTrigger, Shutdown) {
+    let (sender, receiver) = watch::channel(false);
+    (ShutdownTrigger(sender), Shutdown(receiver))
+}
+
+impl ShutdownTrigger {
+    pub fn trigger(&self) {
+        self.0.send_replace(true);
+    }
+}
+
+impl Shutdown {
+    pub fn is_requested(&self) -> bool {
+        *self.0.borrow() || self.0.has_changed().is_err()
+    }
+
+    pub async fn requested(&self) {
+        let mut receiver = self.0.clone();
+        // An error means that the trigger is gone.
+        let _ = receiver.wait_for(|requested| *requested).await;
+    }
+
+    /// Sleeps for `duration`, or until the shutdown is requested if that comes first.
+    pub async fn sleep(&self, duration: Duration) {
+        tokio::select! {
+            _ = tokio::time::sleep(duration) => {}
+            _ = self.requested() => {}
+        }
+    }
+}
+
+/// Resolves on SIGTERM, which is what orchestrators send, or SIGINT (Ctrl-C).
+pub async fn termination_signal() -> Result<(), std::io::Error> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            _ = terminate.recv() => Ok(()),
+            interrupted = tokio::signal::ctrl_c() => interrupted,
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn triggering_wakes_every_waiting_task() {
+        let (trigger, shutdown) = shutdown_channel();
+        assert!(!shutdown.is_requested());
+        let waiting = tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move { shutdown.requested().await }
+        });
+
+        trigger.trigger();
+
+        tokio::time::timeout(Duration::from_secs(1), waiting).await.unwrap().unwrap();
+        assert!(shutdown.is_requested());
+    }
+
+    #[tokio::test]
+    async fn dropping_the_trigger_requests_the_shutdown() {
+        let (trigger, shutdown) = shutdown_channel();
+
+        drop(trigger);
+
+        assert!(shutdown.is_requested());
+        tokio::time::timeout(Duration::from_secs(1), shutdown.sleep(Duration::from_secs(60)))
+            .await
+            .unwrap();
+    }
+}
@@ -1,10 +1,12 @@
-use crate::authentication::reject_anonymous_users;
-use crate::configuration::{DatabaseSettings, Settings};
-use crate::email_client::EmailClient;
+use crate::authentication::{reject_anonymous_users, reject_forged_requests, require_editor, require_owner, require_viewer, PasswordHashing};
+use crate::configuration::{DatabaseSettings, IdempotencySettings, LoginThrottleSettings, Settings};
+use crate::email_client::EmailTransport;
+use crate::idempotency::idempotent;
 use crate::routes::get::login_form;
 use crate::routes::post::login;
-use crate::routes::{admin_dashboard, change_password, change_password_form, confirm, health_check, home, log_out, publish_newsletter, publish_newsletter_form, publish_newsletters, subscribe};
-use actix_session::storage::RedisSessionStore;
+use crate::routes::{activate_user, create_api_token, list_api_tokens, revoke_api_token, admin_dashboard, audit_log, export_audit_log, cancel_newsletter_issue, change_password, change_password_form, clear_failed_login, confirm, create_draft, deactivate_user, delete_draft, delete_user, draft_form, enroll_two_factor, failed_deliveries, failed_logins, forgot_password_form, health_check, home, invite_user, list_drafts, list_sessions, list_users, log_out, newsletter_issues, postmark_webhook, preview_draft, publish_draft, publish_newsletter, publish_newsletter_form, publish_newsletters, request_password_reset, requeue_failed_delivery, reschedule_newsletter_issue, reset_password, reset_password_form, revoke_all_sessions, revoke_session, subscribe, two_factor_form, two_factor_settings, unenroll_two_factor, unsubscribe, unsubscribe_form, update_draft, verify_two_factor};
+use crate::session_store::IndexedSessionStore;
+use crate::shutdown::Shutdown;
 use actix_session::SessionMiddleware;
 use actix_web::cookie::Key;
 use actix_web::dev::Server;
@@ -16,7 +18,9 @@ use actix_web_flash_messages::FlashMessagesFramework;
 use secrecy::{ExposeSecret, SecretString};
 use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
-use std::net::TcpListener;
+use std::net::{IpAddr, TcpListener};
+use std::sync::Arc;
+use std::time::Duration;
 use tracing_actix_web::TracingLogger;
 
 pub struct Application {
@@ -26,6 +30,7 @@ pub struct Application {
 impl Application {
     pub async fn build(configuration: Settings) -> Result<Self, anyhow::Error> {
         let connection_pool = get_connection_pool(&configuration.database);
+        let webhook_secret = configuration.email_client.webhook_secret.clone();
         let email_client = configuration.email_client.client();
         let address = format!(
             "{}:{}",
@@ -33,13 +38,20 @@ impl Application {
         );
         let listener = TcpListener::bind(address)?;
         let port = listener.local_addr()?.port();
+        let shutdown_timeout = configuration.application.shutdown_timeout();
         let server = run(
             listener,
             connection_pool,
             email_client,
             configuration.application.base_url,
             configuration.application.hmac_secret,
+            webhook_secret,
             configuration.redis_uri,
+            configuration.login_throttle,
+            configuration.idempotency,
+            PasswordHashing::new(&configuration.password_hashing)?,
+            configuration.application.trusted_proxies,
+            shutdown_timeout,
         ).await?;
         Ok(Self {
             port,
@@ -51,7 +63,14 @@ impl Application {
         self.port
     }
 
-    pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
+    /// Serves requests until `shutdown` is requested. The server then stops accepting
+    /// connections and lets the requests in flight finish, for up to the shutdown timeout.
+    pub async fn run_until_stopped(self, shutdown: Shutdown) -> Result<(), std::io::Error> {
+        let handle = self.server.handle();
+        tokio::spawn(async move {
+            shutdown.requested().await;
+            handle.stop(true).await;
+        });
         self.server.await
     }
 }
@@ -65,49 +84,123 @@ pub fn get_connection_pool(configuration: &DatabaseSettings) -> PgPool {
 
 pub struct ApplicationBaseUrl(pub String);
 
+pub struct HmacSecret(pub SecretString);
+
+pub struct WebhookSecret(pub SecretString);
+
+pub struct TrustedProxies(pub Vec<IpAddr>);
+
+#[allow(clippy::too_many_arguments)]
 async fn run(
     listener: TcpListener,
     db_pool: PgPool,
-    email_client: EmailClient,
+    email_client: Arc<dyn EmailTransport>,
     base_url: String,
     hmac_secret: SecretString,
+    webhook_secret: SecretString,
     redis_uri: SecretString,
-    // 下面因为 改异步和使用 RedisSessionStore::new(redis_uri.expose_secret()).await?; 这行代码有变化
+    login_throttle: LoginThrottleSettings,
+    idempotency: IdempotencySettings,
+    password_hashing: PasswordHashing,
+    trusted_proxies: Vec<IpAddr>,
+    shutdown_timeout: Duration,
+    // 下面因为 改异步和使用 IndexedSessionStore::new(redis_uri.expose_secret()).await?; 这行代码有变化
 ) -> Result<Server, anyhow::Error> {
     let db_pool = web::Data::new(db_pool);
-    let email_client = Data::new(email_client);
+    let email_client: Data<dyn EmailTransport> = Data::from(email_client);
     let base_url = Data::new(ApplicationBaseUrl(base_url));
     let secret_key = Key::from(hmac_secret.expose_secret().as_bytes());
+    let hmac_secret = Data::new(HmacSecret(hmac_secret));
+    let webhook_secret = Data::new(WebhookSecret(webhook_secret));
+    let login_throttle = Data::new(login_throttle);
+    let idempotency = Data::new(idempotency);
+    let password_hashing = Data::new(password_hashing);
+    let trusted_proxies = Data::new(TrustedProxies(trusted_proxies));
     let messages_store = CookieMessageStore::builder(secret_key.clone()).build();
     let message_framework = FlashMessagesFramework::builder(messages_store).build();
-    let redis_store = RedisSessionStore::new(redis_uri.expose_secret()).await?;
+    let redis_store = IndexedSessionStore::new(redis_uri.expose_secret()).await?;
+    let session_store = Data::new(redis_store.clone());
     let server = HttpServer::new(move || {
         App::new()
             .wrap(SessionMiddleware::new(redis_store.clone(), secret_key.clone()))
             .wrap(TracingLogger::default())
             .wrap(message_framework.clone())
             .route("/health_check", web::get().to(health_check))
-            .route("/subscriptions", web::post().to(subscribe))
+            .route("/subscriptions", web::post().to(subscribe).wrap(from_fn(idempotent)))
             .route("/subscriptions/confirm", web::get().to(confirm))
-            .route("/newsletters", web::post().to(publish_newsletters))
+            .route("/subscriptions/unsubscribe", web::get().to(unsubscribe_form))
+            .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
+            .route("/newsletters", web::post().to(publish_newsletters).wrap(from_fn(idempotent)))
+            .route("/webhooks/postmark", web::post().to(postmark_webhook))
             .route("/", web::get().to(home))
-            .route("/login", web::get().to(login_form))
-            .route("/login", web::post().to(login))
-
+            .service(
+                web::scope("/login")
+                    .wrap(from_fn(reject_forged_requests))
+                    .route("", web::get().to(login_form))
+                    .route("", web::post().to(login))
+                    .route("/two-factor", web::get().to(two_factor_form))
+                    .route("/two-factor", web::post().to(verify_two_factor))
+                    .route("/forgot", web::get().to(forgot_password_form))
+                    .route("/forgot", web::post().to(request_password_reset))
+                    .route("/reset", web::get().to(reset_password_form))
+                    .route("/reset", web::post().to(reset_password))
+            )
             .service(
                 web::scope("/admin")
+                    // Anonymous requests are redirected to the login page before the CSRF check.
+                    .wrap(from_fn(reject_forged_requests))
                     .wrap(from_fn(reject_anonymous_users))
-                    .route("/dashboard", web::get().to(admin_dashboard))
-                    .route("/password", web::get().to(change_password_form))
-                    .route("/password", web::post().to(change_password))
-                    .route("/logout", web::post().to(log_out))
-                    .route("/newsletters", web::get().to(publish_newsletter_form))
-                    .route("/newsletters", web::post().to(publish_newsletter))
+                    .route("/dashboard", web::get().to(admin_dashboard).wrap(from_fn(require_viewer)))
+                    .route("/password", web::get().to(change_password_form).wrap(from_fn(require_viewer)))
+                    .route("/password", web::post().to(change_password).wrap(from_fn(require_viewer)))
+                    .route("/two-factor", web::get().to(two_factor_settings).wrap(from_fn(require_viewer)))
+                    .route("/two-factor/enable", web::post().to(enroll_two_factor).wrap(from_fn(require_viewer)))
+                    .route("/two-factor/disable", web::post().to(unenroll_two_factor).wrap(from_fn(require_viewer)))
+                    .route("/sessions", web::get().to(list_sessions).wrap(from_fn(require_viewer)))
+                    .route("/sessions/revoke-all", web::post().to(revoke_all_sessions).wrap(from_fn(require_viewer)))
+                    .route("/sessions/{session_id}/revoke", web::post().to(revoke_session).wrap(from_fn(require_viewer)))
+                    .route("/api-tokens", web::get().to(list_api_tokens).wrap(from_fn(require_editor)))
+                    .route("/api-tokens", web::post().to(create_api_token).wrap(from_fn(require_editor)))
+                    .route("/api-tokens/{api_token_id}/revoke", web::post().to(revoke_api_token).wrap(from_fn(require_editor)))
+                    .route("/logout", web::post().to(log_out).wrap(from_fn(require_viewer)))
+                    .route("/newsletters", web::get().to(publish_newsletter_form).wrap(from_fn(require_editor)))
+                    .route("/newsletters", web::post().to(publish_newsletter).wrap(from_fn(idempotent)).wrap(from_fn(require_editor)))
+                    .route("/newsletters/drafts", web::get().to(list_drafts).wrap(from_fn(require_viewer)))
+                    .route("/newsletters/drafts", web::post().to(create_draft).wrap(from_fn(require_editor)))
+                    .route("/newsletters/drafts/{newsletter_issue_id}", web::get().to(draft_form).wrap(from_fn(require_viewer)))
+                    .route("/newsletters/drafts/{newsletter_issue_id}", web::post().to(update_draft).wrap(from_fn(require_editor)))
+                    .route("/newsletters/drafts/{newsletter_issue_id}/preview", web::get().to(preview_draft).wrap(from_fn(require_viewer)))
+                    .route("/newsletters/drafts/{newsletter_issue_id}/delete", web::post().to(delete_draft).wrap(from_fn(require_editor)))
+                    .route("/newsletters/drafts/{newsletter_issue_id}/publish", web::post().to(publish_draft).wrap(from_fn(idempotent)).wrap(from_fn(require_editor)))
+                    .route("/newsletters/issues", web::get().to(newsletter_issues).wrap(from_fn(require_viewer)))
+                    .route("/newsletters/issues/{newsletter_issue_id}/reschedule", web::post().to(reschedule_newsletter_issue).wrap(from_fn(require_editor)))
+                    .route("/newsletters/issues/{newsletter_issue_id}/cancel", web::post().to(cancel_newsletter_issue).wrap(from_fn(require_editor)))
+                    .route("/deliveries", web::get().to(failed_deliveries).wrap(from_fn(require_viewer)))
+                    .route("/deliveries/requeue", web::post().to(requeue_failed_delivery).wrap(from_fn(require_editor)))
+                    .route("/users", web::get().to(list_users).wrap(from_fn(require_owner)))
+                    .route("/users", web::post().to(invite_user).wrap(from_fn(require_owner)))
+                    .route("/users/{user_id}/deactivate", web::post().to(deactivate_user).wrap(from_fn(require_owner)))
+                    .route("/users/{user_id}/activate", web::post().to(activate_user).wrap(from_fn(require_owner)))
+                    .route("/users/{user_id}/delete", web::post().to(delete_user).wrap(from_fn(require_owner)))
+                    .route("/login-attempts", web::get().to(failed_logins).wrap(from_fn(require_owner)))
+                    .route("/login-attempts/clear", web::post().to(clear_failed_login).wrap(from_fn(require_owner)))
+                    .route("/audit", web::get().to(audit_log).wrap(from_fn(require_owner)))
+                    .route("/audit/export", web::get().to(export_audit_log).wrap(from_fn(require_owner)))
             )
             .app_data(db_pool.clone())
             .app_data(email_client.clone())
             .app_data(base_url.clone())
+            .app_data(hmac_secret.clone())
+            .app_data(webhook_secret.clone())
+            .app_data(session_store.clone())
+            .app_data(login_throttle.clone())
+            .app_data(idempotency.clone())
+            .app_data(password_hashing.clone())
+            .app_data(trusted_proxies.clone())
     })
+    // Signals are handled by the caller, which also has background tasks to stop.
+    .disable_signals()
+    .shutdown_timeout(shutdown_timeout.as_secs())
     .listen(listener)?
     .run();
     Ok(server)
@@ -1,6 +1,8 @@
 use actix_web::http::header::LOCATION;
-use actix_web::HttpResponse;
+use crate::startup::TrustedProxies;
+use actix_web::{web, HttpRequest, HttpResponse};
 use std::fmt::{Debug, Display};
+use std::net::IpAddr;
 
 pub fn e500<T>(e: T) -> actix_web::Error
 where
@@ -9,7 +11,7 @@ where
     actix_web::error::ErrorInternalServerError(e)
 }
 
-pub fn e400<T: std::fmt::Debug + std::fmt::Display>(e: T) -> actix_web::Error
+pub fn e400<T>(e: T) -> actix_web::Error
 where
     T: std::fmt::Debug + std::fmt::Display + 'static,
 {
@@ -20,4 +22,98 @@ pub fn see_other(location: &str) -> HttpResponse {
     HttpResponse::SeeOther()
         .insert_header((LOCATION, location))
         .finish()
-}
\ No newline at end of file
+}
+
+pub fn html_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The client address. `X-Forwarded-For` is only looked at on connections from one of the
+/// `application.trusted_proxies`, since it is whatever the client wants it to be otherwise.
+pub fn client_ip(request: &HttpRequest) -> Option<String> {
+    let peer = request.peer_addr()?.ip();
+    let trusted_proxies = request
+        .app_data::<web::Data<TrustedProxies>>()
+        .map(|proxies| proxies.0.as_slice())
+        .unwrap_or_default();
+    let forwarded_for = request
+        .headers()
+        .get_all("X-Forwarded-For")
+        .filter_map(|value| value.to_str().ok())
+        .collect::<Vec<_>>()
+        .join(",");
+    Some(resolve_client_ip(peer, &forwarded_for, trusted_proxies).to_string())
+}
+
+/// Each proxy appends the address it got the request from, so the entries are read from the
+/// right and the first one that is not a trusted proxy is the client. Whatever is left of it
+/// may have been made up by the client.
+fn resolve_client_ip(peer: IpAddr, forwarded_for: &str, trusted_proxies: &[IpAddr]) -> IpAddr {
+    let mut client = peer;
+    for entry in forwarded_for.rsplit(',') {
+        if !trusted_proxies.contains(&client) {
+            break;
+        }
+        match entry.trim().parse() {
+            Ok(address) => client = address,
+            Err(_) => break,
+        }
+    }
+    client
+}
+
+#[cfg(test)]
+mod tests {
+    use super::resolve_client_ip;
+    use std::net::IpAddr;
+
+    fn ip(address: &str) -> IpAddr {
+        address.parse().unwrap()
+    }
+
+    #[test]
+    fn forwarded_for_is_ignored_without_a_trusted_proxy() {
+        assert_eq!(resolve_client_ip(ip("198.51.100.7"), "203.0.113.1", &[]), ip("198.51.100.7"));
+        assert_eq!(
+            resolve_client_ip(ip("198.51.100.7"), "203.0.113.1", &[ip("10.0.0.2")]),
+            ip("198.51.100.7")
+        );
+    }
+
+    #[test]
+    fn the_address_appended_by_a_trusted_proxy_is_the_client() {
+        let proxies = [ip("10.0.0.2")];
+        assert_eq!(resolve_client_ip(ip("10.0.0.2"), "203.0.113.1", &proxies), ip("203.0.113.1"));
+        // The leftmost entry came from the client itself.
+        assert_eq!(
+            resolve_client_ip(ip("10.0.0.2"), "192.0.2.99, 203.0.113.1", &proxies),
+            ip("203.0.113.1")
+        );
+    }
+
+    #[test]
+    fn chains_of_trusted_proxies_are_skipped() {
+        let proxies = [ip("10.0.0.2"), ip("10.0.0.3")];
+        assert_eq!(
+            resolve_client_ip(ip("10.0.0.2"), "192.0.2.99, 203.0.113.1, 10.0.0.3", &proxies),
+            ip("203.0.113.1")
+        );
+    }
+
+    #[test]
+    fn a_trusted_proxy_without_forwarded_for_is_the_client() {
+        assert_eq!(resolve_client_ip(ip("10.0.0.2"), "", &[ip("10.0.0.2")]), ip("10.0.0.2"));
+        assert_eq!(resolve_client_ip(ip("10.0.0.2"), "garbage", &[ip("10.0.0.2")]), ip("10.0.0.2"));
+    }
+}
@@ -0,0 +1,334 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp, TestUser};
+use uuid::Uuid;
+use zero2prod_my::authentication::{enable_two_factor, generate_totp_secret, ApiScope};
+
+async fn insert_subscriber(app: &TestApp, name: &str, email: &str, status: &str) -> Uuid {
+    let id = Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO subscriptions (id, email, name, subscribed_at, status) VALUES ($1, $2, $3, now(), $4)",
+        id,
+        email,
+        name,
+        status
+    )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    id
+}
+
+/// Publishes an issue through the API to the confirmed subscribers and returns its id.
+async fn publish_issue(app: &TestApp) -> Uuid {
+    let token = app.create_api_token(&ApiScope::ALL).await;
+    let response = app
+        .post_newsletters(
+            &token,
+            serde_json::json!({
+                "title": "Newsletter title",
+                "content": {
+                    "text": "Newsletter body as plain text",
+                    "html": "<p>Newsletter body as HTML</p>",
+                }
+            }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    let body: serde_json::Value = response.json().await.unwrap();
+    body["newsletter_issue_id"].as_str().unwrap().parse().unwrap()
+}
+
+#[tokio::test]
+async fn create_admin_adds_an_owner_who_can_log_in() {
+    // Arrange
+    let app = spawn_app().await;
+    let admin = TestUser::generate();
+
+    // Act
+    let report = app
+        .run_admin_cli(&["create-admin", "--username", &admin.username, "--password", &admin.password])
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(report.starts_with(&format!("Created the owner {}", admin.username)));
+    let role = sqlx::query!("SELECT role FROM users WHERE username = $1", admin.username)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .role;
+    assert_eq!(role, "owner");
+    let response = app.login_as(&admin).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let recorded = sqlx::query!("SELECT actor_id, target_id FROM audit_log WHERE action = 'create_admin'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(Some(recorded.actor_id), recorded.target_id);
+}
+
+#[tokio::test]
+async fn create_admin_refuses_a_taken_username() {
+    let app = spawn_app().await;
+
+    let result = app
+        .run_admin_cli(&["create-admin", "--username", &app.test_user.username, "--password", "whatever"])
+        .await;
+
+    assert!(result.unwrap_err().to_string().contains("already taken"));
+}
+
+#[tokio::test]
+async fn reset_admin_restores_access_to_a_locked_out_account() {
+    // Arrange
+    let app = spawn_app().await;
+    for _ in 0..app.login_throttle.max_failures_per_username {
+        app.post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": "wrong-password",
+        }))
+        .await;
+    }
+    sqlx::query!("UPDATE users SET is_active = false WHERE user_id = $1", app.test_user.user_id)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let new_password = Uuid::new_v4().to_string();
+
+    // Act
+    app.run_admin_cli(&["reset-admin", "--username", &app.test_user.username, "--password", &new_password])
+        .await
+        .unwrap();
+
+    // Assert
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn reset_admin_turns_off_two_factor_and_logs_out_every_session() {
+    // Arrange
+    let app = spawn_app().await;
+    app.post_test_user_login().await;
+    let mut transaction = app.db_pool.begin().await.unwrap();
+    enable_two_factor(app.test_user.user_id, &generate_totp_secret(), &mut transaction)
+        .await
+        .unwrap();
+    transaction.commit().await.unwrap();
+    let new_password = Uuid::new_v4().to_string();
+
+    // Act
+    app.run_admin_cli(&["reset-admin", "--username", &app.test_user.username, "--password", &new_password])
+        .await
+        .unwrap();
+
+    // Assert
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+    let user = sqlx::query!(
+        r#"SELECT totp_secret, (SELECT COUNT(*) FROM totp_recovery_codes WHERE user_id = $1) AS "recovery_codes!" FROM users WHERE user_id = $1"#,
+        app.test_user.user_id
+    )
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(user.totp_secret.is_none());
+    assert_eq!(user.recovery_codes, 0);
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let actions = sqlx::query!("SELECT action FROM audit_log WHERE actor_id = $1", app.test_user.user_id)
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(actions.iter().any(|r| r.action == "reset_admin"));
+}
+
+#[tokio::test]
+async fn reset_admin_changes_nothing_without_a_password() {
+    let app = spawn_app().await;
+    sqlx::query!("UPDATE users SET is_active = false WHERE user_id = $1", app.test_user.user_id)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let result = app
+        .run_admin_cli(&["reset-admin", "--username", &app.test_user.username, "--password", ""])
+        .await;
+
+    assert!(result.is_err());
+    let is_active = sqlx::query!("SELECT is_active FROM users WHERE user_id = $1", app.test_user.user_id)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .is_active;
+    assert!(!is_active);
+}
+
+#[tokio::test]
+async fn reset_admin_fails_for_an_unknown_user() {
+    let app = spawn_app().await;
+
+    let result = app
+        .run_admin_cli(&["reset-admin", "--username", "nobody", "--password", "whatever"])
+        .await;
+
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn subscribers_can_be_listed_by_status_and_searched() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_subscriber(&app, "Ursula Le Guin", "ursula@example.com", "confirmed").await;
+    insert_subscriber(&app, "Octavia Butler", "octavia@example.com", "pending_confirmation").await;
+    insert_subscriber(&app, "Percent 100%", "percent@example.com", "confirmed").await;
+
+    // Act
+    let confirmed = app
+        .run_admin_cli(&["subscribers", "list", "--status", "confirmed"])
+        .await
+        .unwrap();
+    let found = app.run_admin_cli(&["subscribers", "search", "OCTAVIA"]).await.unwrap();
+    let literal_percent = app.run_admin_cli(&["subscribers", "search", "0%"]).await.unwrap();
+
+    // Assert
+    assert!(confirmed.contains("ursula@example.com"));
+    assert!(!confirmed.contains("octavia@example.com"));
+    assert_eq!(found.lines().count(), 2);
+    assert!(found.contains("octavia@example.com\tOctavia Butler\tpending_confirmation"));
+    assert_eq!(literal_percent.lines().count(), 2);
+    assert!(literal_percent.contains("percent@example.com"));
+}
+
+#[tokio::test]
+async fn queued_deliveries_can_be_inspected() {
+    let app = spawn_app().await;
+    insert_subscriber(&app, "Ursula Le Guin", "ursula@example.com", "confirmed").await;
+    let issue_id = publish_issue(&app).await;
+
+    let stats = app.run_admin_cli(&["queue", "stats"]).await.unwrap();
+    let list = app
+        .run_admin_cli(&["queue", "list", "--issue", &issue_id.to_string()])
+        .await
+        .unwrap();
+
+    assert!(stats.contains(&format!("{}\tNewsletter title\t1\t0\t0", issue_id)));
+    assert!(list.contains(&format!("{}\tursula@example.com\t0\t", issue_id)));
+}
+
+#[tokio::test]
+async fn requeue_revives_dead_letters_and_resets_backed_off_deliveries() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_subscriber(&app, "Ursula Le Guin", "ursula@example.com", "confirmed").await;
+    insert_subscriber(&app, "Octavia Butler", "octavia@example.com", "confirmed").await;
+    let issue_id = publish_issue(&app).await;
+    // One delivery gave up, the other one is waiting for its next attempt.
+    sqlx::query!(
+        r#"
+WITH failed AS (
+    DELETE FROM issue_delivery_queue WHERE subscriber_email = 'ursula@example.com' RETURNING *
+)
+INSERT INTO issue_delivery_dead_letters (newsletter_issue_id, subscriber_email, n_attempts, last_error, failed_at)
+SELECT newsletter_issue_id, subscriber_email, 5, 'Mailbox full', now() FROM failed
+"#
+    )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    sqlx::query!("UPDATE issue_delivery_queue SET n_attempts = 3, execute_after = now() + interval '1 hour'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let dead_letters = app.run_admin_cli(&["queue", "dead-letters"]).await.unwrap();
+    assert!(dead_letters.contains("ursula@example.com\t5\t"));
+    assert!(dead_letters.contains("Mailbox full"));
+
+    // Act
+    let report = app
+        .run_admin_cli(&["queue", "requeue", "--issue", &issue_id.to_string()])
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(report.trim(), "Re-queued 1 dead letters and made 1 backed-off deliveries due now.");
+    let queue = sqlx::query!("SELECT n_attempts, execute_after <= now() AS \"due!\" FROM issue_delivery_queue")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queue.len(), 2);
+    assert!(queue.iter().all(|task| task.n_attempts == 0 && task.due));
+    let dead_letters = sqlx::query!("SELECT count(*) AS \"count!\" FROM issue_delivery_dead_letters")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(dead_letters.count, 0);
+}
+
+#[tokio::test]
+async fn idempotency_purge_deletes_the_expired_records() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app.create_api_token(&ApiScope::ALL).await;
+    for _ in 0..2 {
+        let response = app
+            .post_newsletters_with_idempotency_key(
+                &token,
+                &Uuid::new_v4().to_string(),
+                serde_json::json!({
+                    "title": "Newsletter title",
+                    "content": {
+                        "text": "Newsletter body as plain text",
+                        "html": "<p>Newsletter body as HTML</p>",
+                    }
+                }),
+            )
+            .await;
+        assert_eq!(response.status().as_u16(), 202);
+    }
+    sqlx::query!(
+        "UPDATE idempotency SET created_at = now() - interval '2 hours' WHERE ctid IN (SELECT ctid FROM idempotency LIMIT 1)"
+    )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let report = app
+        .run_admin_cli(&["idempotency", "purge", "--older-than-seconds", "3600"])
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(report.trim(), "Purged 1 idempotency records.");
+}
+
+#[tokio::test]
+async fn migrate_is_a_no_op_on_an_up_to_date_database() {
+    let app = spawn_app().await;
+
+    let report = app.run_admin_cli(&["migrate"]).await.unwrap();
+
+    assert_eq!(report.trim(), "The database is up to date.");
+}
+
+#[tokio::test]
+async fn the_seeded_admin_account_is_gone() {
+    let app = spawn_app().await;
+
+    let seeded = sqlx::query!("SELECT user_id FROM users WHERE username = 'admin'")
+        .fetch_optional(&app.db_pool)
+        .await
+        .unwrap();
+
+    assert!(seeded.is_none());
+}
@@ -0,0 +1,143 @@
+use crate::helpers::{assert_is_redirect_to, csrf_token, spawn_app, TestApp, TestUser};
+use uuid::Uuid;
+
+/// A separate browser, logged in as `user`.
+async fn log_in_from_another_browser(app: &TestApp, user: &TestUser, user_agent: &str) -> reqwest::Client {
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .user_agent(user_agent)
+        .build()
+        .unwrap();
+    let response = client
+        .post(format!("{}/login", &app.address))
+        .form(&serde_json::json!({
+            "username": &user.username,
+            "password": &user.password,
+            "csrf_token": csrf_token(&client, &app.address).await
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    client
+}
+
+async fn get_dashboard(app: &TestApp, client: &reqwest::Client) -> reqwest::Response {
+    client
+        .get(format!("{}/admin/dashboard", &app.address))
+        .send()
+        .await
+        .unwrap()
+}
+
+fn revocable_session_ids(html_page: &str) -> Vec<String> {
+    html_page
+        .split(r#"<form action="/admin/sessions/"#)
+        .skip(1)
+        .filter_map(|s| s.split('"').next().unwrap().strip_suffix("/revoke"))
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+#[tokio::test]
+async fn the_sessions_page_lists_where_you_are_logged_in() {
+    // Arrange
+    let app = spawn_app().await;
+    app.post_test_user_login().await;
+    log_in_from_another_browser(&app, &app.test_user, "Firefox on the office laptop").await;
+
+    // Act
+    let html_page = app.get_sessions_html().await;
+
+    // Assert
+    assert!(html_page.contains("(this session)"));
+    assert!(html_page.contains("<td>Firefox on the office laptop</td>"));
+    assert!(html_page.contains("<td>127.0.0.1</td>"));
+    assert_eq!(revocable_session_ids(&html_page).len(), 1);
+}
+
+#[tokio::test]
+async fn other_users_sessions_are_not_listed() {
+    let app = spawn_app().await;
+    let other_user = TestUser::generate_with_role("viewer");
+    other_user.store(&app.db_pool).await;
+    log_in_from_another_browser(&app, &other_user, "Someone else").await;
+    app.post_test_user_login().await;
+
+    let html_page = app.get_sessions_html().await;
+
+    assert!(!html_page.contains("Someone else"));
+    assert!(revocable_session_ids(&html_page).is_empty());
+}
+
+#[tokio::test]
+async fn a_revoked_session_is_logged_out() {
+    // Arrange
+    let app = spawn_app().await;
+    app.post_test_user_login().await;
+    let stolen = log_in_from_another_browser(&app, &app.test_user, "Stolen cookie").await;
+    let html_page = app.get_sessions_html().await;
+    let session_id = revocable_session_ids(&html_page).pop().unwrap();
+
+    // Act
+    let response = app.post_revoke_session(&session_id).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/sessions");
+    let html_page = app.get_sessions_html().await;
+    assert!(html_page.contains("The session has been revoked."));
+    assert!(!html_page.contains("Stolen cookie"));
+    assert_is_redirect_to(&get_dashboard(&app, &stolen).await, "/login");
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn an_unknown_session_cannot_be_revoked() {
+    let app = spawn_app().await;
+    app.post_test_user_login().await;
+
+    let response = app.post_revoke_session(&Uuid::new_v4().to_string()).await;
+
+    assert_is_redirect_to(&response, "/admin/sessions");
+    let html_page = app.get_sessions_html().await;
+    assert!(html_page.contains("The session could not be found"));
+}
+
+#[tokio::test]
+async fn log_out_everywhere_ends_every_session() {
+    let app = spawn_app().await;
+    app.post_test_user_login().await;
+    let other_browser = log_in_from_another_browser(&app, &app.test_user, "Phone").await;
+
+    let response = app.post_revoke_all_sessions().await;
+
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("You have been logged out of every session."));
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+    assert_is_redirect_to(&get_dashboard(&app, &other_browser).await, "/login");
+}
+
+#[tokio::test]
+async fn changing_your_password_logs_out_your_other_sessions() {
+    // Arrange
+    let app = spawn_app().await;
+    app.post_test_user_login().await;
+    let other_browser = log_in_from_another_browser(&app, &app.test_user, "Phone").await;
+    let new_password = Uuid::new_v4().to_string();
+
+    // Act
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/password");
+    assert_is_redirect_to(&get_dashboard(&app, &other_browser).await, "/login");
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+}
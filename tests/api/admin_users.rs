@@ -0,0 +1,211 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestUser};
+use uuid::Uuid;
+
+#[tokio::test]
+async fn only_owners_can_manage_users() {
+    // Arrange
+    let app = spawn_app().await;
+    for role in ["viewer", "editor"] {
+        let user = TestUser::generate_with_role(role);
+        user.store(&app.db_pool).await;
+        app.login_as(&user).await;
+
+        // Act
+        let list = app.get_users().await;
+        let invite = app
+            .post_invite_user(&serde_json::json!({"username": "mallory", "role": "owner"}))
+            .await;
+
+        // Assert
+        assert_eq!(list.status().as_u16(), 403);
+        assert_eq!(invite.status().as_u16(), 403);
+        app.post_logout().await;
+    }
+}
+
+#[tokio::test]
+async fn viewers_cannot_publish_newsletters() {
+    // Arrange
+    let app = spawn_app().await;
+    let viewer = TestUser::generate_with_role("viewer");
+    viewer.store(&app.db_pool).await;
+    app.login_as(&viewer).await;
+
+    // Act
+    let dashboard = app.get_admin_dashboard().await;
+    let publish = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(dashboard.status().as_u16(), 200);
+    assert_eq!(publish.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn editors_can_publish_newsletters() {
+    // Arrange
+    let app = spawn_app().await;
+    let editor = TestUser::generate_with_role("editor");
+    editor.store(&app.db_pool).await;
+    app.login_as(&editor).await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+}
+
+#[tokio::test]
+async fn invited_users_can_log_in_with_their_temporary_password() {
+    // Arrange
+    let app = spawn_app().await;
+    app.post_test_user_login().await;
+
+    // Act - Part 1 - Invite
+    let response = app
+        .post_invite_user(&serde_json::json!({"username": "ursula", "role": "editor"}))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    let password = html_page
+        .split(r#"<code id="temporary-password">"#)
+        .nth(1)
+        .and_then(|rest| rest.split("</code>").next())
+        .unwrap()
+        .to_owned();
+
+    // Act - Part 2 - The new user shows up in the list
+    let html_page = app.get_users_html().await;
+    assert!(html_page.contains("ursula"));
+    assert!(html_page.contains("editor"));
+
+    // Act - Part 3 - Log in as the new user
+    app.post_logout().await;
+    let response = app
+        .post_login(&serde_json::json!({"username": "ursula", "password": password}))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains("You are signed in as editor."));
+}
+
+#[tokio::test]
+async fn usernames_must_be_unique() {
+    // Arrange
+    let app = spawn_app().await;
+    app.post_test_user_login().await;
+
+    // Act
+    let response = app
+        .post_invite_user(&serde_json::json!({
+            "username": &app.test_user.username,
+            "role": "viewer"
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/users");
+    let html_page = app.get_users_html().await;
+    assert!(html_page.contains("is already taken."));
+}
+
+#[tokio::test]
+async fn deactivated_users_are_logged_out_and_cannot_log_back_in() {
+    // Arrange
+    let app = spawn_app().await;
+    let editor = TestUser::generate_with_role("editor");
+    editor.store(&app.db_pool).await;
+    app.login_as(&editor).await;
+
+    // Act - Part 1 - Deactivate the editor behind their back
+    sqlx::query!("UPDATE users SET is_active = false WHERE user_id = $1", editor.user_id)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act - Part 2 - Their session no longer works
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Act - Part 3 - Nor do their credentials
+    let response = app.login_as(&editor).await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn owners_can_deactivate_and_delete_other_users() {
+    // Arrange
+    let app = spawn_app().await;
+    let viewer = TestUser::generate_with_role("viewer");
+    viewer.store(&app.db_pool).await;
+    app.post_test_user_login().await;
+
+    // Act - Part 1 - Deactivate
+    let response = app.post_user_action(viewer.user_id, "deactivate").await;
+    assert_is_redirect_to(&response, "/admin/users");
+    let html_page = app.get_users_html().await;
+    assert!(html_page.contains("<p><i>The user has been deactivated.</i></p>"));
+    assert!(html_page.contains("deactivated"));
+
+    // Act - Part 2 - Delete
+    let response = app.post_user_action(viewer.user_id, "delete").await;
+    assert_is_redirect_to(&response, "/admin/users");
+    let html_page = app.get_users_html().await;
+    assert!(html_page.contains("<p><i>The user has been deleted.</i></p>"));
+    assert!(!html_page.contains(&viewer.username));
+}
+
+#[tokio::test]
+async fn owners_cannot_lock_themselves_out() {
+    // Arrange
+    let app = spawn_app().await;
+    app.post_test_user_login().await;
+
+    // Act
+    app.post_user_action(app.test_user.user_id, "deactivate").await;
+    app.post_user_action(app.test_user.user_id, "delete").await;
+
+    // Assert
+    let response = app.get_users().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("You cannot delete your own account."));
+}
+
+#[tokio::test]
+async fn invited_users_can_be_given_an_email_address_for_password_resets() {
+    // Arrange
+    let app = spawn_app().await;
+    app.post_test_user_login().await;
+
+    // Act
+    let response = app
+        .post_invite_user(&serde_json::json!({
+            "username": "ursula",
+            "email": "ursula@example.com",
+            "role": "viewer"
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let stored = sqlx::query!("SELECT email FROM users WHERE username = 'ursula'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(stored.email.as_deref(), Some("ursula@example.com"));
+}
@@ -0,0 +1,202 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp, TestUser};
+use crate::newsletter::create_confirmed_subscriber;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod_my::authentication::ApiScope;
+
+fn newsletter_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    })
+}
+
+fn extract_api_token(html_page: &str) -> String {
+    let start = html_page.find(r#"<code id="api-token">"#).unwrap() + r#"<code id="api-token">"#.len();
+    let end = start + html_page[start..].find("</code>").unwrap();
+    html_page[start..end].to_owned()
+}
+
+fn revocable_token_ids(html_page: &str) -> Vec<String> {
+    html_page
+        .split(r#"<form action="/admin/api-tokens/"#)
+        .skip(1)
+        .filter_map(|s| s.split('"').next().unwrap().strip_suffix("/revoke"))
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+async fn create_token_from_the_dashboard(app: &TestApp, name: &str) -> String {
+    let response = app
+        .post_create_api_token(&[
+            ("name", name),
+            ("scopes", "newsletters:publish"),
+            ("expires_in_days", "30"),
+        ])
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    extract_api_token(&response.text().await.unwrap())
+}
+
+#[tokio::test]
+async fn a_token_created_from_the_dashboard_can_publish_newsletters() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.post_test_user_login().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let token = create_token_from_the_dashboard(&app, "CI pipeline").await;
+    let response = app.post_newsletters(&token, newsletter_body()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+    let html_page = app.get_api_tokens_html().await;
+    assert!(html_page.contains("<td>CI pipeline</td>"));
+    assert!(html_page.contains("<td>newsletters:publish</td>"));
+    assert!(!html_page.contains(&token));
+    assert!(!html_page.contains("never used"));
+}
+
+#[tokio::test]
+async fn tokens_are_stored_hashed() {
+    let app = spawn_app().await;
+    app.post_test_user_login().await;
+
+    let token = create_token_from_the_dashboard(&app, "CI pipeline").await;
+
+    let stored = sqlx::query!("SELECT token_hash FROM api_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_ne!(stored.token_hash, token);
+    assert!(!stored.token_hash.contains(&token));
+}
+
+#[tokio::test]
+async fn a_revoked_token_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.post_test_user_login().await;
+    let token = create_token_from_the_dashboard(&app, "Old laptop").await;
+    let token_ids = revocable_token_ids(&app.get_api_tokens_html().await);
+    assert_eq!(token_ids.len(), 1);
+
+    // Act
+    let response = app.post_revoke_api_token(&token_ids[0]).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/api-tokens");
+    let html_page = app.get_api_tokens_html().await;
+    assert!(html_page.contains("The API token has been revoked."));
+    assert!(html_page.contains("<td>revoked</td>"));
+    let response = app.post_newsletters(&token, newsletter_body()).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn an_expired_token_is_rejected() {
+    let app = spawn_app().await;
+    let token = app.create_api_token(&ApiScope::ALL).await;
+    sqlx::query!("UPDATE api_tokens SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app.post_newsletters(&token, newsletter_body()).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn a_token_without_the_publish_scope_is_forbidden() {
+    let app = spawn_app().await;
+    let token = app.create_api_token(&[]).await;
+
+    let response = app.post_newsletters(&token, newsletter_body()).await;
+
+    assert_eq!(response.status().as_u16(), 403);
+    assert!(response.headers()["WWW-Authenticate"]
+        .to_str()
+        .unwrap()
+        .contains(r#"scope="newsletters:publish""#));
+}
+
+#[tokio::test]
+async fn tokens_stop_working_when_their_owner_loses_the_role() {
+    let app = spawn_app().await;
+    let token = app.create_api_token(&ApiScope::ALL).await;
+    sqlx::query!(
+        "UPDATE users SET role = 'viewer' WHERE user_id = $1",
+        app.test_user.user_id
+    )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app.post_newsletters(&token, newsletter_body()).await;
+
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn tokens_of_deactivated_users_are_rejected() {
+    let app = spawn_app().await;
+    let token = app.create_api_token(&ApiScope::ALL).await;
+    sqlx::query!(
+        "UPDATE users SET is_active = false WHERE user_id = $1",
+        app.test_user.user_id
+    )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app.post_newsletters(&token, newsletter_body()).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn a_token_needs_a_name_and_a_scope() {
+    let app = spawn_app().await;
+    app.post_test_user_login().await;
+
+    let response = app
+        .post_create_api_token(&[("name", " "), ("scopes", "newsletters:publish"), ("expires_in_days", "")])
+        .await;
+    assert_is_redirect_to(&response, "/admin/api-tokens");
+    assert!(app.get_api_tokens_html().await.contains("Please give the token a name."));
+
+    let response = app
+        .post_create_api_token(&[("name", "No scopes"), ("expires_in_days", "")])
+        .await;
+    assert_is_redirect_to(&response, "/admin/api-tokens");
+    assert!(app.get_api_tokens_html().await.contains("Please pick at least one scope."));
+}
+
+#[tokio::test]
+async fn viewers_cannot_manage_tokens() {
+    let app = spawn_app().await;
+    let viewer = TestUser::generate_with_role("viewer");
+    viewer.store(&app.db_pool).await;
+    app.login_as(&viewer).await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/admin/api-tokens", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 403);
+}
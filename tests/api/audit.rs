@@ -0,0 +1,268 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp, TestUser};
+use uuid::Uuid;
+use zero2prod_my::audit::{record_audit_event, AuditAction};
+use zero2prod_my::authentication::ApiScope;
+
+async fn recorded_actions(app: &TestApp) -> Vec<(Uuid, String, Option<Uuid>)> {
+    sqlx::query!("SELECT actor_id, action, target_id FROM audit_log ORDER BY audit_log_id")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| (r.actor_id, r.action, r.target_id))
+        .collect()
+}
+
+async fn only_issue_id(app: &TestApp) -> Uuid {
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+}
+
+#[tokio::test]
+async fn administrative_actions_are_recorded() {
+    // Arrange
+    let app = spawn_app().await;
+    let user_id = app.test_user.user_id;
+    let new_password = Uuid::new_v4().to_string();
+
+    // Act
+    app.post_test_user_login().await;
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/password");
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.post_logout().await;
+
+    // Assert
+    let issue_id = only_issue_id(&app).await;
+    assert_eq!(
+        recorded_actions(&app).await,
+        vec![
+            (user_id, "log_in".to_string(), None),
+            (user_id, "change_password".to_string(), None),
+            (user_id, "publish_newsletter".to_string(), Some(issue_id)),
+            (user_id, "log_out".to_string(), None),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn managing_users_is_recorded_with_the_affected_user() {
+    // Arrange
+    let app = spawn_app().await;
+    let user_id = app.test_user.user_id;
+    app.post_test_user_login().await;
+
+    // Act
+    let response = app
+        .post_invite_user(&serde_json::json!({"username": "ursula", "role": "editor"}))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let invited_user_id = sqlx::query!("SELECT user_id FROM users WHERE username = 'ursula'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .user_id;
+    for action in ["deactivate", "activate", "delete"] {
+        let response = app.post_user_action(invited_user_id, action).await;
+        assert_is_redirect_to(&response, "/admin/users");
+    }
+    // Nothing happened, so there is nothing to record.
+    app.post_user_action(invited_user_id, "delete").await;
+
+    // Assert
+    assert_eq!(
+        recorded_actions(&app).await,
+        vec![
+            (user_id, "log_in".to_string(), None),
+            (user_id, "invite_user".to_string(), Some(invited_user_id)),
+            (user_id, "deactivate_user".to_string(), Some(invited_user_id)),
+            (user_id, "activate_user".to_string(), Some(invited_user_id)),
+            (user_id, "delete_user".to_string(), Some(invited_user_id)),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn publishing_through_the_api_is_recorded_for_the_token_owner() {
+    let app = spawn_app().await;
+    let token = app.create_api_token(&ApiScope::ALL).await;
+
+    let response = app
+        .post_newsletters(
+            &token,
+            serde_json::json!({
+                "title": "Newsletter title",
+                "content": {
+                    "text": "Newsletter body as plain text",
+                    "html": "<p>Newsletter body as HTML</p>",
+                }
+            }),
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 202);
+    let issue_id = only_issue_id(&app).await;
+    assert_eq!(
+        recorded_actions(&app).await,
+        vec![(app.test_user.user_id, "publish_newsletter".to_string(), Some(issue_id))]
+    );
+}
+
+#[tokio::test]
+async fn failed_logins_are_not_recorded() {
+    let app = spawn_app().await;
+
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": "wrong-password",
+    }))
+    .await;
+
+    assert!(recorded_actions(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn the_audit_log_can_be_filtered_by_user_and_action() {
+    // Arrange
+    let app = spawn_app().await;
+    let editor = TestUser::generate_with_role("editor");
+    editor.store(&app.db_pool).await;
+    let target_id = Uuid::new_v4();
+    record_audit_event(&app.db_pool, editor.user_id, AuditAction::PublishNewsletter, Some(target_id), None)
+        .await
+        .unwrap();
+    app.post_test_user_login().await;
+
+    // Act
+    let by_editor = app.get_audit_log_html(&format!("actor={}", editor.username)).await;
+    let log_ins = app.get_audit_log_html("action=log_in").await;
+
+    // Assert
+    assert!(by_editor.contains(&target_id.to_string()));
+    assert!(!by_editor.contains(&app.test_user.username));
+    assert!(log_ins.contains(&app.test_user.username));
+    assert!(!log_ins.contains(&target_id.to_string()));
+}
+
+#[tokio::test]
+async fn the_audit_log_can_be_filtered_by_date() {
+    let app = spawn_app().await;
+    app.post_test_user_login().await;
+    let today = chrono::Utc::now().date_naive();
+    let yesterday = today.pred_opt().unwrap();
+
+    let until_yesterday = app.get_audit_log_html(&format!("to={}", yesterday)).await;
+    let from_today = app.get_audit_log_html(&format!("from={}&to={}", today, today)).await;
+
+    assert!(until_yesterday.contains("No matching entries."));
+    assert!(from_today.contains("<td>log_in</td>"));
+}
+
+#[tokio::test]
+async fn the_audit_log_is_paginated() {
+    // Arrange
+    let app = spawn_app().await;
+    for _ in 0..60 {
+        record_audit_event(&app.db_pool, app.test_user.user_id, AuditAction::ChangePassword, None, None)
+            .await
+            .unwrap();
+    }
+    app.post_test_user_login().await;
+
+    // Act
+    let first_page = app.get_audit_log_html("").await;
+    let second_page = app.get_audit_log_html("page=2").await;
+
+    // Assert
+    // The 60 password changes and the login.
+    assert_eq!(first_page.matches("<tr>").count() - 1, 50);
+    assert!(first_page.contains("Older -&gt;"));
+    assert!(!first_page.contains("&lt;- Newer"));
+    assert_eq!(second_page.matches("<tr>").count() - 1, 11);
+    assert!(second_page.contains("&lt;- Newer"));
+    assert!(!second_page.contains("Older -&gt;"));
+}
+
+#[tokio::test]
+async fn the_audit_log_can_be_exported_as_csv() {
+    // Arrange
+    let app = spawn_app().await;
+    let prankster = TestUser {
+        username: "=HYPERLINK(\"http://evil.example\")".to_string(),
+        ..TestUser::generate_with_role("viewer")
+    };
+    prankster.store(&app.db_pool).await;
+    record_audit_event(&app.db_pool, prankster.user_id, AuditAction::LogIn, None, Some("10.0.0.1"))
+        .await
+        .unwrap();
+    app.post_test_user_login().await;
+
+    // Act
+    let response = app.get_audit_log_export("action=log_in").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["Content-Type"], "text/csv; charset=utf-8");
+    assert!(response.headers()["Content-Disposition"]
+        .to_str()
+        .unwrap()
+        .starts_with("attachment"));
+    let csv = response.text().await.unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0], "occurred_at,actor_id,actor,action,target_id,ip");
+    assert!(lines[1].contains(&format!(",{},{},log_in,,", app.test_user.user_id, app.test_user.username)));
+    assert!(lines[2].contains(r#","'=HYPERLINK(""http://evil.example"")",log_in,,10.0.0.1"#));
+}
+
+#[tokio::test]
+async fn an_invalid_filter_is_rejected() {
+    let app = spawn_app().await;
+    app.post_test_user_login().await;
+
+    assert_eq!(app.get_audit_log("from=yesterday").await.status().as_u16(), 400);
+    assert_eq!(app.get_audit_log("action=launch_missiles").await.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn only_owners_can_see_the_audit_log() {
+    let app = spawn_app().await;
+    let editor = TestUser::generate_with_role("editor");
+    editor.store(&app.db_pool).await;
+    app.login_as(&editor).await;
+
+    assert_eq!(app.get_audit_log("").await.status().as_u16(), 403);
+    assert_eq!(app.get_audit_log_export("").await.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn the_audit_log_is_append_only() {
+    let app = spawn_app().await;
+    app.post_test_user_login().await;
+
+    let update = sqlx::query!("UPDATE audit_log SET action = 'nothing_to_see_here'")
+        .execute(&app.db_pool)
+        .await;
+    let delete = sqlx::query!("DELETE FROM audit_log").execute(&app.db_pool).await;
+
+    assert!(update.is_err());
+    assert!(delete.is_err());
+    assert_eq!(recorded_actions(&app).await.len(), 1);
+}
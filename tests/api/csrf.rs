@@ -0,0 +1,127 @@
+use crate::helpers::{assert_is_redirect_to, build_api_client, csrf_token, spawn_app, TestApp};
+
+async fn send(request: reqwest::RequestBuilder) -> reqwest::Response {
+    request.send().await.expect("Failed to execute request.")
+}
+
+fn logout_request(app: &TestApp) -> reqwest::RequestBuilder {
+    app.api_client.post(format!("{}/admin/logout", &app.address))
+}
+
+#[tokio::test]
+async fn admin_posts_without_a_token_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.post_test_user_login().await;
+
+    // Act
+    let response = send(logout_request(&app)).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn admin_posts_with_a_wrong_token_are_rejected() {
+    let app = spawn_app().await;
+    app.post_test_user_login().await;
+
+    let response = send(logout_request(&app).form(&serde_json::json!({"csrf_token": "not-the-token"}))).await;
+
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn a_token_from_another_session_is_rejected() {
+    let app = spawn_app().await;
+    let other_browser = build_api_client();
+    let foreign_token = csrf_token(&other_browser, &app.address).await;
+    app.post_test_user_login().await;
+
+    let response = send(logout_request(&app).header("X-CSRF-Token", foreign_token)).await;
+
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn the_token_can_be_sent_as_a_form_field() {
+    let app = spawn_app().await;
+    app.post_test_user_login().await;
+    let html_page = app.get_admin_dashboard_html().await;
+    let token = app.csrf_token().await;
+    assert!(html_page.contains(&format!(r#"name="csrf_token" value="{}""#, token)));
+
+    let response = send(logout_request(&app).form(&serde_json::json!({"csrf_token": token}))).await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn requests_from_a_foreign_origin_are_rejected_even_with_a_valid_token() {
+    let app = spawn_app().await;
+    app.post_test_user_login().await;
+
+    for (header, value) in [
+        ("Origin", "https://evil.example.com"),
+        ("Origin", "null"),
+        ("Referer", "https://evil.example.com/attack.html"),
+    ] {
+        let response = send(
+            logout_request(&app)
+                .header("X-CSRF-Token", app.csrf_token().await)
+                .header(header, value),
+        )
+        .await;
+        assert_eq!(response.status().as_u16(), 403, "{}: {}", header, value);
+    }
+
+    let response = send(
+        logout_request(&app)
+            .header("X-CSRF-Token", app.csrf_token().await)
+            .header("Origin", &app.address),
+    )
+    .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn login_without_a_token_is_rejected() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .post(format!("{}/login", &app.address))
+        .form(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 403);
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+}
+
+#[tokio::test]
+async fn the_token_changes_when_logging_in() {
+    let app = spawn_app().await;
+    let anonymous_token = app.csrf_token().await;
+
+    app.post_test_user_login().await;
+
+    assert_ne!(app.csrf_token().await, anonymous_token);
+    let response = send(logout_request(&app).header("X-CSRF-Token", anonymous_token)).await;
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn anonymous_admin_posts_are_still_redirected_to_login() {
+    let app = spawn_app().await;
+
+    let response = send(logout_request(&app)).await;
+
+    assert_is_redirect_to(&response, "/login");
+}
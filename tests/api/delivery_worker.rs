@@ -0,0 +1,228 @@
+use crate::helpers::{spawn_app, TestApp};
+use crate::newsletter::create_confirmed_subscriber;
+use sqlx::postgres::PgListener;
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+use wiremock::matchers::{body_partial_json, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod_my::authentication::ApiScope;
+use zero2prod_my::issue_delivery_worker::run_worker_until_stopped;
+use zero2prod_my::shutdown::{shutdown_channel, ShutdownTrigger};
+
+/// Starts a worker that would not look at the queue again for `poll_interval` on its own.
+async fn start_worker(
+    app: &TestApp,
+    poll_interval_seconds: u64,
+    concurrency: usize,
+) -> (ShutdownTrigger, JoinHandle<Result<(), anyhow::Error>>) {
+    let mut configuration = app.configuration.clone();
+    configuration.worker.queue_poll_interval_seconds = poll_interval_seconds;
+    configuration.worker.concurrency = concurrency;
+    let (trigger, shutdown) = shutdown_channel();
+    let worker = tokio::spawn(run_worker_until_stopped(configuration, shutdown));
+    // Long enough to find the queue empty and start waiting.
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    (trigger, worker)
+}
+
+async fn publish_newsletter(app: &TestApp) {
+    let token = app.create_api_token(&ApiScope::ALL).await;
+    let response = app
+        .post_newsletters(
+            &token,
+            serde_json::json!({
+                "title": "Newsletter title",
+                "content": {
+                    "text": "Newsletter body as plain text",
+                    "html": "<p>Newsletter body as HTML</p>",
+                }
+            }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+}
+
+/// Panics if the queue is not empty after `timeout`.
+async fn wait_for_empty_queue(app: &TestApp, timeout: Duration) {
+    wait_for_queue_length(app, 0, timeout).await
+}
+
+/// Panics if the queue does not come down to `length` tasks within `timeout`.
+async fn wait_for_queue_length(app: &TestApp, length: i64, timeout: Duration) {
+    let start = Instant::now();
+    loop {
+        let pending = sqlx::query!("SELECT count(*) AS \"count!\" FROM issue_delivery_queue")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap()
+            .count;
+        if pending == length {
+            return;
+        }
+        assert!(start.elapsed() < timeout, "{} tasks are still queued", pending);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+/// Inserts `n` confirmed subscribers directly, much faster than going through the API.
+async fn create_confirmed_subscribers(app: &TestApp, n: usize) -> Vec<String> {
+    let mut emails = Vec::with_capacity(n);
+    for i in 0..n {
+        let email = format!("subscriber-{}@example.com", i);
+        sqlx::query!(
+            "INSERT INTO subscriptions (id, email, name, subscribed_at, status) VALUES ($1, $2, $3, now(), 'confirmed')",
+            Uuid::new_v4(),
+            email,
+            format!("Subscriber {}", i)
+        )
+            .execute(&app.db_pool)
+            .await
+            .unwrap();
+        emails.push(email);
+    }
+    emails
+}
+
+async fn mount_email_server(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+}
+
+#[tokio::test]
+async fn an_idle_worker_is_woken_up_by_new_tasks() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    mount_email_server(&app).await;
+    let (_trigger, _worker) = start_worker(&app, 600, 1).await;
+
+    publish_newsletter(&app).await;
+
+    wait_for_empty_queue(&app, Duration::from_secs(5)).await;
+}
+
+#[tokio::test]
+async fn the_worker_listens_again_after_losing_its_connection() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    mount_email_server(&app).await;
+    let (_trigger, _worker) = start_worker(&app, 600, 1).await;
+    let terminated = sqlx::query!(
+        r#"
+SELECT pg_terminate_backend(pid) AS "terminated!"
+FROM pg_stat_activity
+WHERE datname = current_database() AND query LIKE 'LISTEN%'
+"#
+    )
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(terminated.len(), 1);
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    // Act
+    publish_newsletter(&app).await;
+
+    // Assert
+    wait_for_empty_queue(&app, Duration::from_secs(5)).await;
+}
+
+#[tokio::test]
+async fn the_worker_still_polls_the_queue_without_notifications() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    mount_email_server(&app).await;
+    sqlx::query!("ALTER TABLE issue_delivery_queue DISABLE TRIGGER issue_delivery_queue_notify_on_insert")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let (_trigger, _worker) = start_worker(&app, 1, 1).await;
+
+    publish_newsletter(&app).await;
+
+    wait_for_empty_queue(&app, Duration::from_secs(5)).await;
+}
+
+#[tokio::test]
+async fn postponing_a_task_does_not_wake_the_workers() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    publish_newsletter(&app).await;
+    let mut listener = PgListener::connect_with(&app.db_pool).await.unwrap();
+    listener.listen("issue_delivery_queue").await.unwrap();
+
+    sqlx::query!("UPDATE issue_delivery_queue SET execute_after = now() + interval '1 hour'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let postponed = tokio::time::timeout(Duration::from_millis(500), listener.recv()).await;
+    sqlx::query!("UPDATE issue_delivery_queue SET execute_after = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let made_due = tokio::time::timeout(Duration::from_secs(5), listener.recv()).await;
+
+    assert!(postponed.is_err(), "Postponing a task should not notify the workers");
+    assert!(made_due.is_ok(), "Making a task due should notify the workers");
+}
+
+#[tokio::test]
+async fn concurrent_executors_drain_the_queue_in_parallel() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscribers(&app, 40).await;
+    // Delivered one at a time, these would take 40 * 500ms = 20s.
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(500)))
+        .expect(40)
+        .mount(&app.email_server)
+        .await;
+    let (_trigger, _worker) = start_worker(&app, 600, 10).await;
+
+    // Act
+    publish_newsletter(&app).await;
+
+    // Assert
+    wait_for_empty_queue(&app, Duration::from_secs(6)).await;
+}
+
+#[tokio::test]
+async fn a_failing_delivery_does_not_hold_up_the_other_executors() {
+    // Arrange
+    let app = spawn_app().await;
+    let emails = create_confirmed_subscribers(&app, 20).await;
+    let failing_email = &emails[0];
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .and(body_partial_json(serde_json::json!({ "To": failing_email })))
+        .respond_with(ResponseTemplate::new(500))
+        .with_priority(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(200)))
+        .expect(19)
+        .mount(&app.email_server)
+        .await;
+    let (_trigger, _worker) = start_worker(&app, 600, 4).await;
+
+    // Act
+    publish_newsletter(&app).await;
+
+    // Assert
+    wait_for_queue_length(&app, 1, Duration::from_secs(5)).await;
+    let pending = sqlx::query!("SELECT subscriber_email, n_attempts FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(&pending.subscriber_email, failing_email);
+    assert_eq!(pending.n_attempts, 1);
+}
@@ -0,0 +1,165 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use crate::newsletter::create_confirmed_subscriber;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn create_dead_letter(app: &TestApp) -> (Uuid, String) {
+    create_confirmed_subscriber(app).await;
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    }))
+        .await;
+    for _ in 0..app.worker_settings.max_delivery_attempts {
+        sqlx::query!("UPDATE issue_delivery_queue SET execute_after = now()")
+            .execute(&app.db_pool)
+            .await
+            .unwrap();
+        app.dispatch_all_pending_emails().await;
+    }
+    let r = sqlx::query!("SELECT newsletter_issue_id, subscriber_email FROM issue_delivery_dead_letters")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the dead letter");
+    (r.newsletter_issue_id, r.subscriber_email)
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_failed_deliveries() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/admin/deliveries", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_listed() {
+    let app = spawn_app().await;
+    app.post_test_user_login().await;
+    let (_, subscriber_email) = create_dead_letter(&app).await;
+
+    let html_page = app.get_failed_deliveries_html().await;
+
+    assert!(html_page.contains(&subscriber_email));
+    assert!(html_page.contains("Newsletter title"));
+}
+
+#[tokio::test]
+async fn failed_deliveries_can_be_requeued() {
+    // Arrange
+    let app = spawn_app().await;
+    app.post_test_user_login().await;
+    let (newsletter_issue_id, subscriber_email) = create_dead_letter(&app).await;
+
+    // Act - Part 1 - Re-queue
+    let response = app
+        .post_requeue_failed_delivery(&serde_json::json!({
+            "newsletter_issue_id": newsletter_issue_id,
+            "subscriber_email": &subscriber_email,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/deliveries");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_failed_deliveries_html().await;
+    assert!(html_page.contains(&format!(
+        "<p><i>The delivery to {} has been re-queued.</i></p>",
+        subscriber_email
+    )));
+
+    // Act - Part 3 - The worker delivers it this time
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let n_dead_letters = sqlx::query!("SELECT COUNT(*) AS \"n!\" FROM issue_delivery_dead_letters")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_dead_letters, 0);
+}
+
+#[tokio::test]
+async fn dead_letters_of_deleted_subscribers_are_kept() {
+    // Arrange
+    let app = spawn_app().await;
+    app.post_test_user_login().await;
+    let (newsletter_issue_id, subscriber_email) = create_dead_letter(&app).await;
+    sqlx::query!("DELETE FROM subscription_tokens")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    sqlx::query!("DELETE FROM subscriptions WHERE email = $1", subscriber_email)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = app
+        .post_requeue_failed_delivery(&serde_json::json!({
+            "newsletter_issue_id": newsletter_issue_id,
+            "subscriber_email": &subscriber_email,
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/deliveries");
+    let html_page = app.get_failed_deliveries_html().await;
+    assert!(html_page.contains("<p><i>The failed delivery could not be found.</i></p>"));
+    assert!(html_page.contains(&subscriber_email));
+    let n_queued = sqlx::query!("SELECT COUNT(*) AS \"n!\" FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_queued, 0);
+}
+
+#[tokio::test]
+async fn requeue_messages_escape_the_subscriber_email() {
+    // Arrange
+    let app = spawn_app().await;
+    app.post_test_user_login().await;
+    let (newsletter_issue_id, _) = create_dead_letter(&app).await;
+    let subscriber_email = "<script>alert(1)</script>@example.com";
+    sqlx::query!("UPDATE issue_delivery_dead_letters SET subscriber_email = $1", subscriber_email)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    sqlx::query!("UPDATE subscriptions SET email = $1", subscriber_email)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    app.post_requeue_failed_delivery(&serde_json::json!({
+        "newsletter_issue_id": newsletter_issue_id,
+        "subscriber_email": subscriber_email,
+    }))
+        .await;
+    let html_page = app.get_failed_deliveries_html().await;
+
+    // Assert
+    assert!(!html_page.contains("<script>"));
+    assert!(html_page.contains("&lt;script&gt;alert(1)&lt;/script&gt;@example.com"));
+}
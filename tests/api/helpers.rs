@@ -1,14 +1,22 @@
 use argon2::password_hash::SaltString;
 use argon2::PasswordHasher;
+use clap::Parser;
 use once_cell::sync::Lazy;
 use reqwest::{Response, Url};
+use secrecy::{ExposeSecret, SecretString};
 use serde::Serialize;
 use sqlx::{Connection, Executor, PgConnection, PgPool};
+use std::sync::Arc;
+use tokio::task::JoinHandle;
 use uuid::Uuid;
 use wiremock::MockServer;
-use zero2prod_my::configuration::{get_configuration, DatabaseSettings};
-use zero2prod_my::email_client::EmailClient;
+use zero2prod_my::authentication::{create_api_token, ApiScope};
+use zero2prod_my::cli::{self, Cli};
+use zero2prod_my::configuration::{get_configuration, ApplicationSettings, Settings, DatabaseSettings, IdempotencySettings, LoginThrottleSettings, WorkerSettings};
+use zero2prod_my::email_client::EmailTransport;
 use zero2prod_my::issue_delivery_worker::{try_execute_task, ExecutionOutcome};
+use zero2prod_my::newsletter_scheduler::{try_release_scheduled_issue, ReleaseOutcome};
+use zero2prod_my::shutdown::{shutdown_channel, ShutdownTrigger};
 use zero2prod_my::startup::{get_connection_pool, Application};
 use zero2prod_my::telemetry::{get_subscriber, init_subscriber};
 
@@ -29,13 +37,19 @@ pub struct TestUser {
     pub user_id: Uuid,
     pub username: String,
     pub password: String,
+    pub role: &'static str,
 }
 impl TestUser {
     pub fn generate() -> Self {
+        Self::generate_with_role("owner")
+    }
+
+    pub fn generate_with_role(role: &'static str) -> Self {
         Self {
             user_id: Uuid::new_v4(),
             username: Uuid::new_v4().to_string(),
             password: Uuid::new_v4().to_string(),
+            role,
         }
     }
 
@@ -57,12 +71,13 @@ impl TestUser {
             .to_string();
         sqlx::query!(
             r#"
-            INSERT INTO users (user_id, username,password_hash )
-            VALUES ($1, $2, $3)
+            INSERT INTO users (user_id, username, password_hash, role)
+            VALUES ($1, $2, $3, $4)
             "#,
             self.user_id,
             self.username,
             password_hash,
+            self.role,
         )
             .execute(pool)
             .await
@@ -77,12 +92,23 @@ pub struct TestApp {
     pub port: u16,
     pub test_user: TestUser,
     pub api_client: reqwest::Client,
-    pub email_client: EmailClient
+    pub email_client: Arc<dyn EmailTransport>,
+    pub worker_settings: WorkerSettings,
+    pub application_settings: ApplicationSettings,
+    pub webhook_secret: SecretString,
+    pub login_throttle: LoginThrottleSettings,
+    pub idempotency_settings: IdempotencySettings,
+    /// What the app was started with, pointing at its own database.
+    pub configuration: Settings,
+    /// Stops the server gracefully; dropping the `TestApp` does the same.
+    pub shutdown: ShutdownTrigger,
+    pub server: JoinHandle<Result<(), std::io::Error>>,
 }
 
 
 pub struct ConfirmationLinks {
     pub html: Url,
+    #[allow(dead_code)]
     pub text: Url,
 }
 
@@ -95,7 +121,13 @@ impl TestApp {
     pub async fn dispatch_all_pending_emails(&self) {
         loop {
             if let ExecutionOutcome::EmptyQueue =
-                try_execute_task(&self.db_pool, &self.email_client)
+                try_execute_task(
+                    &self.db_pool,
+                    self.email_client.as_ref(),
+                    &self.worker_settings,
+                    &self.application_settings.base_url,
+                    &self.application_settings.hmac_secret,
+                )
                     .await
                     .unwrap()
             {
@@ -103,10 +135,171 @@ impl TestApp {
             }
         }
     }
-    
+
+    pub async fn release_due_scheduled_issues(&self) {
+        loop {
+            if let ReleaseOutcome::NothingDue = try_release_scheduled_issue(&self.db_pool)
+                .await
+                .unwrap()
+            {
+                break;
+            }
+        }
+    }
+
+    pub async fn get_newsletter_issues_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/newsletters/issues", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_reschedule_newsletter_issue<Body>(
+        &self,
+        newsletter_issue_id: Uuid,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/issues/{}/reschedule",
+                &self.address, newsletter_issue_id
+            ))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_cancel_newsletter_issue(&self, newsletter_issue_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/issues/{}/cancel",
+                &self.address, newsletter_issue_id
+            ))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_drafts_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/newsletters/drafts", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_create_draft<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/newsletters/drafts", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_draft(&self, newsletter_issue_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/newsletters/drafts/{}", &self.address, newsletter_issue_id))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_draft_html(&self, newsletter_issue_id: Uuid) -> String {
+        self.get_draft(newsletter_issue_id).await.text().await.unwrap()
+    }
+
+    pub async fn post_update_draft<Body>(&self, newsletter_issue_id: Uuid, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/newsletters/drafts/{}", &self.address, newsletter_issue_id))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_draft_preview_html(&self, newsletter_issue_id: Uuid) -> String {
+        self.api_client
+            .get(format!("{}/admin/newsletters/drafts/{}/preview", &self.address, newsletter_issue_id))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_delete_draft(&self, newsletter_issue_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/newsletters/drafts/{}/delete", &self.address, newsletter_issue_id))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_publish_draft<Body>(&self, newsletter_issue_id: Uuid, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/newsletters/drafts/{}/publish", &self.address, newsletter_issue_id))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_failed_deliveries_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/deliveries", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_requeue_failed_delivery<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/deliveries/requeue", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn post_logout(&self) -> reqwest::Response {
         self.api_client
             .post(format!("{}/admin/logout", self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
             .send()
             .await
             .expect("Failed to execute request.")
@@ -120,6 +313,48 @@ impl TestApp {
             .await;
     }
 
+    pub async fn login_as(&self, user: &TestUser) -> reqwest::Response {
+        self.post_login(&serde_json::json!({
+            "username": &user.username,
+            "password": &user.password
+        }))
+            .await
+    }
+
+    pub async fn get_users(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/users", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_users_html(&self) -> String {
+        self.get_users().await.text().await.unwrap()
+    }
+
+    pub async fn post_invite_user<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/users", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_user_action(&self, user_id: Uuid, action: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/users/{}/{}", &self.address, user_id, action))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn get_change_password(&self) -> reqwest::Response {
         self.api_client
             .get(format!("{}/admin/password", self.address))
@@ -138,6 +373,7 @@ impl TestApp {
     {
         self.api_client
             .post(format!("{}/admin/password", self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
             .form(body)
             .send()
             .await
@@ -146,7 +382,7 @@ impl TestApp {
 
     pub async fn get_admin_dashboard(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/dashboard", &self.address))
+            .get(format!("{}/admin/dashboard", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -155,9 +391,13 @@ impl TestApp {
         self.get_admin_dashboard().await.text().await.unwrap()
     }
 
+    pub async fn csrf_token(&self) -> String {
+        csrf_token(&self.api_client, &self.address).await
+    }
+
     pub async fn get_login_html(&self) -> String {
         self.api_client
-            .get(&format!("{}/login", self.address))
+            .get(format!("{}/login", self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -172,27 +412,51 @@ impl TestApp {
     {
         self.api_client
             .post(format!("{}/login", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
             .form(body)
             .send()
             .await
             .expect("Failed to execute request")
     }
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
-        let response = self
+        self
             .api_client
             .post(format!("{}/subscriptions", &self.address))
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(body)
             .send()
             .await
-            .expect("Failed to execute request.");
-        response
+            .expect("Failed to execute request.")
+    }
+
+    /// Creates an API token for the test user, bypassing the admin pages.
+    pub async fn create_api_token(&self, scopes: &[ApiScope]) -> String {
+        let (_, token) = create_api_token(self.test_user.user_id, "test", scopes, None, &self.db_pool)
+            .await
+            .unwrap();
+        token
+    }
+
+    pub async fn post_newsletters(&self, api_token: &str, body: serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/newsletters", &self.address))
+            .bearer_auth(api_token)
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
     }
 
-    pub async fn post_newsletters(&self, body: serde_json::Value) -> reqwest::Response {
+    pub async fn post_newsletters_with_idempotency_key(
+        &self,
+        api_token: &str,
+        idempotency_key: &str,
+        body: serde_json::Value,
+    ) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/newsletters", &self.address))
-            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .post(format!("{}/newsletters", &self.address))
+            .bearer_auth(api_token)
+            .header("Idempotency-Key", idempotency_key)
             .json(&body)
             .send()
             .await
@@ -200,7 +464,7 @@ impl TestApp {
     }
     pub async fn get_publish_newsletter(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/newsletters", &self.address))
+            .get(format!("{}/admin/newsletters", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -214,12 +478,242 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/admin/newsletters", &self.address))
+            .post(format!("{}/admin/newsletters", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
             .form(body)
             .send()
             .await
             .expect("Failed to execute request.")
     }
+    pub async fn post_postmark_webhook(&self, payload: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/webhooks/postmark", &self.address))
+            .basic_auth("postmark", Some(self.webhook_secret.expose_secret()))
+            .header("Content-Type", "application/json")
+            .body(payload.to_owned())
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_two_factor_settings_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/two-factor", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_enable_two_factor<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/two-factor/enable", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_disable_two_factor<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/two-factor/disable", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_login_two_factor(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/login/two-factor", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_login_two_factor(&self, code: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/login/two-factor", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(&serde_json::json!({"code": code}))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_sessions_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/sessions", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_revoke_session(&self, session_id: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/sessions/{}/revoke", &self.address, session_id))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_revoke_all_sessions(&self) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/sessions/revoke-all", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_api_tokens_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/api-tokens", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_create_api_token<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize + ?Sized,
+    {
+        self.api_client
+            .post(format!("{}/admin/api-tokens", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_revoke_api_token(&self, api_token_id: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/api-tokens/{}/revoke", &self.address, api_token_id))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_failed_logins_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/login-attempts", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_clear_failed_login<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/login-attempts/clear", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_audit_log(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/audit?{}", &self.address, query))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_audit_log_html(&self, query: &str) -> String {
+        self.get_audit_log(query).await.text().await.unwrap()
+    }
+
+    pub async fn get_audit_log_export(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/audit/export?{}", &self.address, query))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Runs `zero2prod-admin` with `args` against the app's database and returns its report.
+    pub async fn run_admin_cli(&self, args: &[&str]) -> Result<String, anyhow::Error> {
+        let cli = Cli::try_parse_from(std::iter::once("zero2prod-admin").chain(args.iter().copied()))?;
+        let mut out = Vec::new();
+        cli::run(cli.command, self.configuration.clone(), &mut out).await?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    pub async fn get_forgot_password_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/login/forgot", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_forgot_password<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: Serialize,
+    {
+        self.api_client
+            .post(format!("{}/login/forgot", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_reset_password<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: Serialize,
+    {
+        self.api_client
+            .post(format!("{}/login/reset", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Extracts the reset token from the link in the last email that was sent.
+    pub async fn get_password_reset_token(&self) -> String {
+        let links = self.get_confirmation_links().await;
+        assert_eq!(links.html.path(), "/login/reset");
+        links
+            .html
+            .query_pairs()
+            .find(|(k, _)| k == "token")
+            .map(|(_, v)| v.into_owned())
+            .unwrap()
+    }
+
     pub async fn get_confirmation_links(&self) -> ConfirmationLinks {
         let requests = self.email_server.received_requests().await.unwrap();
         let request = &requests[requests.len() - 1];  // 获取最后一个请求
@@ -232,8 +726,8 @@ impl TestApp {
             assert_eq!(links.len(), 1);
             links[0].as_str().to_owned()
         };
-        let html = get_link(&body["HtmlBody"].as_str().unwrap());
-        let text = get_link(&body["TextBody"].as_str().unwrap());
+        let html = get_link(body["HtmlBody"].as_str().unwrap());
+        let text = get_link(body["TextBody"].as_str().unwrap());
         let mut html = Url::parse(&html).unwrap();
         let mut text = Url::parse(&text).unwrap();
         html.set_port(Some(self.port)).unwrap();
@@ -243,6 +737,11 @@ impl TestApp {
 }
 
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Like `spawn_app`, with the test's own tweaks applied to the configuration.
+pub async fn spawn_app_with(configure: impl FnOnce(&mut Settings)) -> TestApp {
     Lazy::force(&TRACING);
     let email_server = MockServer::start().await;
 
@@ -250,6 +749,9 @@ pub async fn spawn_app() -> TestApp {
     configuration.database.database_name = Uuid::new_v4().to_string();
     configuration.application.port = 0;
     configuration.email_client.base_url = email_server.uri();
+    // Lockouts still apply, but failed attempts should not slow the test suite down.
+    configuration.login_throttle.base_delay_milliseconds = 0;
+    configure(&mut configuration);
     tracing::info!("Configuration: {:#?}", configuration.email_client.base_url);
     configure_database(&configuration.database).await;
 
@@ -258,12 +760,9 @@ pub async fn spawn_app() -> TestApp {
         .expect("Failed to build application");
     let port = application.port();
     let address = format!("http://localhost:{}", port);
-    let _ = tokio::spawn(application.run_until_stopped());
-    let client = reqwest::Client::builder()
-        .redirect(reqwest::redirect::Policy::none())
-        .cookie_store(true)
-        .build()
-        .unwrap();
+    let (shutdown, server_shutdown) = shutdown_channel();
+    let server = tokio::spawn(application.run_until_stopped(server_shutdown));
+    let client = build_api_client();
     let test_app = TestApp {
         address,
         db_pool: get_connection_pool(&configuration.database),
@@ -271,12 +770,47 @@ pub async fn spawn_app() -> TestApp {
         port,
         test_user: TestUser::generate(),
         api_client: client,
-        email_client: configuration.email_client.client()
+        webhook_secret: configuration.email_client.webhook_secret.clone(),
+        login_throttle: configuration.login_throttle.clone(),
+        idempotency_settings: configuration.idempotency.clone(),
+        configuration: configuration.clone(),
+        shutdown,
+        server,
+        email_client: configuration.email_client.client(),
+        worker_settings: configuration.worker,
+        application_settings: configuration.application,
     };
     test_app.test_user.store(&test_app.db_pool).await;
     test_app
 }
 
+/// The CSRF token of the client's session, as embedded in the login form.
+pub async fn csrf_token(client: &reqwest::Client, address: &str) -> String {
+    let html_page = client
+        .get(format!("{}/login", address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .text()
+        .await
+        .unwrap();
+    let start = html_page
+        .find(r#"name="csrf_token" value=""#)
+        .expect("The login form has no CSRF token.")
+        + r#"name="csrf_token" value=""#.len();
+    let end = start + html_page[start..].find('"').unwrap();
+    html_page[start..end].to_owned()
+}
+
+/// A client with its own cookie jar, i.e. a separate browser session.
+pub fn build_api_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap()
+}
+
 async fn configure_database(config: &DatabaseSettings) -> PgPool {
     let mut connection = PgConnection::connect_with(&config.without_db())
         .await
@@ -0,0 +1,321 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+use std::time::Duration;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod_my::authentication::ApiScope;
+use zero2prod_my::idempotency::{
+    purge_expired_idempotency_records, try_processing, IdempotencyKey, IdempotencyScope, NextAction,
+    RequestFingerprint,
+};
+
+fn newsletter_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    })
+}
+
+#[tokio::test]
+async fn an_expired_idempotency_key_is_treated_as_fresh() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app.create_api_token(&ApiScope::ALL).await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    let response = app
+        .post_newsletters_with_idempotency_key(&token, &idempotency_key, newsletter_body())
+        .await;
+    let first: serde_json::Value = response.json().await.unwrap();
+    sqlx::query!(
+        "UPDATE idempotency SET created_at = now() - make_interval(secs => $1)",
+        (app.idempotency_settings.ttl_seconds + 60) as f64
+    )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = app
+        .post_newsletters_with_idempotency_key(&token, &idempotency_key, newsletter_body())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+    let second: serde_json::Value = response.json().await.unwrap();
+    assert_ne!(first["newsletter_issue_id"], second["newsletter_issue_id"]);
+}
+
+#[tokio::test]
+async fn the_cleanup_purges_expired_records_in_batches() {
+    // Arrange
+    let app = spawn_app().await;
+    for age_hours in [48, 49, 50, 51, 52, 1] {
+        sqlx::query!(
+            r#"
+            INSERT INTO idempotency (scope, idempotency_key, created_at)
+            VALUES ($1, $2, now() - make_interval(hours => $3))
+            "#,
+            IdempotencyScope::User(app.test_user.user_id).to_string(),
+            Uuid::new_v4().to_string(),
+            age_hours
+        )
+            .execute(&app.db_pool)
+            .await
+            .unwrap();
+    }
+
+    // Act
+    let purged = purge_expired_idempotency_records(&app.db_pool, Duration::from_secs(24 * 3600), 2)
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(purged, 5);
+    let remaining = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM idempotency"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining.count, 1);
+}
+
+async fn count_issues(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+async fn post_newsletters_preferring_to_wait(
+    app: &TestApp,
+    api_token: &str,
+    idempotency_key: &str,
+    wait_seconds: u64,
+) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/newsletters", &app.address))
+        .bearer_auth(api_token)
+        .header("Idempotency-Key", idempotency_key)
+        .header("Prefer", format!("wait={}", wait_seconds))
+        .json(&newsletter_body())
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn reusing_a_key_for_a_different_api_request_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app.create_api_token(&ApiScope::ALL).await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    let response = app
+        .post_newsletters_with_idempotency_key(&token, &idempotency_key, newsletter_body())
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    // Act
+    let mut other_body = newsletter_body();
+    other_body["title"] = "Another title".into();
+    let response = app
+        .post_newsletters_with_idempotency_key(&token, &idempotency_key, other_body)
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 422);
+    assert!(response.text().await.unwrap().contains("already used for a request with different content"));
+    assert_eq!(count_issues(&app).await, 1);
+}
+
+#[tokio::test]
+async fn reusing_a_key_for_a_different_form_submission_is_rejected() {
+    let app = spawn_app().await;
+    app.post_test_user_login().await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    let form = |title: &str| {
+        serde_json::json!({
+            "title": title,
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": idempotency_key,
+        })
+    };
+    app.post_publish_newsletter(&form("Newsletter title")).await;
+
+    let response = app.post_publish_newsletter(&form("Edited title")).await;
+
+    assert_eq!(response.status().as_u16(), 422);
+    assert_eq!(count_issues(&app).await, 1);
+}
+
+#[tokio::test]
+async fn an_in_flight_duplicate_can_ask_not_to_wait() {
+    // Arrange - the original request holds the key until its transaction ends
+    let app = spawn_app().await;
+    let token = app.create_api_token(&ApiScope::ALL).await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    let NextAction::StartProcessing(in_flight) = try_processing(
+        &app.db_pool,
+        &IdempotencyKey::try_from(idempotency_key.clone()).unwrap(),
+        &IdempotencyScope::api_token(&token),
+        &RequestFingerprint::new("test", serde_json::json!({})),
+        Duration::from_secs(3600),
+        Duration::ZERO,
+    )
+        .await
+        .unwrap()
+    else {
+        panic!("The key should have been fresh");
+    };
+
+    // Act
+    let response = post_newsletters_preferring_to_wait(&app, &token, &idempotency_key, 0).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 409);
+    let response = post_newsletters_preferring_to_wait(&app, &token, &idempotency_key, 1).await;
+    assert_eq!(response.status().as_u16(), 409);
+    // Once the original gives up the key, the duplicate goes through.
+    drop(in_flight);
+    let response = post_newsletters_preferring_to_wait(&app, &token, &idempotency_key, 0).await;
+    assert_eq!(response.status().as_u16(), 202);
+}
+
+#[tokio::test]
+async fn an_in_flight_duplicate_waits_for_the_original_by_default() {
+    let app = spawn_app().await;
+    let token = app.create_api_token(&ApiScope::ALL).await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    let NextAction::StartProcessing(in_flight) = try_processing(
+        &app.db_pool,
+        &IdempotencyKey::try_from(idempotency_key.clone()).unwrap(),
+        &IdempotencyScope::api_token(&token),
+        &RequestFingerprint::new("test", serde_json::json!({})),
+        Duration::from_secs(3600),
+        Duration::ZERO,
+    )
+        .await
+        .unwrap()
+    else {
+        panic!("The key should have been fresh");
+    };
+
+    let duplicate = app.post_newsletters_with_idempotency_key(&token, &idempotency_key, newsletter_body());
+    let release = async {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        drop(in_flight);
+    };
+    let (response, _) = tokio::join!(duplicate, release);
+
+    assert_eq!(response.status().as_u16(), 202);
+}
+
+fn subscription_request(app: &TestApp, body: &'static str, idempotency_key: &str, client_ip: &str) -> reqwest::RequestBuilder {
+    app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Idempotency-Key", idempotency_key)
+        .header("X-Forwarded-For", client_ip)
+        .body(body)
+}
+
+#[tokio::test]
+async fn anonymous_requests_are_deduplicated_per_client_ip() {
+    // Arrange - the test client plays the reverse proxy
+    let app = spawn_app_with(|c| c.application.trusted_proxies = vec!["127.0.0.1".parse().unwrap()]).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    let idempotency_key = Uuid::new_v4().to_string();
+
+    // Act - a retry from the same address, then the same key from somewhere else
+    for (body, client_ip) in [
+        ("name=le%20guin&email=ursula_le_guin%40gmail.com", "203.0.113.1"),
+        ("name=le%20guin&email=ursula_le_guin%40gmail.com", "203.0.113.1"),
+        ("name=tolkien&email=jrr_tolkien%40gmail.com", "203.0.113.2"),
+    ] {
+        let response = subscription_request(&app, body, &idempotency_key, client_ip)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    // Assert
+    let subscribers = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscribers.count, 2);
+}
+
+#[tokio::test]
+async fn api_keys_are_scoped_to_their_token() {
+    let app = spawn_app().await;
+    let first_token = app.create_api_token(&ApiScope::ALL).await;
+    let second_token = app.create_api_token(&ApiScope::ALL).await;
+    let idempotency_key = Uuid::new_v4().to_string();
+
+    for token in [&first_token, &second_token] {
+        let response = app
+            .post_newsletters_with_idempotency_key(token, &idempotency_key, newsletter_body())
+            .await;
+        assert_eq!(response.status().as_u16(), 202);
+    }
+
+    assert_eq!(count_issues(&app).await, 2);
+}
+
+#[tokio::test]
+async fn a_failed_request_does_not_use_up_its_key() {
+    let app = spawn_app().await;
+    let token = app.create_api_token(&ApiScope::ALL).await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    let mut invalid_body = newsletter_body();
+    invalid_body["title"] = "Hello {{unknown}}".into();
+    let response = app
+        .post_newsletters_with_idempotency_key(&token, &idempotency_key, invalid_body)
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app
+        .post_newsletters_with_idempotency_key(&token, &idempotency_key, newsletter_body())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 202);
+    assert_eq!(count_issues(&app).await, 1);
+}
+
+#[tokio::test]
+async fn a_replayed_form_submission_shows_the_original_messages_again() {
+    // Arrange
+    let app = spawn_app().await;
+    app.post_test_user_login().await;
+    let form = serde_json::json!({
+        "title": "Hello {{unknown}}",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    });
+    app.post_publish_newsletter(&form).await;
+    app.get_publish_newsletter_html().await;
+
+    // Act
+    let response = app.post_publish_newsletter(&form).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("The newsletter issue could not be published."));
+    let saved = sqlx::query!("SELECT flash_messages FROM idempotency")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.flash_messages.unwrap().contains("could not be published"));
+}
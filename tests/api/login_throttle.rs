@@ -0,0 +1,185 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp, TestUser};
+use uuid::Uuid;
+use zero2prod_my::authentication::{enable_two_factor, generate_totp_secret};
+
+async fn fail_logins(app: &TestApp, username: &str, n: i32) {
+    for _ in 0..n {
+        let response = app
+            .post_login(&serde_json::json!({
+                "username": username,
+                "password": Uuid::new_v4().to_string()
+            }))
+            .await;
+        assert_is_redirect_to(&response, "/login");
+    }
+}
+
+async fn post_test_user_password(app: &TestApp) -> reqwest::Response {
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    }))
+        .await
+}
+
+#[tokio::test]
+async fn a_username_is_locked_out_after_too_many_failures() {
+    // Arrange
+    let app = spawn_app().await;
+    fail_logins(&app, &app.test_user.username, app.login_throttle.max_failures_per_username).await;
+
+    // Act - Even the right password is refused now
+    let response = post_test_user_password(&app).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("Too many failed login attempts, please try again later"));
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn unknown_usernames_are_locked_out_the_same_way() {
+    let app = spawn_app().await;
+    let username = Uuid::new_v4().to_string();
+    fail_logins(&app, &username, app.login_throttle.max_failures_per_username).await;
+
+    fail_logins(&app, &username, 1).await;
+
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("Too many failed login attempts, please try again later"));
+}
+
+#[tokio::test]
+async fn a_successful_login_resets_the_username_counter() {
+    let app = spawn_app().await;
+    let almost = app.login_throttle.max_failures_per_username - 1;
+    fail_logins(&app, &app.test_user.username, almost).await;
+    assert_is_redirect_to(&post_test_user_password(&app).await, "/admin/dashboard");
+    app.post_logout().await;
+
+    fail_logins(&app, &app.test_user.username, almost).await;
+
+    assert_is_redirect_to(&post_test_user_password(&app).await, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn a_locked_out_client_ip_cannot_log_in_to_any_account() {
+    let app = spawn_app().await;
+    sqlx::query!(
+        r#"
+        INSERT INTO failed_login_attempts (scope, subject, failures, last_failure_at, locked_until)
+        VALUES ('ip', '127.0.0.1', 50, now(), now() + interval '15 minutes')
+        "#
+    )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = post_test_user_password(&app).await;
+
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("Too many failed login attempts, please try again later"));
+}
+
+#[tokio::test]
+async fn a_spoofed_forwarded_for_header_does_not_escape_an_ip_lockout() {
+    let app = spawn_app().await;
+    sqlx::query!(
+        r#"
+        INSERT INTO failed_login_attempts (scope, subject, failures, last_failure_at, locked_until)
+        VALUES ('ip', '127.0.0.1', 50, now(), now() + interval '15 minutes')
+        "#
+    )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app
+        .api_client
+        .post(format!("{}/login", &app.address))
+        .header("X-CSRF-Token", app.csrf_token().await)
+        .header("X-Forwarded-For", "203.0.113.9")
+        .form(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("Too many failed login attempts, please try again later"));
+}
+
+#[tokio::test]
+async fn wrong_two_factor_codes_count_as_failures() {
+    let app = spawn_app().await;
+    let secret = generate_totp_secret();
+    let mut transaction = app.db_pool.begin().await.unwrap();
+    enable_two_factor(app.test_user.user_id, &secret, &mut transaction)
+        .await
+        .unwrap();
+    transaction.commit().await.unwrap();
+    for _ in 0..app.login_throttle.max_failures_per_username {
+        assert_is_redirect_to(&post_test_user_password(&app).await, "/login/two-factor");
+        let response = app.post_login_two_factor("000000").await;
+        assert_is_redirect_to(&response, "/login/two-factor");
+    }
+
+    let response = post_test_user_password(&app).await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn owners_can_see_and_clear_the_counters() {
+    // Arrange
+    let app = spawn_app().await;
+    let locked_out = TestUser::generate_with_role("editor");
+    locked_out.store(&app.db_pool).await;
+    fail_logins(&app, &locked_out.username, app.login_throttle.max_failures_per_username).await;
+    app.post_test_user_login().await;
+
+    // Act - Part 1 - See the counters
+    let html_page = app.get_failed_logins_html().await;
+    assert!(html_page.contains(&format!("<td>{}</td>", locked_out.username)));
+    assert!(html_page.contains("<td>127.0.0.1</td>"));
+
+    // Act - Part 2 - Clear the username counter
+    let response = app
+        .post_clear_failed_login(&serde_json::json!({
+            "scope": "username",
+            "subject": &locked_out.username
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/login-attempts");
+    let html_page = app.get_failed_logins_html().await;
+    assert!(html_page.contains("The failed login counter has been cleared."));
+    assert!(!html_page.contains(&format!("<td>{}</td>", locked_out.username)));
+    app.post_logout().await;
+
+    // Assert
+    let response = app.login_as(&locked_out).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn only_owners_can_see_the_counters() {
+    let app = spawn_app().await;
+    let editor = TestUser::generate_with_role("editor");
+    editor.store(&app.db_pool).await;
+    app.login_as(&editor).await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/admin/login-attempts", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 403);
+}
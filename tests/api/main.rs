@@ -5,4 +5,22 @@ mod subscriptions_confirm;
 mod newsletter;
 mod login;
 mod change_password;
-mod admin_dashboard;
\ No newline at end of file
+mod admin_dashboard;
+mod failed_deliveries;
+mod subscriptions_unsubscribe;
+mod scheduled_newsletters;
+mod newsletter_drafts;
+mod email_webhooks;
+mod admin_users;
+mod password_reset;
+mod two_factor;
+mod login_throttle;
+mod password_hashing;
+mod admin_sessions;
+mod csrf;
+mod api_tokens;
+mod idempotency;
+mod audit;
+mod admin_cli;
+mod shutdown;
+mod delivery_worker;
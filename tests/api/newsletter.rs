@@ -4,43 +4,40 @@ use fake::faker::name::zh_tw::Name;
 use fake::Fake;
 use std::time::Duration;
 use uuid::Uuid;
-use wiremock::matchers::{any, method, path};
+use wiremock::matchers::{any, body_partial_json, method, path};
 use wiremock::{Mock, MockBuilder, ResponseTemplate};
+use zero2prod_my::authentication::ApiScope;
+use zero2prod_my::domain::UnsubscribeToken;
 
 #[tokio::test]
-async fn invalid_password_is_rejected() {
+async fn invalid_api_token_is_rejected() {
     let app = spawn_app().await;
-    let username = app.test_user.username;
-    let password = Uuid::new_v4().to_string();
-    let res = reqwest::Client::new()
-        .post(&format!("{}/newsletters", &app.address))
-        .basic_auth(username, Some(password))
-        .json(
-            &serde_json::json!({
+    let token = format!("z2p_{}", Uuid::new_v4().simple());
+    let res = app
+        .post_newsletters(
+            &token,
+            serde_json::json!({
                 "title": "Newsletter title",
                 "content": {
                     "text": "Newsletter body as plain text",
                     "html": "<p>Newsletter body as HTML</p>",
                 }
-            })
+            }),
         )
-        .send()
-        .await
-        .expect("Failed to execute request.");
+        .await;
     assert_eq!(res.status().as_u16(), 401);
     assert_eq!(
-        r#"Basic realm="publish""#,
+        r#"Bearer realm="publish""#,
         res.headers()["WWW-Authenticate"]
     );
 }
+
 #[tokio::test]
-async fn non_existing_user_is_rejected() {
+async fn basic_auth_is_no_longer_accepted() {
     let app = spawn_app().await;
-    let username = Uuid::new_v4().to_string();
-    let password = Uuid::new_v4().to_string();
     let res = reqwest::Client::new()
-        .post(&format!("{}/newsletters", &app.address))
-        .basic_auth(username, Some(password))
+        .post(format!("{}/newsletters", &app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
         .json(
             &serde_json::json!({
                 "title": "Newsletter title",
@@ -54,10 +51,6 @@ async fn non_existing_user_is_rejected() {
         .await
         .expect("Failed to execute request.");
     assert_eq!(res.status().as_u16(), 401);
-    assert_eq!(
-        r#"Basic realm="publish""#,
-        res.headers()["WWW-Authenticate"]
-    );
 }
 
 #[tokio::test]
@@ -65,7 +58,7 @@ async fn requests_missing_authorization_are_rejected() {
     let app = spawn_app().await;
 
     let response = reqwest::Client::new()
-        .post(&format!("{}/newsletters", &app.address))
+        .post(format!("{}/newsletters", &app.address))
         .json(
             &serde_json::json!({
                 "title": "Newsletter title",
@@ -192,10 +185,10 @@ async fn newsletters_returns_400_for_invalid_data() {
 }
 
 
-async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
+pub async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
     let name: String = Name().fake();
     let email: String = SafeEmail().fake();
-    let body = serde_urlencoded::to_string(&serde_json::json!({
+    let body = serde_urlencoded::to_string(serde_json::json!({
         "name": name,
         "email": email
     }))
@@ -210,7 +203,7 @@ async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
         .expect(1)
         .mount_as_scoped(&app.email_server)
         .await;
-    app.post_subscriptions(body.into())
+    app.post_subscriptions(body)
         .await
         .error_for_status()
         .unwrap();
@@ -218,7 +211,7 @@ async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
     app.get_confirmation_links().await
 }
 
-async fn create_confirmed_subscriber(app: &TestApp) {
+pub async fn create_confirmed_subscriber(app: &TestApp) {
     let link = create_unconfirmed_subscriber(app).await.html;
 
     
@@ -313,48 +306,384 @@ fn when_sending_an_email() -> MockBuilder {
     Mock::given(path("/email")).and(method("POST"))
 }
 
-// #[tokio::test]
-// async fn transient_errors_do_not_cause_duplicate_deliveries_on_retries() {
-//     let app = spawn_app().await;
-//     let newsletter_request_body = serde_json::json!({
-//          "title": "Newsletter title",
-//         "text_content": "Newsletter body as plain text",
-//         "html_content": "<p>Newsletter body as HTML</p>",
-//         "idempotency_key": uuid::Uuid::new_v4().to_string()
-//     });
-//
-//     create_confirmed_subscriber(&app).await;
-//     create_confirmed_subscriber(&app).await;
-//
-//     app.post_test_user_login().await;
-//
-//     when_sending_an_email()
-//         .respond_with(ResponseTemplate::new(200))
-//         .up_to_n_times(1)
-//         .expect(1)
-//         .mount(&app.email_server)
-//         .await;
-//
-//     when_sending_an_email()
-//         .respond_with(ResponseTemplate::new(500))
-//         .up_to_n_times(1)
-//         .expect(1)
-//         .mount(&app.email_server)
-//         .await;
-//
-//     let response = app.post_publish_newsletter(&newsletter_request_body)
-//         .await;
-//
-//     assert_eq!(response.status().as_u16(), 500);
-//
-//
-//     when_sending_an_email()
-//         .respond_with(ResponseTemplate::new(200))
-//         .expect(1)
-//         .named("Delivery retry")
-//         .mount(&app.email_server)
-//         .await;
-//     let response = app.post_publish_newsletter(&newsletter_request_body).await;
-//
-//     assert_eq!(response.status().as_u16(), 303)
-// }
+#[tokio::test]
+async fn newsletter_issues_are_sent_with_html_and_text_in_the_matching_fields() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.post_test_user_login().await;
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+    let unsubscribe_link = format!(
+        "{}/subscriptions/unsubscribe?token={}",
+        app.application_settings.base_url,
+        UnsubscribeToken::generate(subscriber_id, &app.application_settings.hmac_secret).as_ref()
+    );
+
+    when_sending_an_email()
+        .and(body_partial_json(serde_json::json!({
+            "HtmlBody": format!(
+                "<p>Newsletter body as HTML</p><p><a href=\"{}\">Unsubscribe</a></p>",
+                unsubscribe_link
+            ),
+            "TextBody": format!("Newsletter body as plain text\n\nUnsubscribe: {}", unsubscribe_link),
+        })))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    app.post_publish_newsletter(&newsletter_request_body).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    // Mock verifies on Drop that the email went out with each body in its own field
+}
+
+#[tokio::test]
+async fn transient_delivery_errors_are_retried_later() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.post_test_user_login().await;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .expect(1)
+        .named("Failed delivery")
+        .mount(&app.email_server)
+        .await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .named("Delivery retry")
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Act - Part 1 - The first attempt fails
+    app.dispatch_all_pending_emails().await;
+
+    // Assert - The task is still queued, with its retry pushed into the future
+    let task = sqlx::query!(
+        "SELECT n_attempts, last_error, execute_after > now() AS \"in_the_future!\" FROM issue_delivery_queue"
+    )
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the queued task");
+    assert_eq!(task.n_attempts, 1);
+    assert!(task.last_error.is_some());
+    assert!(task.in_the_future);
+
+    // Act - Part 2 - The retry is due
+    sqlx::query!("UPDATE issue_delivery_queue SET execute_after = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let n_queued = sqlx::query!("SELECT COUNT(*) AS \"n!\" FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_queued, 0);
+    // Mock verifies on Drop that we have retried once
+}
+
+#[tokio::test]
+async fn deliveries_are_dead_lettered_after_too_many_attempts() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.post_test_user_login().await;
+
+    let max_attempts = app.worker_settings.max_delivery_attempts;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(500))
+        .expect(max_attempts as u64)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    app.post_publish_newsletter(&newsletter_request_body).await;
+
+    // Act
+    for _ in 0..max_attempts {
+        sqlx::query!("UPDATE issue_delivery_queue SET execute_after = now()")
+            .execute(&app.db_pool)
+            .await
+            .unwrap();
+        app.dispatch_all_pending_emails().await;
+    }
+
+    // Assert
+    let n_queued = sqlx::query!("SELECT COUNT(*) AS \"n!\" FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_queued, 0);
+    let dead_letter = sqlx::query!("SELECT n_attempts FROM issue_delivery_dead_letters")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the dead letter");
+    assert_eq!(dead_letter.n_attempts, max_attempts);
+}
+
+#[tokio::test]
+async fn merge_tags_are_filled_in_for_each_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.post_test_user_login().await;
+    let subscriber = sqlx::query!("SELECT name, email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Hi {{ name }}",
+        "text_content": "This issue was sent to {{email}}.",
+        "html_content": "<p>Hi {{ name }}</p><a href=\"{{ unsubscribe_url }}\">Leave</a>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    }))
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let requests = app.email_server.received_requests().await.unwrap();
+    let email_request = requests.last().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["Subject"], format!("Hi {}", subscriber.name));
+    let text_body = body["TextBody"].as_str().unwrap();
+    assert!(text_body.contains(&format!("This issue was sent to {}.", subscriber.email)));
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    assert!(html_body.contains(&format!("<p>Hi {}</p>", subscriber.name)));
+    assert!(html_body.contains("<a href=\"http://"));
+    assert!(!html_body.contains("{{"));
+}
+
+#[tokio::test]
+async fn newsletters_with_invalid_merge_tags_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.post_test_user_login().await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let test_cases = vec![
+        ("Hi {{ surname }}", "Body", "{{ surname }} is not a known merge tag."),
+        ("Title", "Hi {{ name", "The plain text content is invalid"),
+    ];
+    for (title, text_content, error_message) in test_cases {
+        // Act
+        let response = app
+            .post_publish_newsletter(&serde_json::json!({
+                "title": title,
+                "text_content": text_content,
+                "html_content": "<p>Body</p>",
+                "idempotency_key": Uuid::new_v4().to_string()
+            }))
+            .await;
+
+        // Assert
+        assert_is_redirect_to(&response, "/admin/newsletters");
+        let html_page = app.get_publish_newsletter_html().await;
+        assert!(html_page.contains(error_message));
+    }
+    app.dispatch_all_pending_emails().await;
+    let n_issues = sqlx::query!("SELECT COUNT(*) AS \"n!\" FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_issues, 0);
+}
+
+#[tokio::test]
+async fn markdown_newsletters_are_sent_as_html_and_plain_text() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.post_test_user_login().await;
+    let markdown_content = "# Hello\n\nRead [the docs](https://example.com/docs).<script>alert(1)</script>";
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "",
+        "html_content": "",
+        "markdown_content": markdown_content,
+        "idempotency_key": Uuid::new_v4().to_string()
+    }))
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let requests = app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&requests.last().unwrap().body).unwrap();
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    assert!(html_body.contains("<h1>Hello</h1>"));
+    assert!(html_body.contains(r#"<a href="https://example.com/docs""#));
+    assert!(!html_body.contains("<script>"));
+    let text_body = body["TextBody"].as_str().unwrap();
+    assert!(text_body.starts_with("Hello\n=====\n\nRead the docs [1]."));
+    assert!(text_body.contains("[1] https://example.com/docs"));
+    let saved = sqlx::query!("SELECT markdown_content FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.markdown_content.as_deref(), Some(markdown_content));
+}
+
+fn api_newsletter_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    })
+}
+
+#[tokio::test]
+async fn the_api_queues_deliveries_and_returns_the_issue_id() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = app.create_api_token(&ApiScope::ALL).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_newsletters(&token, api_newsletter_body()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+    let body: serde_json::Value = response.json().await.unwrap();
+    let issue_id: Uuid = body["newsletter_issue_id"].as_str().unwrap().parse().unwrap();
+    let queued = sqlx::query!(
+        "SELECT COUNT(*) AS \"count!\" FROM issue_delivery_queue WHERE newsletter_issue_id = $1",
+        issue_id
+    )
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.count, 1);
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn the_api_honours_the_idempotency_key_header() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = app.create_api_token(&ApiScope::ALL).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let idempotency_key = Uuid::new_v4().to_string();
+
+    // Act - Retry the same request
+    let response1 = app
+        .post_newsletters_with_idempotency_key(&token, &idempotency_key, api_newsletter_body())
+        .await;
+    let response2 = app
+        .post_newsletters_with_idempotency_key(&token, &idempotency_key, api_newsletter_body())
+        .await;
+
+    // Assert
+    assert_eq!(response1.status().as_u16(), 202);
+    assert_eq!(response2.status().as_u16(), 202);
+    assert_eq!(response1.text().await.unwrap(), response2.text().await.unwrap());
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that the subscriber was only mailed once
+}
+
+#[tokio::test]
+async fn api_requests_without_an_idempotency_key_each_publish_an_issue() {
+    let app = spawn_app().await;
+    let token = app.create_api_token(&ApiScope::ALL).await;
+
+    for _ in 0..2 {
+        let response = app.post_newsletters(&token, api_newsletter_body()).await;
+        assert_eq!(response.status().as_u16(), 202);
+    }
+
+    let issues = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issues.count, 2);
+}
+
+#[tokio::test]
+async fn the_api_rejects_invalid_merge_tags_and_idempotency_keys() {
+    let app = spawn_app().await;
+    let token = app.create_api_token(&ApiScope::ALL).await;
+
+    let response = app
+        .post_newsletters(
+            &token,
+            serde_json::json!({
+                "title": "Hi {{ surname }}",
+                "content": {"text": "Body", "html": "<p>Body</p>"}
+            }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app
+        .post_newsletters_with_idempotency_key(&token, &"x".repeat(60), api_newsletter_body())
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+}
@@ -0,0 +1,190 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use crate::newsletter::create_confirmed_subscriber;
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn create_draft(app: &TestApp) -> Uuid {
+    let response = app
+        .post_create_draft(&serde_json::json!({
+            "title": "Draft title",
+            "text_content": "Draft body as plain text",
+            "html_content": "<p>Draft body as HTML</p>",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+    let location = response.headers().get("Location").unwrap().to_str().unwrap();
+    location
+        .strip_prefix("/admin/newsletters/drafts/")
+        .unwrap()
+        .parse()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_drafts() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/admin/newsletters/drafts", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn saving_a_draft_does_not_send_anything() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.post_test_user_login().await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let newsletter_issue_id = create_draft(&app).await;
+    app.release_due_scheduled_issues().await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let html_page = app.get_draft_html(newsletter_issue_id).await;
+    assert!(html_page.contains("<p><i>The draft has been saved.</i></p>"));
+    let html_page = app.get_drafts_html().await;
+    assert!(html_page.contains("Draft title"));
+    let html_page = app.get_newsletter_issues_html().await;
+    assert!(!html_page.contains("Draft title"));
+}
+
+#[tokio::test]
+async fn drafts_can_be_edited() {
+    // Arrange
+    let app = spawn_app().await;
+    app.post_test_user_login().await;
+    let newsletter_issue_id = create_draft(&app).await;
+
+    // Act
+    let response = app
+        .post_update_draft(
+            newsletter_issue_id,
+            &serde_json::json!({
+                "title": "Edited title",
+                "text_content": "Edited body",
+                "html_content": "<p>Edited body</p>",
+            }),
+        )
+        .await;
+    assert_is_redirect_to(
+        &response,
+        &format!("/admin/newsletters/drafts/{}", newsletter_issue_id),
+    );
+
+    // Assert
+    let html_page = app.get_draft_html(newsletter_issue_id).await;
+    assert!(html_page.contains(r#"value="Edited title""#));
+    assert!(html_page.contains("&lt;p&gt;Edited body&lt;/p&gt;"));
+}
+
+#[tokio::test]
+async fn the_preview_renders_both_bodies() {
+    // Arrange
+    let app = spawn_app().await;
+    app.post_test_user_login().await;
+    let newsletter_issue_id = create_draft(&app).await;
+
+    // Act
+    let html_page = app.get_draft_preview_html(newsletter_issue_id).await;
+
+    // Assert
+    assert!(html_page.contains(r#"srcdoc="&lt;p&gt;Draft body as HTML&lt;/p&gt;""#));
+    assert!(html_page.contains("<pre>Draft body as plain text</pre>"));
+}
+
+#[tokio::test]
+async fn drafts_can_be_deleted() {
+    // Arrange
+    let app = spawn_app().await;
+    app.post_test_user_login().await;
+    let newsletter_issue_id = create_draft(&app).await;
+
+    // Act
+    let response = app.post_delete_draft(newsletter_issue_id).await;
+    assert_is_redirect_to(&response, "/admin/newsletters/drafts");
+
+    // Assert
+    let html_page = app.get_drafts_html().await;
+    assert!(html_page.contains("<p><i>The draft has been deleted.</i></p>"));
+    assert!(!html_page.contains("Draft title"));
+    let response = app.get_draft(newsletter_issue_id).await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn publishing_a_draft_delivers_it_exactly_once() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.post_test_user_login().await;
+    let newsletter_issue_id = create_draft(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Publish the draft
+    let publish_form = serde_json::json!({
+        "idempotency_key": Uuid::new_v4().to_string(),
+    });
+    let response = app.post_publish_draft(newsletter_issue_id, &publish_form).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Act - Part 2 - Submit the form again
+    let response = app.post_publish_draft(newsletter_issue_id, &publish_form).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let html_page = app.get_drafts_html().await;
+    assert!(!html_page.contains("Draft title"));
+    let html_page = app.get_newsletter_issues_html().await;
+    assert!(html_page.contains("Draft title"));
+}
+
+#[tokio::test]
+async fn markdown_drafts_keep_their_source_for_editing() {
+    // Arrange
+    let app = spawn_app().await;
+    app.post_test_user_login().await;
+    let response = app
+        .post_create_draft(&serde_json::json!({
+            "title": "Draft title",
+            "text_content": "",
+            "html_content": "",
+            "markdown_content": "## Work in *progress*",
+        }))
+        .await;
+    let location = response.headers().get("Location").unwrap().to_str().unwrap();
+    let newsletter_issue_id: Uuid = location
+        .strip_prefix("/admin/newsletters/drafts/")
+        .unwrap()
+        .parse()
+        .unwrap();
+
+    // Act
+    let edit_page = app.get_draft_html(newsletter_issue_id).await;
+    let preview_page = app.get_draft_preview_html(newsletter_issue_id).await;
+
+    // Assert
+    assert!(edit_page.contains(">## Work in *progress*</textarea>"));
+    assert!(preview_page.contains("&lt;h2&gt;Work in &lt;em&gt;progress&lt;/em&gt;&lt;/h2&gt;"));
+    assert!(preview_page.contains("<pre>Work in progress\n----------------</pre>"));
+}
@@ -0,0 +1,87 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use argon2::password_hash::SaltString;
+use argon2::{Algorithm, Argon2, Params, PasswordHasher, Version};
+
+async fn stored_password_hash(app: &TestApp) -> String {
+    sqlx::query!(
+        "SELECT password_hash FROM users WHERE user_id = $1",
+        app.test_user.user_id
+    )
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .password_hash
+}
+
+async fn login(app: &TestApp) -> reqwest::Response {
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    }))
+        .await
+}
+
+#[tokio::test]
+async fn logging_in_upgrades_a_hash_made_with_weaker_parameters() {
+    // Arrange
+    let app = spawn_app().await;
+    let salt = SaltString::generate(&mut rand::thread_rng());
+    let weak_hash = Argon2::new(Algorithm::Argon2id, Version::V0x13, Params::new(4096, 1, 1, None).unwrap())
+        .hash_password(app.test_user.password.as_bytes(), &salt)
+        .unwrap()
+        .to_string();
+    sqlx::query!(
+        "UPDATE users SET password_hash = $1 WHERE user_id = $2",
+        weak_hash,
+        app.test_user.user_id
+    )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = login(&app).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let upgraded_hash = stored_password_hash(&app).await;
+    assert_ne!(upgraded_hash, weak_hash);
+    assert!(upgraded_hash.contains("$m=15000,t=2,p=1$"));
+    app.post_logout().await;
+    let response = login(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn an_up_to_date_hash_is_left_alone() {
+    let app = spawn_app().await;
+    let hash_before = stored_password_hash(&app).await;
+
+    let response = login(&app).await;
+
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    assert_eq!(stored_password_hash(&app).await, hash_before);
+}
+
+#[tokio::test]
+async fn a_failed_login_does_not_touch_the_hash() {
+    let app = spawn_app().await;
+    sqlx::query!(
+        "UPDATE users SET password_hash = '$argon2id$v=19$m=4096,t=1,p=1$goV5w/49LP4yuat+AN4pIQ$lY3KAqM/9V7ds+i9QwIAeOF+AAowx5ysp+ksRHtk0os' WHERE user_id = $1",
+        app.test_user.user_id
+    )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let hash_before = stored_password_hash(&app).await;
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": "wrong password"
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+    assert_eq!(stored_password_hash(&app).await, hash_before);
+}
@@ -0,0 +1,224 @@
+use crate::helpers::{assert_is_redirect_to, build_api_client, csrf_token, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+const TEST_USER_EMAIL: &str = "admin@example.com";
+
+async fn give_test_user_an_email(app: &TestApp) {
+    sqlx::query!(
+        "UPDATE users SET email = $1 WHERE user_id = $2",
+        TEST_USER_EMAIL,
+        app.test_user.user_id
+    )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+}
+
+async fn request_reset_token(app: &TestApp) -> String {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let response = app
+        .post_forgot_password(&serde_json::json!({"email": TEST_USER_EMAIL}))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    app.get_password_reset_token().await
+}
+
+#[tokio::test]
+async fn the_login_page_links_to_the_forgot_password_form() {
+    let app = spawn_app().await;
+
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains(r#"href="/login/forgot""#));
+
+    let html_page = app.get_forgot_password_html().await;
+    assert!(html_page.contains(r#"name="email""#));
+}
+
+#[tokio::test]
+async fn requesting_a_reset_for_an_unknown_email_sends_nothing_but_looks_the_same() {
+    let app = spawn_app().await;
+    give_test_user_an_email(&app).await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_forgot_password(&serde_json::json!({"email": "nobody@example.com"}))
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("If an account exists for that address"));
+}
+
+#[tokio::test]
+async fn only_the_hash_of_the_reset_token_is_stored() {
+    let app = spawn_app().await;
+    give_test_user_an_email(&app).await;
+
+    let token = request_reset_token(&app).await;
+
+    let stored = sqlx::query!("SELECT token_hash, user_id FROM password_reset_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(stored.user_id, app.test_user.user_id);
+    assert_ne!(stored.token_hash, token);
+}
+
+#[tokio::test]
+async fn a_reset_link_lets_the_user_log_in_with_a_new_password() {
+    let app = spawn_app().await;
+    give_test_user_an_email(&app).await;
+    let new_password = Uuid::new_v4().to_string();
+    let token = request_reset_token(&app).await;
+
+    let response = app
+        .post_reset_password(&serde_json::json!({
+            "token": &token,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("Your password has been reset."));
+
+    // The old password no longer works...
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    // ...the new one does.
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn a_reset_link_can_only_be_used_once() {
+    let app = spawn_app().await;
+    give_test_user_an_email(&app).await;
+    let token = request_reset_token(&app).await;
+    let new_password = Uuid::new_v4().to_string();
+    let body = serde_json::json!({
+        "token": &token,
+        "new_password": &new_password,
+        "new_password_check": &new_password,
+    });
+    let response = app.post_reset_password(&body).await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app.post_reset_password(&body).await;
+
+    assert_is_redirect_to(&response, "/login/forgot");
+    let html_page = app.get_forgot_password_html().await;
+    assert!(html_page.contains("This password reset link is invalid or has expired."));
+}
+
+#[tokio::test]
+async fn an_expired_reset_link_is_rejected() {
+    let app = spawn_app().await;
+    give_test_user_an_email(&app).await;
+    let token = request_reset_token(&app).await;
+    sqlx::query!("UPDATE password_reset_tokens SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let new_password = Uuid::new_v4().to_string();
+
+    let response = app
+        .post_reset_password(&serde_json::json!({
+            "token": &token,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/login/forgot");
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn mismatched_passwords_do_not_use_up_the_reset_link() {
+    let app = spawn_app().await;
+    give_test_user_an_email(&app).await;
+    let token = request_reset_token(&app).await;
+
+    let response = app
+        .post_reset_password(&serde_json::json!({
+            "token": &token,
+            "new_password": Uuid::new_v4().to_string(),
+            "new_password_check": Uuid::new_v4().to_string(),
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, &format!("/login/reset?token={}", token));
+    let n_tokens = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM password_reset_tokens"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_tokens, 1);
+}
+
+#[tokio::test]
+async fn resetting_the_password_logs_out_every_existing_session() {
+    let app = spawn_app().await;
+    give_test_user_an_email(&app).await;
+    let other_browser = build_api_client();
+    for client in [&app.api_client, &other_browser] {
+        let response = client
+            .post(format!("{}/login", &app.address))
+            .form(&serde_json::json!({
+                "username": &app.test_user.username,
+                "password": &app.test_user.password,
+                "csrf_token": csrf_token(client, &app.address).await,
+            }))
+            .send()
+            .await
+            .unwrap();
+        assert_is_redirect_to(&response, "/admin/dashboard");
+    }
+    let token = request_reset_token(&app).await;
+    let new_password = Uuid::new_v4().to_string();
+
+    app.post_reset_password(&serde_json::json!({
+        "token": &token,
+        "new_password": &new_password,
+        "new_password_check": &new_password,
+    }))
+        .await;
+
+    for client in [&app.api_client, &other_browser] {
+        let response = client
+            .get(format!("{}/admin/dashboard", &app.address))
+            .send()
+            .await
+            .unwrap();
+        assert_is_redirect_to(&response, "/login");
+    }
+}
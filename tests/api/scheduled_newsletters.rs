@@ -0,0 +1,206 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use crate::newsletter::create_confirmed_subscriber;
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+fn in_one_hour() -> String {
+    (Utc::now() + Duration::hours(1))
+        .format("%Y-%m-%dT%H:%M")
+        .to_string()
+}
+
+async fn schedule_newsletter(app: &TestApp) -> Uuid {
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "send_at": in_one_hour(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the scheduled issue")
+        .newsletter_issue_id
+}
+
+async fn make_scheduled_issues_due(app: &TestApp) {
+    sqlx::query!("UPDATE newsletter_issues SET send_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn scheduled_newsletters_are_not_delivered_before_their_send_time() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.post_test_user_login().await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    schedule_newsletter(&app).await;
+    let html_page = app.get_publish_newsletter_html().await;
+    app.release_due_scheduled_issues().await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert!(html_page.contains("The newsletter issue has been scheduled for"));
+    let html_page = app.get_newsletter_issues_html().await;
+    assert!(html_page.contains("Newsletter title"));
+    assert!(html_page.contains("scheduled"));
+}
+
+#[tokio::test]
+async fn scheduled_newsletters_are_delivered_once_due() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.post_test_user_login().await;
+    schedule_newsletter(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    make_scheduled_issues_due(&app).await;
+    app.release_due_scheduled_issues().await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let issue = sqlx::query!("SELECT status, published_at FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.status, "published");
+    assert!(issue.published_at.is_some());
+}
+
+#[tokio::test]
+async fn a_due_issue_is_fanned_out_only_once_by_concurrent_schedulers() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.post_test_user_login().await;
+    schedule_newsletter(&app).await;
+    make_scheduled_issues_due(&app).await;
+
+    // Act
+    tokio::join!(
+        app.release_due_scheduled_issues(),
+        app.release_due_scheduled_issues()
+    );
+
+    // Assert
+    let n_tasks = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_tasks, 1);
+}
+
+#[tokio::test]
+async fn cancelled_newsletters_are_never_delivered() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.post_test_user_login().await;
+    let newsletter_issue_id = schedule_newsletter(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Cancel
+    let response = app.post_cancel_newsletter_issue(newsletter_issue_id).await;
+    assert_is_redirect_to(&response, "/admin/newsletters/issues");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_newsletter_issues_html().await;
+    assert!(html_page.contains("<p><i>The newsletter issue has been cancelled.</i></p>"));
+
+    // Act - Part 3 - The original send time passes
+    make_scheduled_issues_due(&app).await;
+    app.release_due_scheduled_issues().await;
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn scheduled_newsletters_can_be_rescheduled() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.post_test_user_login().await;
+    let newsletter_issue_id = schedule_newsletter(&app).await;
+    let new_send_at = Utc::now() + Duration::days(1);
+
+    // Act
+    let response = app
+        .post_reschedule_newsletter_issue(
+            newsletter_issue_id,
+            &serde_json::json!({
+                "send_at": new_send_at.format("%Y-%m-%dT%H:%M").to_string(),
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters/issues");
+
+    // Assert
+    let html_page = app.get_newsletter_issues_html().await;
+    assert!(html_page.contains(&format!(
+        "<p><i>The newsletter issue has been rescheduled for {}.</i></p>",
+        new_send_at.format("%Y-%m-%d %H:%M UTC")
+    )));
+    let send_at = sqlx::query!("SELECT send_at FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .send_at
+        .unwrap();
+    assert_eq!(
+        send_at.format("%Y-%m-%dT%H:%M").to_string(),
+        new_send_at.format("%Y-%m-%dT%H:%M").to_string()
+    );
+}
+
+#[tokio::test]
+async fn published_newsletters_cannot_be_cancelled() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.post_test_user_login().await;
+    let newsletter_issue_id = schedule_newsletter(&app).await;
+    make_scheduled_issues_due(&app).await;
+    app.release_due_scheduled_issues().await;
+
+    // Act
+    app.post_cancel_newsletter_issue(newsletter_issue_id).await;
+
+    // Assert
+    let html_page = app.get_newsletter_issues_html().await;
+    assert!(html_page.contains("The newsletter issue is no longer scheduled"));
+    let status = sqlx::query!("SELECT status FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .status;
+    assert_eq!(status, "published");
+}
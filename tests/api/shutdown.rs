@@ -0,0 +1,119 @@
+use crate::helpers::{build_api_client, spawn_app};
+use crate::newsletter::create_confirmed_subscriber;
+use std::time::Duration;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod_my::authentication::ApiScope;
+use zero2prod_my::issue_delivery_worker::run_worker_until_stopped;
+use zero2prod_my::shutdown::shutdown_channel;
+
+#[tokio::test]
+async fn the_server_finishes_requests_in_flight_before_stopping() {
+    // Arrange
+    let app = spawn_app().await;
+    // Sending the confirmation email keeps the request busy for a while.
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(1)))
+        .mount(&app.email_server)
+        .await;
+    let in_flight = tokio::spawn({
+        let client = build_api_client();
+        let address = app.address.clone();
+        async move {
+            client
+                .post(format!("{}/subscriptions", address))
+                .header("Content-Type", "application/x-www-form-urlencoded")
+                .body("name=le%20guin&email=ursula_le_guin%40gmail.com")
+                .send()
+                .await
+        }
+    });
+    // The request is in flight once its confirmation email is being sent.
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while app.email_server.received_requests().await.unwrap().is_empty() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+        .await
+        .expect("The request never reached the email server.");
+
+    // Act
+    app.shutdown.trigger();
+
+    // Assert
+    let response = in_flight.await.unwrap().expect("The request in flight was cut off.");
+    assert_eq!(response.status().as_u16(), 200);
+    tokio::time::timeout(Duration::from_secs(5), app.server)
+        .await
+        .expect("The server did not stop.")
+        .unwrap()
+        .unwrap();
+    let refused = build_api_client()
+        .get(format!("{}/health_check", app.address))
+        .send()
+        .await;
+    assert!(refused.is_err());
+}
+
+#[tokio::test]
+async fn an_idle_worker_stops_as_soon_as_it_is_asked_to() {
+    let app = spawn_app().await;
+    let (trigger, shutdown) = shutdown_channel();
+    let worker = tokio::spawn(run_worker_until_stopped(app.configuration.clone(), shutdown));
+    // Long enough for the worker to find the queue empty and go to sleep.
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    trigger.trigger();
+
+    tokio::time::timeout(Duration::from_secs(1), worker)
+        .await
+        .expect("The worker kept sleeping.")
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn the_worker_finishes_its_current_delivery_before_stopping() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = app.create_api_token(&ApiScope::ALL).await;
+    let response = app
+        .post_newsletters(
+            &token,
+            serde_json::json!({
+                "title": "Newsletter title",
+                "content": {
+                    "text": "Newsletter body as plain text",
+                    "html": "<p>Newsletter body as HTML</p>",
+                }
+            }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(1)))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let (trigger, shutdown) = shutdown_channel();
+    let worker = tokio::spawn(run_worker_until_stopped(app.configuration.clone(), shutdown));
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    // Act
+    trigger.trigger();
+
+    // Assert
+    tokio::time::timeout(Duration::from_secs(5), worker)
+        .await
+        .expect("The worker did not stop.")
+        .unwrap()
+        .unwrap();
+    let pending = sqlx::query!("SELECT count(*) AS \"count!\" FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(pending.count, 0);
+}
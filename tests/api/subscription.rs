@@ -116,8 +116,8 @@ async fn subscriber_sends_a_confirmation_email_for_with_a_link() {
     let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
     let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
 
-    let html_links = get_links(&body["HtmlBody"].as_str().unwrap());
-    let text_links = get_links(&body["TextBody"].as_str().unwrap());
+    let html_links = get_links(body["HtmlBody"].as_str().unwrap());
+    let text_links = get_links(body["TextBody"].as_str().unwrap());
     tracing::info!("html_links:{}", html_links);
     tracing::info!("text_links:{}", text_links);
     assert_eq!(html_links, text_links);
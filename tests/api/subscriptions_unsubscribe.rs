@@ -0,0 +1,185 @@
+use crate::helpers::{spawn_app, TestApp};
+use crate::newsletter::create_confirmed_subscriber;
+use reqwest::Url;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn publish_and_deliver_an_issue(app: &TestApp) {
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    }))
+        .await;
+    app.dispatch_all_pending_emails().await;
+}
+
+/// Delivers an issue to a freshly confirmed subscriber and returns the body of the issue email.
+async fn receive_an_issue(app: &TestApp) -> serde_json::Value {
+    create_confirmed_subscriber(app).await;
+    app.post_test_user_login().await;
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    publish_and_deliver_an_issue(app).await;
+    let requests = app.email_server.received_requests().await.unwrap();
+    serde_json::from_slice(&requests[requests.len() - 1].body).unwrap()
+}
+
+fn get_unsubscribe_link(app: &TestApp, email_body: &serde_json::Value) -> Url {
+    let header = email_body["Headers"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|h| h["Name"] == "List-Unsubscribe")
+        .expect("The issue has no List-Unsubscribe header");
+    let link = header["Value"]
+        .as_str()
+        .unwrap()
+        .trim_start_matches('<')
+        .trim_end_matches('>');
+    let mut link = Url::parse(link).unwrap();
+    link.set_port(Some(app.port)).unwrap();
+    link
+}
+
+async fn subscriber_status(app: &TestApp) -> String {
+    sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription")
+        .status
+}
+
+#[tokio::test]
+async fn issues_carry_one_click_unsubscribe_headers_and_a_link() {
+    let app = spawn_app().await;
+
+    let email_body = receive_an_issue(&app).await;
+
+    let headers = email_body["Headers"].as_array().unwrap();
+    assert!(headers.iter().any(|h| h["Name"] == "List-Unsubscribe-Post"
+        && h["Value"] == "List-Unsubscribe=One-Click"));
+    let link = get_unsubscribe_link(&app, &email_body);
+    assert_eq!(link.path(), "/subscriptions/unsubscribe");
+    let token = link.query_pairs().find(|(k, _)| k == "token").unwrap().1;
+    assert!(email_body["HtmlBody"].as_str().unwrap().contains(token.as_ref()));
+    assert!(email_body["TextBody"].as_str().unwrap().contains(token.as_ref()));
+}
+
+#[tokio::test]
+async fn following_the_unsubscribe_link_does_not_unsubscribe_the_subscriber() {
+    let app = spawn_app().await;
+    let email_body = receive_an_issue(&app).await;
+    let link = get_unsubscribe_link(&app, &email_body);
+
+    let response = reqwest::get(link).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(r#"<form action="/subscriptions/unsubscribe?token="#));
+    assert!(html_page.contains(r#"method="post""#));
+    assert_eq!(subscriber_status(&app).await, "confirmed");
+}
+
+#[tokio::test]
+async fn confirming_from_the_unsubscribe_page_unsubscribes_the_subscriber() {
+    let app = spawn_app().await;
+    let email_body = receive_an_issue(&app).await;
+    let link = get_unsubscribe_link(&app, &email_body);
+
+    let response = reqwest::Client::new().post(link).send().await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "unsubscribed");
+}
+
+#[tokio::test]
+async fn one_click_unsubscribe_posts_unsubscribe_the_subscriber() {
+    let app = spawn_app().await;
+    let email_body = receive_an_issue(&app).await;
+    let link = get_unsubscribe_link(&app, &email_body);
+
+    let response = reqwest::Client::new()
+        .post(link)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("List-Unsubscribe=One-Click")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "unsubscribed");
+}
+
+#[tokio::test]
+async fn unsubscribing_keeps_the_status_of_a_bounced_address() {
+    let app = spawn_app().await;
+    let email_body = receive_an_issue(&app).await;
+    let link = get_unsubscribe_link(&app, &email_body);
+    sqlx::query!("UPDATE subscriptions SET status = 'bounced'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = reqwest::Client::new().post(link).send().await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "bounced");
+}
+
+#[tokio::test]
+async fn unsubscribe_without_token_is_rejected_with_a_400() {
+    let app = spawn_app().await;
+
+    let response = reqwest::get(format!("{}/subscriptions/unsubscribe", app.address))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn forged_unsubscribe_tokens_are_rejected_with_a_401() {
+    let app = spawn_app().await;
+    let email_body = receive_an_issue(&app).await;
+    let mut link = get_unsubscribe_link(&app, &email_body);
+    let token = link.query_pairs().find(|(k, _)| k == "token").unwrap().1.into_owned();
+    let (_, signature) = token.split_once('.').unwrap();
+    link.set_query(Some(&format!("token={}.{}", uuid::Uuid::new_v4(), signature)));
+
+    let response = reqwest::get(link).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(subscriber_status(&app).await, "confirmed");
+}
+
+#[tokio::test]
+async fn unsubscribed_subscribers_do_not_receive_newsletters() {
+    // Arrange
+    let app = spawn_app().await;
+    let email_body = receive_an_issue(&app).await;
+    let link = get_unsubscribe_link(&app, &email_body);
+    reqwest::Client::new()
+        .post(link)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    publish_and_deliver_an_issue(&app).await;
+
+    // Mock verifies on Drop that we haven't sent the newsletter email
+}
@@ -0,0 +1,208 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use zero2prod_my::authentication::{enable_two_factor, generate_totp_secret, totp_code};
+
+fn current_code(secret: &str) -> String {
+    totp_code(secret, chrono::Utc::now().timestamp() as u64).unwrap()
+}
+
+fn extract_code_element(html: &str, id: &str) -> String {
+    let start = html.find(&format!(r#"<code id="{}">"#, id)).unwrap() + id.len() + 12;
+    let end = start + html[start..].find("</code>").unwrap();
+    html[start..end].to_owned()
+}
+
+/// Enrolls the test user directly and returns the TOTP secret and the recovery codes.
+async fn enroll_test_user(app: &TestApp) -> (String, Vec<String>) {
+    let secret = generate_totp_secret();
+    let mut transaction = app.db_pool.begin().await.unwrap();
+    let recovery_codes = enable_two_factor(app.test_user.user_id, &secret, &mut transaction)
+        .await
+        .unwrap();
+    transaction.commit().await.unwrap();
+    (secret, recovery_codes)
+}
+
+async fn post_password_step(app: &TestApp) -> reqwest::Response {
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    }))
+        .await
+}
+
+#[tokio::test]
+async fn an_admin_can_enroll_with_a_code_from_their_app() {
+    // Arrange
+    let app = spawn_app().await;
+    app.post_test_user_login().await;
+    let html_page = app.get_two_factor_settings_html().await;
+    assert!(html_page.contains("Two-factor authentication is <b>disabled</b>."));
+    let secret = extract_code_element(&html_page, "totp-secret");
+    let uri = extract_code_element(&html_page, "otpauth-uri");
+    assert!(uri.starts_with("otpauth://totp/zero2prod:"));
+    assert!(uri.contains(&format!("secret={}", secret)));
+
+    // Reloading the page keeps the same secret
+    let html_page = app.get_two_factor_settings_html().await;
+    assert_eq!(extract_code_element(&html_page, "totp-secret"), secret);
+
+    // Act
+    let response = app
+        .post_enable_two_factor(&serde_json::json!({"code": current_code(&secret)}))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert_eq!(html_page.matches(r#"class="recovery-code""#).count(), 10);
+    let stored = sqlx::query!(
+        "SELECT totp_secret FROM users WHERE user_id = $1",
+        app.test_user.user_id
+    )
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(stored.totp_secret, Some(secret));
+    let html_page = app.get_two_factor_settings_html().await;
+    assert!(html_page.contains("Two-factor authentication is <b>enabled</b>."));
+    assert!(html_page.contains("You have 10 unused recovery codes left."));
+}
+
+#[tokio::test]
+async fn enrollment_is_refused_with_a_wrong_code() {
+    let app = spawn_app().await;
+    app.post_test_user_login().await;
+    app.get_two_factor_settings_html().await;
+
+    let response = app
+        .post_enable_two_factor(&serde_json::json!({"code": "000000x"}))
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/two-factor");
+    let html_page = app.get_two_factor_settings_html().await;
+    assert!(html_page.contains("The code does not match"));
+    assert!(html_page.contains("Two-factor authentication is <b>disabled</b>."));
+}
+
+#[tokio::test]
+async fn recovery_codes_are_stored_hashed() {
+    let app = spawn_app().await;
+    let (_, recovery_codes) = enroll_test_user(&app).await;
+
+    let stored: Vec<String> = sqlx::query!("SELECT code_hash FROM totp_recovery_codes")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.code_hash)
+        .collect();
+
+    assert_eq!(stored.len(), recovery_codes.len());
+    for code in &recovery_codes {
+        assert!(!stored.contains(code));
+    }
+}
+
+#[tokio::test]
+async fn enrolled_users_need_a_second_step_to_log_in() {
+    // Arrange
+    let app = spawn_app().await;
+    let (secret, _) = enroll_test_user(&app).await;
+
+    // Act - Part 1 - The password alone is not enough
+    let response = post_password_step(&app).await;
+    assert_is_redirect_to(&response, "/login/two-factor");
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+    assert_eq!(app.get_login_two_factor().await.status().as_u16(), 200);
+
+    // Act - Part 2 - Provide the code
+    let response = app.post_login_two_factor(&current_code(&secret)).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // Assert
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {}", app.test_user.username)));
+}
+
+#[tokio::test]
+async fn a_wrong_code_does_not_log_you_in() {
+    let app = spawn_app().await;
+    enroll_test_user(&app).await;
+    post_password_step(&app).await;
+
+    let response = app.post_login_two_factor("12345").await;
+
+    assert_is_redirect_to(&response, "/login/two-factor");
+    let html_page = app.get_login_two_factor().await.text().await.unwrap();
+    assert!(html_page.contains("<p><i>The authentication code is not valid.</i></p>"));
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_second_step_requires_the_password_step_first() {
+    let app = spawn_app().await;
+    let (secret, _) = enroll_test_user(&app).await;
+
+    assert_is_redirect_to(&app.get_login_two_factor().await, "/login");
+    let response = app.post_login_two_factor(&current_code(&secret)).await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn a_totp_code_cannot_be_replayed() {
+    let app = spawn_app().await;
+    let (secret, _) = enroll_test_user(&app).await;
+    let code = current_code(&secret);
+    post_password_step(&app).await;
+    let response = app.post_login_two_factor(&code).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    app.post_logout().await;
+
+    post_password_step(&app).await;
+    let response = app.post_login_two_factor(&code).await;
+
+    assert_is_redirect_to(&response, "/login/two-factor");
+}
+
+#[tokio::test]
+async fn a_recovery_code_can_be_used_only_once() {
+    let app = spawn_app().await;
+    let (_, recovery_codes) = enroll_test_user(&app).await;
+    post_password_step(&app).await;
+    let response = app.post_login_two_factor(&recovery_codes[0].to_lowercase()).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html_page = app.get_two_factor_settings_html().await;
+    assert!(html_page.contains("You have 9 unused recovery codes left."));
+    app.post_logout().await;
+
+    post_password_step(&app).await;
+    let response = app.post_login_two_factor(&recovery_codes[0]).await;
+
+    assert_is_redirect_to(&response, "/login/two-factor");
+}
+
+#[tokio::test]
+async fn two_factor_can_be_disabled_with_the_current_password() {
+    let app = spawn_app().await;
+    let (secret, _) = enroll_test_user(&app).await;
+    post_password_step(&app).await;
+    app.post_login_two_factor(&current_code(&secret)).await;
+
+    let response = app
+        .post_disable_two_factor(&serde_json::json!({"current_password": "wrong"}))
+        .await;
+    assert_is_redirect_to(&response, "/admin/two-factor");
+    assert!(app.get_two_factor_settings_html().await.contains("The current password is incorrect."));
+
+    let response = app
+        .post_disable_two_factor(&serde_json::json!({"current_password": &app.test_user.password}))
+        .await;
+    assert_is_redirect_to(&response, "/admin/two-factor");
+    app.post_logout().await;
+
+    // The password is enough again
+    let response = post_password_step(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}